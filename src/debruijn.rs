@@ -1,35 +1,299 @@
+use std::collections::HashMap;
+use std::fmt::Debug;
+use std::hash::{BuildHasherDefault, Hash, Hasher};
+use std::ops::Deref;
+use std::sync::{Arc, LazyLock, Mutex, Weak};
+
+use crate::lex::Span;
 use crate::parse::Expr;
 
-#[derive(Clone, Debug, PartialEq)]
+#[derive(Clone, Debug, PartialEq, Eq, Hash)]
 pub enum DBExpr {
-	Fun(Box<DBExpr>),
-	App(Box<DBExpr>, Box<DBExpr>),
+	Fun(Term),
+	App(Term, Term),
+	Var(usize),
+}
+
+/// A shared, hash-consed expression. Structurally equal terms are always
+/// represented by the same node, so cloning and comparing terms takes
+/// constant time and identical subterms are only stored once.
+#[derive(Clone)]
+pub struct Term(Arc<Node>);
+
+struct Node {
+	expr: DBExpr,
+	hash: u64,
+	free_vars: usize,
+	size: usize,
+	depth: usize,
+	has_redex: bool,
+	/// Whether the node is still in the interner, and still owns its children.
+	interned: bool,
+}
+
+/// Identifies a node by its constructor and the addresses of its children,
+/// which are unique since the children are themselves hash-consed.
+#[derive(Clone, Copy, PartialEq, Eq, Hash)]
+enum Key {
+	Fun(usize),
+	App(usize, usize),
 	Var(usize),
 }
 
+const SHARDS: usize = 16;
+
+type Shard = Mutex<FastMap<Key, Weak<Node>>>;
+
+/// All live terms. The table is split into shards so that threads building
+/// terms at the same time rarely wait for each other.
+static INTERNER: LazyLock<Vec<Shard>> = LazyLock::new(|| {
+	(0..SHARDS)
+		.map(|_| Mutex::new(FastMap::default()))
+		.collect()
+});
+
+impl Term {
+	/// Returns the unique term with the given structure.
+	pub fn new(expr: DBExpr) -> Term {
+		let key = Key::of(&expr);
+		let (hash, free_vars, size, depth, has_redex) = match &expr {
+			DBExpr::Var(i) => (combine(0, &[*i as u64]), i + 1, 1, 1, false),
+			DBExpr::Fun(body) => (
+				combine(1, &[body.0.hash]),
+				body.0.free_vars.saturating_sub(1),
+				body.0.size.saturating_add(1),
+				body.0.depth + 1,
+				body.0.has_redex,
+			),
+			DBExpr::App(f, a) => (
+				combine(2, &[f.0.hash, a.0.hash]),
+				f.0.free_vars.max(a.0.free_vars),
+				f.0.size.saturating_add(a.0.size).saturating_add(1),
+				f.0.depth.max(a.0.depth) + 1,
+				matches!(**f, DBExpr::Fun(_)) || f.0.has_redex || a.0.has_redex,
+			),
+		};
+		let mut shard = shard(hash).lock().unwrap();
+		if let Some(node) = shard.get(&key).and_then(Weak::upgrade) {
+			drop(shard);
+			// Dropping `expr` may free other terms, which needs the lock
+			drop(expr);
+			return Term(node);
+		}
+		let node = Arc::new(Node {
+			expr,
+			hash,
+			free_vars,
+			size,
+			depth,
+			has_redex,
+			interned: true,
+		});
+		shard.insert(key, Arc::downgrade(&node));
+		Term(node)
+	}
+
+	/// A hash of the structure of the term, which does not depend on where
+	/// the term is stored.
+	pub fn structural_hash(&self) -> u64 {
+		self.0.hash
+	}
+
+	/// One more than the largest de Bruijn index that is free in the term, or
+	/// 0 if the term is closed.
+	pub fn free_vars(&self) -> usize {
+		self.0.free_vars
+	}
+
+	/// The number of nodes (variables, functions and applications) in the
+	/// term, counting shared subterms once for each occurrence.
+	pub fn size(&self) -> usize {
+		self.0.size
+	}
+
+	/// The nesting depth of the term.
+	pub fn depth(&self) -> usize {
+		self.0.depth
+	}
+
+	/// Whether the term contains a beta redex anywhere, i.e., whether it is
+	/// not in beta normal form.
+	pub fn has_redex(&self) -> bool {
+		self.0.has_redex
+	}
+
+	/// Whether the term is used in more than one place, for example as a
+	/// subterm of several terms.
+	pub fn is_shared(&self) -> bool {
+		Arc::strong_count(&self.0) > 1
+	}
+
+	/// An identifier for the term, which stays the same as long as the term
+	/// is alive.
+	pub fn id(&self) -> usize {
+		Arc::as_ptr(&self.0) as usize
+	}
+}
+
+impl Key {
+	fn of(expr: &DBExpr) -> Key {
+		match expr {
+			DBExpr::Fun(body) => Key::Fun(body.id()),
+			DBExpr::App(f, a) => Key::App(f.id(), a.id()),
+			DBExpr::Var(i) => Key::Var(*i),
+		}
+	}
+}
+
+fn shard(hash: u64) -> &'static Shard {
+	&INTERNER[(hash % SHARDS as u64) as usize]
+}
+
+impl Node {
+	/// Removes the node, which was shared at address `ptr`, from the interner
+	/// and takes its children.
+	fn unlink(&mut self, ptr: *const Node, children: &mut Vec<Term>) {
+		if !self.interned {
+			return;
+		}
+		self.interned = false;
+		let key = Key::of(&self.expr);
+		let mut shard = shard(self.hash).lock().unwrap();
+		// The entry may already have been replaced by a new node with the same
+		// structure
+		if shard
+			.get(&key)
+			.is_some_and(|w| std::ptr::eq(w.as_ptr(), ptr))
+		{
+			shard.remove(&key);
+		}
+		drop(shard);
+		match std::mem::replace(&mut self.expr, DBExpr::Var(0)) {
+			DBExpr::Fun(body) => children.push(body),
+			DBExpr::App(f, a) => children.extend([f, a]),
+			DBExpr::Var(_) => {}
+		}
+	}
+}
+
+impl Drop for Node {
+	fn drop(&mut self) {
+		// Free the nodes that are no longer used with an explicit stack, since
+		// dropping them recursively could overflow the stack for deep terms
+		let mut stack = Vec::new();
+		self.unlink(self, &mut stack);
+		while let Some(Term(node)) = stack.pop() {
+			let ptr = Arc::as_ptr(&node);
+			if let Some(mut node) = Arc::into_inner(node) {
+				node.unlink(ptr, &mut stack);
+			}
+		}
+	}
+}
+
+/// A fast, non-cryptographic hash combinator.
+pub(crate) fn combine(tag: u8, children: &[u64]) -> u64 {
+	let mut h = FastHasher(tag as u64 + 1);
+	for c in children {
+		h.write_u64(*c);
+	}
+	h.0
+}
+
+/// A hash map using `FastHasher`, for keys that are not chosen by the user.
+pub(crate) type FastMap<K, V> = HashMap<K, V, BuildHasherDefault<FastHasher>>;
+
+/// A fast, non-cryptographic hasher (the one used by rustc). Much faster than
+/// the default hasher for small keys like addresses.
+#[derive(Default)]
+pub(crate) struct FastHasher(u64);
+
+impl Hasher for FastHasher {
+	fn finish(&self) -> u64 {
+		// The low bits, which choose the bucket in a hash map, only depend on
+		// the low bits of the input, which are always 0 for addresses
+		self.0.rotate_left(26)
+	}
+
+	fn write(&mut self, bytes: &[u8]) {
+		for b in bytes {
+			self.write_u64(*b as u64);
+		}
+	}
+
+	fn write_u64(&mut self, n: u64) {
+		self.0 = (self.0.rotate_left(5) ^ n).wrapping_mul(0x517c_c1b7_2722_0a95);
+	}
+
+	fn write_usize(&mut self, n: usize) {
+		self.write_u64(n as u64);
+	}
+}
+
+impl Deref for Term {
+	type Target = DBExpr;
+
+	fn deref(&self) -> &DBExpr {
+		&self.0.expr
+	}
+}
+
+impl AsRef<DBExpr> for Term {
+	fn as_ref(&self) -> &DBExpr {
+		&self.0.expr
+	}
+}
+
+impl PartialEq for Term {
+	fn eq(&self, other: &Term) -> bool {
+		Arc::ptr_eq(&self.0, &other.0)
+	}
+}
+
+impl Eq for Term {}
+
+impl Hash for Term {
+	fn hash<H: std::hash::Hasher>(&self, state: &mut H) {
+		self.id().hash(state);
+	}
+}
+
+impl Debug for Term {
+	fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+		self.0.expr.fmt(f)
+	}
+}
+
 impl Expr {
 	/// Converts a term in the "named" form to a term using de Bruijn indices.
-	pub fn to_debruijn(&self) -> Box<DBExpr> {
-		let mut arg_stack: Vec<&str> = Vec::new();
+	pub fn to_debruijn(&self) -> Term {
+		self.to_debruijn_with_free_vars(&[])
+	}
+
+	/// Like `to_debruijn`, but allows the variables in `free_vars` to appear
+	/// free. They are numbered as if they were bound by lambdas around the
+	/// expression, with the last one innermost.
+	pub fn to_debruijn_with_free_vars(&self, free_vars: &[&str]) -> Term {
+		let mut arg_stack: Vec<&str> = free_vars.to_vec();
 		let mut e_stack = vec![(false, self)];
-		let mut result_stack: Vec<Box<DBExpr>> = Vec::new();
+		let mut result_stack: Vec<Term> = Vec::new();
 		while let Some((visited, e)) = e_stack.pop() {
 			if !visited {
 				e_stack.push((true, e));
 			}
 			match (visited, e) {
-				(false, Expr::Fun(x, body)) => {
+				(false, Expr::Fun(x, body, _)) => {
 					e_stack.push((false, body));
 					arg_stack.push(x);
 				}
-				(false, Expr::App(e1, e2)) => {
+				(false, Expr::App(e1, e2, _)) => {
 					e_stack.push((false, e2));
 					e_stack.push((false, e1));
 				}
-				(false, Expr::Var(_)) => {}
-				(true, Expr::Fun(x, _)) => {
+				(false, Expr::Var(_, _)) => {}
+				(true, Expr::Fun(x, _, _)) => {
 					match result_stack.pop() {
-						Some(e) => result_stack.push(Box::new(DBExpr::Fun(e))),
+						Some(e) => result_stack.push(Term::new(DBExpr::Fun(e))),
 						None => panic!("Missing result for function abstraction"),
 					};
 					match arg_stack.pop() {
@@ -37,16 +301,16 @@ impl Expr {
 						_ => panic!("Unexpected argument popped from the stack"),
 					}
 				}
-				(true, Expr::App(_, _)) => match (result_stack.pop(), result_stack.pop()) {
-					(Some(e2), Some(e1)) => result_stack.push(Box::new(DBExpr::App(e1, e2))),
+				(true, Expr::App(_, _, _)) => match (result_stack.pop(), result_stack.pop()) {
+					(Some(e2), Some(e1)) => result_stack.push(Term::new(DBExpr::App(e1, e2))),
 					_ => panic!("Missing result for function application"),
 				},
-				(true, Expr::Var(x)) => {
+				(true, Expr::Var(x, _)) => {
 					let i = match arg_stack.iter().rev().position(|y| y == x) {
 						Some(i) => i,
 						None => panic!("Free variable {x} in expression"),
 					};
-					result_stack.push(Box::new(DBExpr::Var(i)));
+					result_stack.push(Term::new(DBExpr::Var(i)));
 				}
 			}
 		}
@@ -73,45 +337,44 @@ impl DBExpr {
 					let arg_str = choose_ident(arg_num);
 					arg_stack.push((arg_num, arg_str));
 					e_stack.push((false, body));
-				},
+				}
 				(false, DBExpr::App(e1, e2)) => {
 					e_stack.push((false, e2));
 					e_stack.push((false, e1));
-				},
-				(false, DBExpr::Var(_)) => {},
+				}
+				(false, DBExpr::Var(_)) => {}
 				(true, DBExpr::Fun(_)) => {
 					let arg = match arg_stack.pop() {
 						Some((_, name)) => name,
 						None => panic!("Missing argument"),
 					};
 					match result_stack.pop() {
-						Some(e) => result_stack.push(Box::new(Expr::Fun(arg, e))),
-						None => panic!("Missing result for function abstraction")
+						Some(e) => result_stack.push(Box::new(Expr::Fun(arg, e, Span::default()))),
+						None => panic!("Missing result for function abstraction"),
 					}
-				},
-				(true, DBExpr::App(_, _)) => {
-					match (result_stack.pop(), result_stack.pop()) {
-						(Some(e2), Some(e1)) => result_stack.push(Box::new(Expr::App(e1, e2))),
-						_ => panic!("Missing result for function application"),
+				}
+				(true, DBExpr::App(_, _)) => match (result_stack.pop(), result_stack.pop()) {
+					(Some(e2), Some(e1)) => {
+						result_stack.push(Box::new(Expr::App(e1, e2, Span::default())))
 					}
+					_ => panic!("Missing result for function application"),
 				},
 				(true, DBExpr::Var(i)) => {
 					let name = match arg_stack.get(arg_stack.len() - 1 - i) {
 						Some((_, x)) => (*x).to_owned(),
-						None => panic!("Invalid de Bruijn index")
+						None => panic!("Invalid de Bruijn index"),
 					};
-					result_stack.push(Box::new(Expr::Var(name)))
-				},
+					result_stack.push(Box::new(Expr::Var(name, Span::default())))
+				}
 			}
 		}
 		result_stack.pop().unwrap()
 	}
 }
 
-
 const ALPHABET: [char; 26] = [
-	'a', 'b', 'c', 'd', 'e', 'f', 'g', 'h', 'i', 'j', 'k', 'l', 'm',
-	'n', 'o', 'p', 'q', 'r', 's', 't', 'u', 'v', 'w', 'x', 'y', 'z'
+	'a', 'b', 'c', 'd', 'e', 'f', 'g', 'h', 'i', 'j', 'k', 'l', 'm', 'n', 'o', 'p', 'q', 'r', 's',
+	't', 'u', 'v', 'w', 'x', 'y', 'z',
 ];
 
 fn choose_ident(i: usize) -> String {
@@ -141,18 +404,48 @@ mod debruijn_tests {
 	fn identity_to_debruijn() -> () {
 		let e = Box::new(Expr::Fun(
 			"x".to_owned(),
-			Box::new(Expr::Var("x".to_owned())),
+			Box::new(Expr::Var("x".to_owned(), Span::default())),
+			Span::default(),
 		));
-		let expected = Box::new(DBExpr::Fun(Box::new(DBExpr::Var(0))));
+		let expected = Term::new(DBExpr::Fun(Term::new(DBExpr::Var(0))));
 		assert_eq!(expected, e.to_debruijn());
 	}
 
+	#[test]
+	fn equal_terms_are_shared() -> () {
+		let build = || {
+			Term::new(DBExpr::Fun(Term::new(DBExpr::App(
+				Term::new(DBExpr::Var(0)),
+				Term::new(DBExpr::Var(1)),
+			))))
+		};
+		let (a, b) = (build(), build());
+		assert_eq!(a.id(), b.id());
+		assert_eq!(a.structural_hash(), b.structural_hash());
+		assert_eq!(1, a.free_vars());
+		assert_eq!(4, a.size());
+		assert_eq!(3, a.depth());
+		assert_ne!(a, Term::new(DBExpr::Fun(Term::new(DBExpr::Var(0)))));
+	}
+
+	#[test]
+	fn shared_subterms_are_stored_once() -> () {
+		// A tree with 2^64 leaves, but only 65 distinct subterms
+		let mut e = Term::new(DBExpr::Var(0));
+		for _ in 0..64 {
+			e = Term::new(DBExpr::App(e.clone(), e));
+		}
+		assert_eq!(usize::MAX, e.size());
+		assert_eq!(65, e.depth());
+	}
+
 	#[test]
 	fn identity_to_named() -> () {
-		let e = DBExpr::Fun(Box::new(DBExpr::Var(0)));
+		let e = DBExpr::Fun(Term::new(DBExpr::Var(0)));
 		let expected = Box::new(Expr::Fun(
 			"a".to_owned(),
-			Box::new(Expr::Var("a".to_owned())),
+			Box::new(Expr::Var("a".to_owned(), Span::default())),
+			Span::default(),
 		));
 		assert_eq!(expected, e.to_named());
 	}
@@ -165,15 +458,18 @@ mod debruijn_tests {
 			Box::new(Expr::Fun(
 				"z".to_owned(),
 				Box::new(Expr::App(
-					Box::new(Expr::Var("s".to_owned())),
-					Box::new(Expr::Var("z".to_owned())),
+					Box::new(Expr::Var("s".to_owned(), Span::default())),
+					Box::new(Expr::Var("z".to_owned(), Span::default())),
+					Span::default(),
 				)),
+				Span::default(),
 			)),
+			Span::default(),
 		));
 		// \.\.1(0)
-		let expected = Box::new(DBExpr::Fun(Box::new(DBExpr::Fun(Box::new(DBExpr::App(
-			Box::new(DBExpr::Var(1)),
-			Box::new(DBExpr::Var(0)),
+		let expected = Term::new(DBExpr::Fun(Term::new(DBExpr::Fun(Term::new(DBExpr::App(
+			Term::new(DBExpr::Var(1)),
+			Term::new(DBExpr::Var(0)),
 		))))));
 		assert_eq!(expected, e.to_debruijn());
 	}
@@ -181,9 +477,9 @@ mod debruijn_tests {
 	#[test]
 	fn one_to_named() -> () {
 		// \.\.1(0)
-		let e = Box::new(DBExpr::Fun(Box::new(DBExpr::Fun(Box::new(DBExpr::App(
-			Box::new(DBExpr::Var(1)),
-			Box::new(DBExpr::Var(0)),
+		let e = Term::new(DBExpr::Fun(Term::new(DBExpr::Fun(Term::new(DBExpr::App(
+			Term::new(DBExpr::Var(1)),
+			Term::new(DBExpr::Var(0)),
 		))))));
 		// \a.\b.a(b)
 		let expected = Box::new(Expr::Fun(
@@ -191,10 +487,13 @@ mod debruijn_tests {
 			Box::new(Expr::Fun(
 				"b".to_owned(),
 				Box::new(Expr::App(
-					Box::new(Expr::Var("a".to_owned())),
-					Box::new(Expr::Var("b".to_owned())),
+					Box::new(Expr::Var("a".to_owned(), Span::default())),
+					Box::new(Expr::Var("b".to_owned(), Span::default())),
+					Span::default(),
 				)),
+				Span::default(),
 			)),
+			Span::default(),
 		));
 		assert_eq!(expected, e.to_named());
 	}
@@ -208,28 +507,34 @@ mod debruijn_tests {
 				"y".to_owned(),
 				Box::new(Expr::App(
 					Box::new(Expr::App(
-						Box::new(Expr::Var("y".to_owned())),
+						Box::new(Expr::Var("y".to_owned(), Span::default())),
 						Box::new(Expr::Fun(
 							"x".to_owned(),
-							Box::new(Expr::Var("x".to_owned())),
+							Box::new(Expr::Var("x".to_owned(), Span::default())),
+							Span::default(),
 						)),
+						Span::default(),
 					)),
 					Box::new(Expr::App(
-						Box::new(Expr::Var("y".to_owned())),
-						Box::new(Expr::Var("x".to_owned())),
+						Box::new(Expr::Var("y".to_owned(), Span::default())),
+						Box::new(Expr::Var("x".to_owned(), Span::default())),
+						Span::default(),
 					)),
+					Span::default(),
 				)),
+				Span::default(),
 			)),
+			Span::default(),
 		));
 		// \.\.0 (\.0) (0 1)
-		let expected = Box::new(DBExpr::Fun(Box::new(DBExpr::Fun(Box::new(DBExpr::App(
-			Box::new(DBExpr::App(
-				Box::new(DBExpr::Var(0)),
-				Box::new(DBExpr::Fun(Box::new(DBExpr::Var(0)))),
+		let expected = Term::new(DBExpr::Fun(Term::new(DBExpr::Fun(Term::new(DBExpr::App(
+			Term::new(DBExpr::App(
+				Term::new(DBExpr::Var(0)),
+				Term::new(DBExpr::Fun(Term::new(DBExpr::Var(0)))),
 			)),
-			Box::new(DBExpr::App(
-				Box::new(DBExpr::Var(0)),
-				Box::new(DBExpr::Var(1)),
+			Term::new(DBExpr::App(
+				Term::new(DBExpr::Var(0)),
+				Term::new(DBExpr::Var(1)),
 			)),
 		))))));
 		assert_eq!(expected, e.to_debruijn());
@@ -238,14 +543,14 @@ mod debruijn_tests {
 	#[test]
 	fn deeply_nested_to_named() -> () {
 		// \.\.0 (\.0) (0 1)
-		let e = Box::new(DBExpr::Fun(Box::new(DBExpr::Fun(Box::new(DBExpr::App(
-			Box::new(DBExpr::App(
-				Box::new(DBExpr::Var(0)),
-				Box::new(DBExpr::Fun(Box::new(DBExpr::Var(0)))),
+		let e = Term::new(DBExpr::Fun(Term::new(DBExpr::Fun(Term::new(DBExpr::App(
+			Term::new(DBExpr::App(
+				Term::new(DBExpr::Var(0)),
+				Term::new(DBExpr::Fun(Term::new(DBExpr::Var(0)))),
 			)),
-			Box::new(DBExpr::App(
-				Box::new(DBExpr::Var(0)),
-				Box::new(DBExpr::Var(1)),
+			Term::new(DBExpr::App(
+				Term::new(DBExpr::Var(0)),
+				Term::new(DBExpr::Var(1)),
 			)),
 		))))));
 		// \a.\b.b (\c.c) (b a)
@@ -255,18 +560,24 @@ mod debruijn_tests {
 				"b".to_owned(),
 				Box::new(Expr::App(
 					Box::new(Expr::App(
-						Box::new(Expr::Var("b".to_owned())),
+						Box::new(Expr::Var("b".to_owned(), Span::default())),
 						Box::new(Expr::Fun(
 							"c".to_owned(),
-							Box::new(Expr::Var("c".to_owned())),
+							Box::new(Expr::Var("c".to_owned(), Span::default())),
+							Span::default(),
 						)),
+						Span::default(),
 					)),
 					Box::new(Expr::App(
-						Box::new(Expr::Var("b".to_owned())),
-						Box::new(Expr::Var("a".to_owned())),
+						Box::new(Expr::Var("b".to_owned(), Span::default())),
+						Box::new(Expr::Var("a".to_owned(), Span::default())),
+						Span::default(),
 					)),
+					Span::default(),
 				)),
+				Span::default(),
 			)),
+			Span::default(),
 		));
 		assert_eq!(expected, e.to_named());
 	}
@@ -280,23 +591,28 @@ mod debruijn_tests {
 				Box::new(Expr::App(
 					Box::new(Expr::Fun(
 						"b".to_owned(),
-						Box::new(Expr::Var("b".to_owned())),
+						Box::new(Expr::Var("b".to_owned(), Span::default())),
+						Span::default(),
 					)),
 					Box::new(Expr::Fun(
 						"b".to_owned(),
-						Box::new(Expr::Var("b".to_owned())),
+						Box::new(Expr::Var("b".to_owned(), Span::default())),
+						Span::default(),
 					)),
+					Span::default(),
 				)),
-				Box::new(Expr::Var("a".to_owned())),
+				Box::new(Expr::Var("a".to_owned(), Span::default())),
+				Span::default(),
 			)),
+			Span::default(),
 		));
 		// \.(\.0) (\.0) 0
-		let expected = Box::new(DBExpr::Fun(Box::new(DBExpr::App(
-			Box::new(DBExpr::App(
-				Box::new(DBExpr::Fun(Box::new(DBExpr::Var(0)))),
-				Box::new(DBExpr::Fun(Box::new(DBExpr::Var(0)))),
+		let expected = Term::new(DBExpr::Fun(Term::new(DBExpr::App(
+			Term::new(DBExpr::App(
+				Term::new(DBExpr::Fun(Term::new(DBExpr::Var(0)))),
+				Term::new(DBExpr::Fun(Term::new(DBExpr::Var(0)))),
 			)),
-			Box::new(DBExpr::Var(0)),
+			Term::new(DBExpr::Var(0)),
 		))));
 		assert_eq!(expected, e.to_debruijn());
 	}
@@ -304,12 +620,12 @@ mod debruijn_tests {
 	#[test]
 	fn flat_to_named() -> () {
 		// \.(\.0) (\.0) 0
-		let e = Box::new(DBExpr::Fun(Box::new(DBExpr::App(
-			Box::new(DBExpr::App(
-				Box::new(DBExpr::Fun(Box::new(DBExpr::Var(0)))),
-				Box::new(DBExpr::Fun(Box::new(DBExpr::Var(0)))),
+		let e = Term::new(DBExpr::Fun(Term::new(DBExpr::App(
+			Term::new(DBExpr::App(
+				Term::new(DBExpr::Fun(Term::new(DBExpr::Var(0)))),
+				Term::new(DBExpr::Fun(Term::new(DBExpr::Var(0)))),
 			)),
-			Box::new(DBExpr::Var(0)),
+			Term::new(DBExpr::Var(0)),
 		))));
 		// \a.(\b.b) (\b.b) a
 		// (Can reuse b here!)
@@ -319,15 +635,20 @@ mod debruijn_tests {
 				Box::new(Expr::App(
 					Box::new(Expr::Fun(
 						"b".to_owned(),
-						Box::new(Expr::Var("b".to_owned())),
+						Box::new(Expr::Var("b".to_owned(), Span::default())),
+						Span::default(),
 					)),
 					Box::new(Expr::Fun(
 						"b".to_owned(),
-						Box::new(Expr::Var("b".to_owned())),
+						Box::new(Expr::Var("b".to_owned(), Span::default())),
+						Span::default(),
 					)),
+					Span::default(),
 				)),
-				Box::new(Expr::Var("a".to_owned())),
+				Box::new(Expr::Var("a".to_owned(), Span::default())),
+				Span::default(),
 			)),
+			Span::default(),
 		));
 		assert_eq!(expected, e.to_named());
 	}
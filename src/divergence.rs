@@ -0,0 +1,265 @@
+use crate::debruijn::{DBExpr, FastMap, Term};
+use crate::error::Error;
+use crate::eval::{subterm, try_beta_reduce, Direction, Strategy};
+
+/// Watches the expressions visited during evaluation and reports terms that
+/// will certainly never reach a normal form:
+///  - The whole expression reduces back to itself (e.g., `(\x.x x) (\x.x x)`).
+///  - In normal order only, some subterm reduces back to itself or to an
+///    application of normal terms to itself (e.g., `Y succ`, which reduces to
+///    `\s.\z.s (Y' s z)` and then to `\s.\z.s (s (Y' s z))`), and all steps in
+///    between happened inside that subterm.
+///
+/// To keep memory use low, only the hashes of the subterms along the path to
+/// each redex are recorded, at exponentially spaced steps (as in Brent's cycle
+/// detection algorithm). When the hashes match, the earlier expression is
+/// recomputed to make sure the match is real.
+pub(crate) struct DivergenceDetector {
+	original: Term,
+	strategy: Strategy,
+	steps: usize,
+	path: Vec<Direction>,
+	/// One entry for each prefix of the current redex path.
+	checkpoints: Vec<Checkpoint>,
+}
+
+struct Checkpoint {
+	/// The first step since which every redex was inside the subterm at this
+	/// prefix.
+	since: usize,
+	/// Hash of the subterm at this prefix, and the step at which it was taken.
+	saved: Option<(u64, usize)>,
+	/// Step at which to save the next hash.
+	next: usize,
+}
+
+impl DivergenceDetector {
+	pub(crate) fn new(original: &Term, strategy: Strategy) -> Self {
+		DivergenceDetector {
+			original: original.clone(),
+			strategy,
+			steps: 0,
+			path: Vec::new(),
+			checkpoints: Vec::new(),
+		}
+	}
+
+	/// Must be called for each expression visited, before contracting the
+	/// redex at `redex`.
+	pub(crate) fn check(&mut self, e: &Term, redex: &[Direction]) -> Result<(), Error> {
+		let common = self
+			.path
+			.iter()
+			.zip(redex)
+			.take_while(|(a, b)| a == b)
+			.count();
+		self.checkpoints.truncate(common + 1);
+		while self.checkpoints.len() <= redex.len() {
+			self.checkpoints.push(Checkpoint {
+				since: self.steps,
+				saved: None,
+				next: self.steps,
+			});
+		}
+		self.path = redex.to_vec();
+
+		let mut hashes = vec![e.structural_hash()];
+		let mut sub = e;
+		for d in redex {
+			sub = subterm(sub, &[*d]);
+			hashes.push(sub.structural_hash());
+		}
+		let positions: FastMap<u64, usize> =
+			hashes.iter().enumerate().map(|(i, h)| (*h, i)).collect();
+		// The subterm at prefix k can reappear at prefix m if all steps in
+		// between go into an argument
+		let mut run_end = vec![redex.len(); redex.len() + 1];
+		for k in (0..redex.len()).rev() {
+			run_end[k] = if redex[k] == Direction::Argument {
+				run_end[k + 1]
+			} else {
+				k
+			};
+		}
+
+		for (k, checkpoint) in self.checkpoints.iter().enumerate() {
+			let Some((hash, i)) = checkpoint.saved else {
+				continue;
+			};
+			let Some(&m) = positions.get(&hash) else {
+				continue;
+			};
+			let allowed = if self.strategy == Strategy::NormalOrder {
+				k <= m && m <= run_end[k]
+			} else {
+				k == 0 && m == 0
+			};
+			if allowed {
+				if let Some(err) = self.confirm(e, i, k, m) {
+					return Err(err);
+				}
+			}
+		}
+
+		for (k, checkpoint) in self.checkpoints.iter_mut().enumerate() {
+			if self.steps == checkpoint.next {
+				checkpoint.saved = Some((hashes[k], self.steps));
+				checkpoint.next = checkpoint.since + 2 * (self.steps - checkpoint.since).max(1);
+			}
+		}
+		self.steps += 1;
+		Ok(())
+	}
+
+	/// Recomputes the expression at step `i` and checks whether its subterm
+	/// at the prefix of length `k` of the redex path is the same as the
+	/// subterm of `e` at the prefix of length `m`.
+	fn confirm(&self, e: &Term, i: usize, k: usize, m: usize) -> Option<Error> {
+		let mut earlier = self.original.clone();
+		let mut path = Vec::new();
+		for _ in 0..i {
+			earlier = try_beta_reduce(&earlier, self.strategy, &mut path)?;
+			path.clear();
+		}
+		let prefix = &self.path[..k];
+		if subterm(&earlier, prefix) != subterm(e, &self.path[..m]) {
+			return None;
+		}
+		let n = self.steps - i;
+		let steps = format!("{n} step{}", if n == 1 { "" } else { "s" });
+		let message = if m == 0 {
+			format!("term repeats after {steps}")
+		} else if k == m {
+			format!("`{}` repeats after {steps}", subterm_to_string(e, prefix))
+		} else {
+			format!(
+				"`{}` reduces to `{}` after {steps}, so it keeps growing forever",
+				subterm_to_string(&earlier, prefix),
+				subterm_to_string(e, prefix)
+			)
+		};
+		Some(Error::NonTermination(message))
+	}
+}
+
+/// Converts the subterm at the given path to a string, using the same names
+/// for the variables bound outside the subterm as `e.to_named()` would.
+fn subterm_to_string(e: &Term, path: &[Direction]) -> String {
+	let binders = vec![Direction::Body; path.iter().filter(|d| **d == Direction::Body).count()];
+	let mut closed = subterm(e, path).clone();
+	for _ in 0..binders.len() {
+		closed = Term::new(DBExpr::Fun(closed));
+	}
+	match closed.to_named().to_string_with_span(&binders) {
+		(s, Some((start, end))) => s[start..end].to_owned(),
+		(s, None) => s,
+	}
+}
+
+#[cfg(test)]
+mod divergence_tests {
+	use crate::debruijn::Term;
+	use crate::error::Error;
+	use crate::eval::Strategy;
+	use crate::lex;
+	use crate::parse;
+
+	fn parse(e: &str) -> Term {
+		let mut stream = lex::lex(e).unwrap();
+		parse::parse(&mut stream).unwrap().to_debruijn()
+	}
+
+	const DEFS: &str = "
+		where    Y = \\f.(\\x.f (x x)) (\\x.f (x x))
+		where    I = \\x.x
+		where succ = \\n.\\s.\\z.s (n s z)
+		where    0 = \\s.\\z.z";
+
+	fn non_termination(s: &str) -> Result<Term, Error> {
+		Err(Error::NonTermination(s.to_owned()))
+	}
+
+	#[test]
+	fn omega_repeats() -> () {
+		let e = parse("(\\x.x x) (\\x.x x)");
+		for s in [
+			Strategy::NormalOrder,
+			Strategy::ApplicativeOrder,
+			Strategy::CallByName,
+			Strategy::CallByValue,
+			Strategy::HeadReduction,
+		] {
+			assert_eq!(
+				non_termination("term repeats after 1 step"),
+				e.eval_with_strategy(s, Some(1000))
+			);
+		}
+	}
+
+	#[test]
+	fn y_identity_repeats() -> () {
+		let e = parse(&format!("Y I {DEFS}"));
+		assert_eq!(
+			non_termination("term repeats after 2 steps"),
+			e.eval_with_strategy(Strategy::NormalOrder, Some(1000))
+		);
+	}
+
+	#[test]
+	fn y_eta_repeats_under_lambda() -> () {
+		let e = parse(&format!("Y (\\f.\\x.f x) {DEFS}"));
+		assert_eq!(
+			non_termination("term repeats after 3 steps"),
+			e.eval_with_strategy(Strategy::NormalOrder, Some(1000))
+		);
+	}
+
+	#[test]
+	fn subterm_repeats() -> () {
+		let e = parse("(\\z.\\y.y z) ((\\x.x x) (\\x.x x))");
+		assert_eq!(
+			non_termination("`a ((\\b.b b) (\\b.b b))` repeats after 1 step"),
+			e.eval_with_strategy(Strategy::NormalOrder, Some(1000))
+		);
+	}
+
+	#[test]
+	fn y_succ_grows() -> () {
+		let e = parse(&format!("Y succ {DEFS}"));
+		let result = e.eval_with_strategy(Strategy::NormalOrder, Some(1000));
+		match result {
+			Err(Error::NonTermination(s)) => assert!(s.ends_with("so it keeps growing forever")),
+			r => panic!("Unexpected result {r:?}"),
+		}
+	}
+
+	#[test]
+	fn growth_in_function_position_is_not_reported() -> () {
+		// Each step adds an argument rather than wrapping the loop in one
+		let e = parse("(\\x.x x x) (\\x.x x x)");
+		assert!(matches!(
+			e.eval_with_strategy(Strategy::NormalOrder, Some(100)),
+			Err(Error::StepLimitExceeded(100, _))
+		));
+	}
+
+	#[test]
+	fn terminating_recursion_is_not_reported() -> () {
+		let e = parse(&format!("Y (\\f.\\n.n (\\x.f x) 0) (succ (succ 0)) {DEFS}"));
+		assert_eq!(
+			Ok(parse("\\s.\\z.z")),
+			e.eval_with_strategy(Strategy::NormalOrder, Some(1000))
+		);
+	}
+
+	#[test]
+	fn trace_reports_non_termination() -> () {
+		let e = parse(&format!("Y I {DEFS}"));
+		assert_eq!(
+			Err(Error::NonTermination(
+				"term repeats after 2 steps".to_owned()
+			)),
+			e.trace(Strategy::NormalOrder, Some(1000))
+		);
+	}
+}
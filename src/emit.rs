@@ -1,49 +1,205 @@
 use std::fmt::Display;
 
+use crate::eval::Direction;
 use crate::parse::Expr;
 
+/// Options for converting expressions to strings. The default options give
+/// the same result as `to_string`.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub struct EmitOptions {
+	/// Write nested lambdas with a single backslash, as in `\x y.x`.
+	pub collapse_lambdas: bool,
+	/// Write lambdas as `λ` instead of a backslash.
+	pub unicode_lambda: bool,
+}
+
+impl EmitOptions {
+	/// Converts the expression to a string.
+	pub fn emit(&self, e: &Expr) -> String {
+		let mut s = String::new();
+		emit(e, None, self, &mut s, &mut Vec::new());
+		s
+	}
+
+	/// Like `Expr::to_string_with_span`, but with these options.
+	pub fn emit_with_span(&self, e: &Expr, path: &[Direction]) -> (String, Option<(usize, usize)>) {
+		let mut s = String::new();
+		let mut spans = Vec::new();
+		emit(e, Some(Highlight::Path(path)), self, &mut s, &mut spans);
+		(s, spans.pop())
+	}
+
+	/// Like `Expr::to_string_with_redex_spans`, but with these options.
+	pub fn emit_with_redex_spans(&self, e: &Expr) -> (String, Vec<(usize, usize)>) {
+		let mut s = String::new();
+		let mut spans = Vec::new();
+		emit(e, Some(Highlight::Redexes), self, &mut s, &mut spans);
+		(s, spans)
+	}
+}
+
+/// The alternate flag (`{:#}`) collapses nested lambdas.
 impl Display for Expr {
 	fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
-		let s = match self {
-			Expr::Var(name) => name.clone(),
-			Expr::Fun(x, body) => format!("\\{x}.{}", body.to_string()),
-			Expr::App(f, a) => {
-				let lhs = match f.as_ref() {
-					Expr::Var(_) | Expr::App(_, _) => f.to_string(),
-					Expr::Fun(_, _) => format!("({})", f.to_string()),
-				};
-				let rhs = match a.as_ref() {
-					Expr::Var(_) => a.to_string(),
-					Expr::Fun(_, _) | Expr::App(_, _) => format!("({})", a.to_string()),
-				};
-				format!("{lhs} {rhs}")
+		let options = EmitOptions {
+			collapse_lambdas: f.alternate(),
+			..Default::default()
+		};
+		f.write_str(&options.emit(self))
+	}
+}
+
+impl Expr {
+	/// Converts the expression to a string, as with `to_string`, and also
+	/// returns the byte range of the subterm at the given path.
+	pub fn to_string_with_span(&self, path: &[Direction]) -> (String, Option<(usize, usize)>) {
+		EmitOptions::default().emit_with_span(self, path)
+	}
+
+	/// Converts the expression to a string, as with `to_string`, and also
+	/// returns the byte ranges of the outermost redexes, from left to right.
+	pub fn to_string_with_redex_spans(&self) -> (String, Vec<(usize, usize)>) {
+		EmitOptions::default().emit_with_redex_spans(self)
+	}
+}
+
+/// The subterms whose positions should be saved by `emit`.
+#[derive(Clone, Copy)]
+enum Highlight<'p> {
+	/// The subterm at the given path, if any.
+	Path(&'p [Direction]),
+	/// Every redex that is not inside another redex.
+	Redexes,
+}
+
+impl<'p> Highlight<'p> {
+	/// The subterms to highlight in the child of `e` in direction `d`.
+	fn descend(highlight: Option<Self>, e: &Expr, d: Direction) -> Option<Self> {
+		match highlight {
+			Some(Highlight::Path([first, rest @ ..])) if *first == d => Some(Highlight::Path(rest)),
+			Some(Highlight::Redexes) if !is_redex(e) => Some(Highlight::Redexes),
+			_ => None,
+		}
+	}
+}
+
+fn is_redex(e: &Expr) -> bool {
+	matches!(e, Expr::App(f, _, _) if matches!(f.as_ref(), Expr::Fun(_, _, _)))
+}
+
+/// Appends the string representation of `e` to `out`, and appends the
+/// positions of the subterms selected by `highlight` to `spans`.
+fn emit(
+	e: &Expr,
+	highlight: Option<Highlight>,
+	options: &EmitOptions,
+	out: &mut String,
+	spans: &mut Vec<(usize, usize)>,
+) {
+	enum Work<'e> {
+		Expr(&'e Expr, Option<Highlight<'e>>),
+		Str(&'static str),
+		SpanEnd(usize),
+	}
+	// An explicit stack is used so that deep expressions cannot overflow the
+	// call stack. Items are popped in the order in which they are emitted.
+	let mut work = vec![Work::Expr(e, highlight)];
+	while let Some(w) = work.pop() {
+		let (e, highlight) = match w {
+			Work::Expr(e, highlight) => (e, highlight),
+			Work::Str(s) => {
+				out.push_str(s);
+				continue;
+			}
+			Work::SpanEnd(start) => {
+				spans.push((start, out.len()));
+				continue;
 			}
 		};
-		f.write_str(&s)
+		let highlighted = match highlight {
+			Some(Highlight::Path(p)) => p.is_empty(),
+			Some(Highlight::Redexes) => is_redex(e),
+			None => false,
+		};
+		if highlighted {
+			work.push(Work::SpanEnd(out.len()));
+		}
+		match e {
+			Expr::Var(name, _) => out.push_str(name),
+			Expr::Fun(x, body, _) => {
+				out.push(if options.unicode_lambda { 'λ' } else { '\\' });
+				out.push_str(x);
+				let mut body = body;
+				let mut highlight = Highlight::descend(highlight, e, Direction::Body);
+				while let (true, Expr::Fun(y, inner, _)) = (options.collapse_lambdas, body.as_ref())
+				{
+					// A highlighted lambda must start with its own backslash
+					if matches!(highlight, Some(Highlight::Path([]))) {
+						break;
+					}
+					out.push(' ');
+					out.push_str(y);
+					highlight = Highlight::descend(highlight, body, Direction::Body);
+					body = inner;
+				}
+				out.push('.');
+				work.push(Work::Expr(body, highlight));
+			}
+			Expr::App(f, a, _) => {
+				let parenthesize_f = matches!(f.as_ref(), Expr::Fun(_, _, _));
+				let parenthesize_a = !matches!(a.as_ref(), Expr::Var(_, _));
+				if parenthesize_a {
+					work.push(Work::Str(")"));
+				}
+				work.push(Work::Expr(
+					a,
+					Highlight::descend(highlight, e, Direction::Argument),
+				));
+				work.push(Work::Str(if parenthesize_a { " (" } else { " " }));
+				if parenthesize_f {
+					work.push(Work::Str(")"));
+				}
+				work.push(Work::Expr(
+					f,
+					Highlight::descend(highlight, e, Direction::Function),
+				));
+				if parenthesize_f {
+					work.push(Work::Str("("));
+				}
+			}
+		}
 	}
 }
 
 #[cfg(test)]
 mod emit_tests {
+	use crate::emit::EmitOptions;
+	use crate::eval::Direction;
+	use crate::lex::Span;
 	use crate::parse::Expr;
 
 	#[test]
 	fn emit_var() -> () {
-		let e = Expr::Var("x".to_owned());
+		let e = Expr::Var("x".to_owned(), Span::default());
 		assert_eq!("x", e.to_string());
 	}
 
 	#[test]
 	fn emit_identity() -> () {
-		let e = Expr::Fun("z".to_owned(), Box::new(Expr::Var("z".to_owned())));
+		let e = Expr::Fun(
+			"z".to_owned(),
+			Box::new(Expr::Var("z".to_owned(), Span::default())),
+			Span::default(),
+		);
 		assert_eq!("\\z.z", e.to_string());
 	}
 
 	#[test]
 	fn emit_app_var_var() -> () {
 		let e = Expr::App(
-			Box::new(Expr::Var("s".to_owned())),
-			Box::new(Expr::Var("z".to_owned())),
+			Box::new(Expr::Var("s".to_owned(), Span::default())),
+			Box::new(Expr::Var("z".to_owned(), Span::default())),
+			Span::default(),
 		);
 		assert_eq!("s z", e.to_string());
 	}
@@ -51,11 +207,13 @@ mod emit_tests {
 	#[test]
 	fn emit_app_var_fun() -> () {
 		let e = Expr::App(
-			Box::new(Expr::Var("f".to_owned())),
+			Box::new(Expr::Var("f".to_owned(), Span::default())),
 			Box::new(Expr::Fun(
 				"x".to_owned(),
-				Box::new(Expr::Var("x".to_owned())),
+				Box::new(Expr::Var("x".to_owned(), Span::default())),
+				Span::default(),
 			)),
+			Span::default(),
 		);
 		assert_eq!("f (\\x.x)", e.to_string());
 	}
@@ -63,11 +221,13 @@ mod emit_tests {
 	#[test]
 	fn emit_app_var_app() -> () {
 		let e = Expr::App(
-			Box::new(Expr::Var("s".to_owned())),
+			Box::new(Expr::Var("s".to_owned(), Span::default())),
 			Box::new(Expr::App(
-				Box::new(Expr::Var("s".to_owned())),
-				Box::new(Expr::Var("z".to_owned())),
+				Box::new(Expr::Var("s".to_owned(), Span::default())),
+				Box::new(Expr::Var("z".to_owned(), Span::default())),
+				Span::default(),
 			)),
+			Span::default(),
 		);
 		assert_eq!("s (s z)", e.to_string());
 	}
@@ -77,9 +237,11 @@ mod emit_tests {
 		let e = Expr::App(
 			Box::new(Expr::Fun(
 				"x".to_owned(),
-				Box::new(Expr::Var("x".to_owned())),
+				Box::new(Expr::Var("x".to_owned(), Span::default())),
+				Span::default(),
 			)),
-			Box::new(Expr::Var("y".to_owned())),
+			Box::new(Expr::Var("y".to_owned(), Span::default())),
+			Span::default(),
 		);
 		assert_eq!("(\\x.x) y", e.to_string());
 	}
@@ -89,12 +251,15 @@ mod emit_tests {
 		let e = Expr::App(
 			Box::new(Expr::Fun(
 				"x".to_owned(),
-				Box::new(Expr::Var("x".to_owned())),
+				Box::new(Expr::Var("x".to_owned(), Span::default())),
+				Span::default(),
 			)),
 			Box::new(Expr::Fun(
 				"y".to_owned(),
-				Box::new(Expr::Var("y".to_owned())),
+				Box::new(Expr::Var("y".to_owned(), Span::default())),
+				Span::default(),
 			)),
+			Span::default(),
 		);
 		assert_eq!("(\\x.x) (\\y.y)", e.to_string());
 	}
@@ -104,12 +269,15 @@ mod emit_tests {
 		let e = Expr::App(
 			Box::new(Expr::Fun(
 				"x".to_owned(),
-				Box::new(Expr::Var("x".to_owned())),
+				Box::new(Expr::Var("x".to_owned(), Span::default())),
+				Span::default(),
 			)),
 			Box::new(Expr::App(
-				Box::new(Expr::Var("a".to_owned())),
-				Box::new(Expr::Var("b".to_owned())),
+				Box::new(Expr::Var("a".to_owned(), Span::default())),
+				Box::new(Expr::Var("b".to_owned(), Span::default())),
+				Span::default(),
 			)),
+			Span::default(),
 		);
 		assert_eq!("(\\x.x) (a b)", e.to_string());
 	}
@@ -118,10 +286,12 @@ mod emit_tests {
 	fn emit_app_app_var() -> () {
 		let e = Expr::App(
 			Box::new(Expr::App(
-				Box::new(Expr::Var("x".to_owned())),
-				Box::new(Expr::Var("y".to_owned())),
+				Box::new(Expr::Var("x".to_owned(), Span::default())),
+				Box::new(Expr::Var("y".to_owned(), Span::default())),
+				Span::default(),
 			)),
-			Box::new(Expr::Var("z".to_owned())),
+			Box::new(Expr::Var("z".to_owned(), Span::default())),
+			Span::default(),
 		);
 		assert_eq!("x y z", e.to_string());
 	}
@@ -130,13 +300,16 @@ mod emit_tests {
 	fn emit_app_app_fun() -> () {
 		let e = Expr::App(
 			Box::new(Expr::App(
-				Box::new(Expr::Var("x".to_owned())),
-				Box::new(Expr::Var("y".to_owned())),
+				Box::new(Expr::Var("x".to_owned(), Span::default())),
+				Box::new(Expr::Var("y".to_owned(), Span::default())),
+				Span::default(),
 			)),
 			Box::new(Expr::Fun(
 				"z".to_owned(),
-				Box::new(Expr::Var("z".to_owned())),
+				Box::new(Expr::Var("z".to_owned(), Span::default())),
+				Span::default(),
 			)),
+			Span::default(),
 		);
 		assert_eq!("x y (\\z.z)", e.to_string());
 	}
@@ -145,14 +318,152 @@ mod emit_tests {
 	fn emit_app_app_app() -> () {
 		let e = Expr::App(
 			Box::new(Expr::App(
-				Box::new(Expr::Var("x".to_owned())),
-				Box::new(Expr::Var("y".to_owned())),
+				Box::new(Expr::Var("x".to_owned(), Span::default())),
+				Box::new(Expr::Var("y".to_owned(), Span::default())),
+				Span::default(),
 			)),
 			Box::new(Expr::App(
-				Box::new(Expr::Var("z".to_owned())),
-				Box::new(Expr::Var("w".to_owned())),
+				Box::new(Expr::Var("z".to_owned(), Span::default())),
+				Box::new(Expr::Var("w".to_owned(), Span::default())),
+				Span::default(),
 			)),
+			Span::default(),
 		);
 		assert_eq!("x y (z w)", e.to_string());
 	}
+
+	#[test]
+	fn emit_with_span_root() -> () {
+		let e = Expr::App(
+			Box::new(Expr::Var("x".to_owned(), Span::default())),
+			Box::new(Expr::Var("y".to_owned(), Span::default())),
+			Span::default(),
+		);
+		assert_eq!(("x y".to_owned(), Some((0, 3))), e.to_string_with_span(&[]));
+	}
+
+	#[test]
+	fn emit_with_span_nested() -> () {
+		// \x.x ((\y.y) x)
+		let e = Expr::Fun(
+			"x".to_owned(),
+			Box::new(Expr::App(
+				Box::new(Expr::Var("x".to_owned(), Span::default())),
+				Box::new(Expr::App(
+					Box::new(Expr::Fun(
+						"y".to_owned(),
+						Box::new(Expr::Var("y".to_owned(), Span::default())),
+						Span::default(),
+					)),
+					Box::new(Expr::Var("x".to_owned(), Span::default())),
+					Span::default(),
+				)),
+				Span::default(),
+			)),
+			Span::default(),
+		);
+		let (s, span) = e.to_string_with_span(&[Direction::Body, Direction::Argument]);
+		assert_eq!("\\x.x ((\\y.y) x)", s);
+		assert_eq!(Some((6, 14)), span);
+		assert_eq!("(\\y.y) x", &s[6..14]);
+		let (_, span) =
+			e.to_string_with_span(&[Direction::Body, Direction::Argument, Direction::Function]);
+		assert_eq!(Some((7, 11)), span);
+	}
+
+	#[test]
+	fn emit_with_span_invalid_path() -> () {
+		let e = Expr::Var("x".to_owned(), Span::default());
+		assert_eq!(
+			("x".to_owned(), None),
+			e.to_string_with_span(&[Direction::Body])
+		);
+	}
+
+	#[test]
+	fn emit_with_redex_spans() -> () {
+		let id = |x: &str| {
+			Expr::Fun(
+				x.to_owned(),
+				Box::new(Expr::Var(x.to_owned(), Span::default())),
+				Span::default(),
+			)
+		};
+		let app = |f, a| Expr::App(Box::new(f), Box::new(a), Span::default());
+		// x ((\y.y) ((\z.z) x)) ((\w.w) x)
+		let e = app(
+			app(
+				Expr::Var("x".to_owned(), Span::default()),
+				app(
+					id("y"),
+					app(id("z"), Expr::Var("x".to_owned(), Span::default())),
+				),
+			),
+			app(id("w"), Expr::Var("x".to_owned(), Span::default())),
+		);
+		let (s, spans) = e.to_string_with_redex_spans();
+		assert_eq!("x ((\\y.y) ((\\z.z) x)) ((\\w.w) x)", s);
+		let redexes: Vec<&str> = spans.iter().map(|(start, end)| &s[*start..*end]).collect();
+		assert_eq!(vec!["(\\y.y) ((\\z.z) x)", "(\\w.w) x"], redexes);
+	}
+
+	#[test]
+	fn emit_collapse_lambdas() -> () {
+		let fun = |x: &str, body| Expr::Fun(x.to_owned(), Box::new(body), Span::default());
+		let var = |x: &str| Expr::Var(x.to_owned(), Span::default());
+		let app = |f, a| Expr::App(Box::new(f), Box::new(a), Span::default());
+		// \f.\x.f (\y.\z.y) x
+		let e = fun(
+			"f",
+			fun(
+				"x",
+				app(app(var("f"), fun("y", fun("z", var("y")))), var("x")),
+			),
+		);
+		let options = EmitOptions {
+			collapse_lambdas: true,
+			..Default::default()
+		};
+		assert_eq!("\\f x.f (\\y z.y) x", options.emit(&e));
+		assert_eq!("\\f x.f (\\y z.y) x", format!("{e:#}"));
+		assert_eq!("\\f.\\x.f (\\y.\\z.y) x", e.to_string());
+	}
+
+	#[test]
+	fn emit_collapse_lambdas_with_span() -> () {
+		let fun = |x: &str, body| Expr::Fun(x.to_owned(), Box::new(body), Span::default());
+		let var = |x: &str| Expr::Var(x.to_owned(), Span::default());
+		// \x.\y.\z.x
+		let e = fun("x", fun("y", fun("z", var("x"))));
+		let options = EmitOptions {
+			collapse_lambdas: true,
+			..Default::default()
+		};
+		let (s, span) = options.emit_with_span(&e, &[Direction::Body]);
+		assert_eq!("\\x.\\y z.x", s);
+		assert_eq!(Some((3, 9)), span);
+		let (s, span) = options.emit_with_span(&e, &[Direction::Body; 3]);
+		assert_eq!("\\x y z.x", s);
+		assert_eq!(Some((7, 8)), span);
+	}
+
+	#[test]
+	fn emit_unicode_lambda() -> () {
+		let fun = |x: &str, body| Expr::Fun(x.to_owned(), Box::new(body), Span::default());
+		let var = |x: &str| Expr::Var(x.to_owned(), Span::default());
+		// \x.\y.x
+		let e = fun("x", fun("y", var("x")));
+		let options = EmitOptions {
+			unicode_lambda: true,
+			..Default::default()
+		};
+		assert_eq!("λx.λy.x", options.emit(&e));
+		let options = EmitOptions {
+			collapse_lambdas: true,
+			unicode_lambda: true,
+		};
+		let (s, span) = options.emit_with_span(&e, &[Direction::Body]);
+		assert_eq!("λx.λy.x", s);
+		assert_eq!(Some((4, 9)), span);
+	}
 }
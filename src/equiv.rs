@@ -0,0 +1,168 @@
+use std::collections::{HashMap, HashSet};
+
+use wasm_bindgen::prelude::*;
+
+use crate::error::Error;
+use crate::eval::Strategy;
+use crate::parse::Expr;
+
+/// Whether two expressions are beta-equivalent (see `beta_eq`).
+#[wasm_bindgen]
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum Equivalence {
+	Equal,
+	NotEqual,
+	/// The answer could not be decided within the step limit.
+	Unknown,
+}
+
+/// Checks whether the expressions are the same up to the names of bound
+/// variables. Free variables must have the same names.
+pub fn alpha_eq(a: &Expr, b: &Expr) -> bool {
+	let free = shared_free_vars(a, b);
+	a.to_debruijn_with_free_vars(&free) == b.to_debruijn_with_free_vars(&free)
+}
+
+/// Checks whether the expressions are beta-equivalent by reducing both to
+/// their normal forms, performing at most `fuel` beta reductions on each.
+/// Free variables are left as they are.
+///
+/// The result is `Unknown` if the answer cannot be decided within the step
+/// limit. Expressions that certainly have no normal form are only known to
+/// be different from expressions that have one.
+pub fn beta_eq(a: &Expr, b: &Expr, fuel: usize) -> Equivalence {
+	let free = shared_free_vars(a, b);
+	let a = a.to_debruijn_with_free_vars(&free);
+	let b = b.to_debruijn_with_free_vars(&free);
+	let a = a.eval_with_strategy(Strategy::NormalOrder, Some(fuel));
+	let b = b.eval_with_strategy(Strategy::NormalOrder, Some(fuel));
+	match (a, b) {
+		// By the Church-Rosser theorem, beta-equivalent expressions have the
+		// same normal form if they have one at all
+		(Ok(a), Ok(b)) if a == b => Equivalence::Equal,
+		(Ok(_), Ok(_)) => Equivalence::NotEqual,
+		(Ok(_), Err(Error::NonTermination(_))) | (Err(Error::NonTermination(_)), Ok(_)) => {
+			Equivalence::NotEqual
+		}
+		// Expressions that reduce to the same term are equivalent, even
+		// without a normal form
+		(Err(Error::StepLimitExceeded(_, a)), Err(Error::StepLimitExceeded(_, b))) if a == b => {
+			Equivalence::Equal
+		}
+		_ => Equivalence::Unknown,
+	}
+}
+
+/// Returns the variables that are free in either expression, in the order in
+/// which they first appear.
+fn shared_free_vars<'e>(a: &'e Expr, b: &'e Expr) -> Vec<&'e str> {
+	let mut free = Vec::new();
+	let mut seen = HashSet::new();
+	for e in [a, b] {
+		enum Work<'e> {
+			Visit(&'e Expr),
+			Unbind(&'e str),
+		}
+		// Number of enclosing lambdas binding each name
+		let mut bound: HashMap<&str, usize> = HashMap::new();
+		let mut work = vec![Work::Visit(e)];
+		while let Some(w) = work.pop() {
+			match w {
+				Work::Visit(Expr::Var(x, _)) => {
+					if bound.get(x.as_str()).copied().unwrap_or(0) == 0 && seen.insert(x.as_str()) {
+						free.push(x.as_str());
+					}
+				}
+				Work::Visit(Expr::Fun(x, body, _)) => {
+					*bound.entry(x).or_insert(0) += 1;
+					work.push(Work::Unbind(x));
+					work.push(Work::Visit(body));
+				}
+				Work::Visit(Expr::App(f, a, _)) => {
+					work.push(Work::Visit(a));
+					work.push(Work::Visit(f));
+				}
+				Work::Unbind(x) => {
+					if let Some(n) = bound.get_mut(x) {
+						*n -= 1;
+					}
+				}
+			}
+		}
+	}
+	free
+}
+
+#[cfg(test)]
+mod equiv_tests {
+	use crate::equiv::{alpha_eq, beta_eq, Equivalence};
+	use crate::lex;
+	use crate::parse::{self, Expr};
+
+	fn parse(e: &str) -> Box<Expr> {
+		let mut stream = lex::lex(e).unwrap();
+		parse::parse(&mut stream).unwrap()
+	}
+
+	const DEFS: &str = "
+		where    + = \\m.\\n.\\s.\\z.m s (n s z)
+		where    * = \\m.\\n.\\s.m (n s)
+		where    K = \\x.\\y.x
+		where    W = (\\x.x x) (\\x.x x)
+		where    2 = \\s.\\z.s (s z)
+		where    3 = \\s.\\z.s (s (s z))";
+
+	#[test]
+	fn alpha_eq_renamed() -> () {
+		assert!(alpha_eq(&parse("\\x.\\y.x y"), &parse("\\a.\\b.a b")));
+		assert!(!alpha_eq(&parse("\\x.\\y.x y"), &parse("\\a.\\b.b a")));
+	}
+
+	#[test]
+	fn alpha_eq_shadowing() -> () {
+		assert!(alpha_eq(&parse("\\x.\\x.x"), &parse("\\a.\\b.b")));
+		assert!(!alpha_eq(&parse("\\x.\\x.x"), &parse("\\a.\\b.a")));
+	}
+
+	#[test]
+	fn alpha_eq_free_vars() -> () {
+		assert!(alpha_eq(&parse("\\x.f x y"), &parse("\\z.f z y")));
+		assert!(!alpha_eq(&parse("\\x.f x y"), &parse("\\z.g z y")));
+		assert!(!alpha_eq(&parse("\\x.f x y"), &parse("\\z.f z z")));
+		// A free variable is different from a bound one with the same name
+		assert!(!alpha_eq(&parse("\\x.x"), &parse("\\y.x")));
+		assert!(!alpha_eq(&parse("(\\x.x) x"), &parse("(\\x.x) y")));
+	}
+
+	#[test]
+	fn beta_eq_normal_forms() -> () {
+		let e = |t: &str| parse(&format!("{t} {DEFS}"));
+		assert_eq!(Equivalence::Equal, beta_eq(&e("+ 2 3"), &e("+ 3 2"), 1000));
+		assert_eq!(Equivalence::Equal, beta_eq(&e("* 2 3"), &e("+ 3 3"), 1000));
+		assert_eq!(
+			Equivalence::NotEqual,
+			beta_eq(&e("* 2 3"), &e("+ 2 3"), 1000)
+		);
+	}
+
+	#[test]
+	fn beta_eq_free_vars() -> () {
+		assert_eq!(
+			Equivalence::Equal,
+			beta_eq(&parse("(\\x.f x) y"), &parse("f y"), 10)
+		);
+		assert_eq!(
+			Equivalence::NotEqual,
+			beta_eq(&parse("(\\x.f x) y"), &parse("f z"), 10)
+		);
+	}
+
+	#[test]
+	fn beta_eq_out_of_fuel() -> () {
+		let e = |t: &str| parse(&format!("{t} {DEFS}"));
+		assert_eq!(Equivalence::Unknown, beta_eq(&e("* 3 3"), &e("+ 3 3"), 2));
+		assert_eq!(Equivalence::Equal, beta_eq(&e("K 2 W"), &e("2"), 10));
+		assert_eq!(Equivalence::NotEqual, beta_eq(&e("W"), &e("2"), 10));
+		assert_eq!(Equivalence::Unknown, beta_eq(&e("W"), &e("K W 2"), 10));
+	}
+}
@@ -1,12 +1,91 @@
 use std::fmt::Display;
 
-use wasm_bindgen::JsValue;
+use wasm_bindgen::prelude::*;
 
-#[derive(Debug, PartialEq)]
+use crate::debruijn::Term;
+use crate::lex::Span;
+
+#[derive(Clone, Debug, PartialEq)]
 pub enum Error {
-	SyntaxError(String),
+	/// A problem with the program's syntax, and the part of the program that
+	/// it is about.
+	SyntaxError(String, Span),
 	TypeError(String),
 	MalformedType(String),
+	/// Evaluation was stopped after the given number of steps.
+	/// Also contains the partially reduced expression.
+	StepLimitExceeded(usize, Term),
+	/// Evaluation was cancelled after the given number of steps (see
+	/// `Evaluation::cancel`). Also contains the partially reduced expression.
+	Cancelled(usize, Term),
+	UnsupportedStrategy(String),
+	/// Evaluation was stopped because it would never terminate. Contains an
+	/// explanation of the loop that was found.
+	NonTermination(String),
+	/// A variable that is not bound by any lambda or declaration, and where it
+	/// is used.
+	FreeVariable(String, Span),
+	/// A numeric literal, as written, is larger than the given maximum for its
+	/// encoding (see `literals::MAX_UNARY_NUMERAL`), and where it is used.
+	NumeralTooLarge(String, usize, Span),
+	/// An error along with the line of the program where it is, with carets
+	/// under the offending text (see `Error::annotate`).
+	Annotated(Box<Error>, String),
+}
+
+impl Error {
+	/// The part of the program that the error is about, if any.
+	pub fn span(&self) -> Option<Span> {
+		match self {
+			Error::SyntaxError(_, span)
+			| Error::FreeVariable(_, span)
+			| Error::NumeralTooLarge(_, _, span) => Some(*span),
+			Error::Annotated(e, _) => e.span(),
+			_ => None,
+		}
+	}
+
+	/// Moves a syntax error to `span`. Other errors are returned unchanged.
+	pub fn at(self, span: Span) -> Error {
+		match self {
+			Error::SyntaxError(s, _) => Error::SyntaxError(s, span),
+			e => e,
+		}
+	}
+
+	/// Adds the line of `src` that the error is about, if any, so that it is
+	/// shown along with the error. `src` must be the program that the span of
+	/// the error refers to.
+	pub fn annotate(self, src: &str) -> Error {
+		match self.span().and_then(|span| snippet(src, span)) {
+			Some(snippet) => Error::Annotated(Box::new(self), snippet),
+			None => self,
+		}
+	}
+}
+
+/// Renders the line of `src` where `span` starts, with carets under the part
+/// of the line that `span` covers, or under the end of the line if it is
+/// empty.
+fn snippet(src: &str, span: Span) -> Option<String> {
+	let before = src.get(..span.start)?;
+	let line_start = before.rfind('\n').map_or(0, |i| i + 1);
+	let line_end = src[span.start..]
+		.find('\n')
+		.map_or(src.len(), |i| span.start + i);
+	let line = src[line_start..line_end].trim_end_matches('\r');
+	let covered = src.get(span.start..span.end.clamp(span.start, line_end))?;
+	// Keep tabs so that the carets line up however wide they are
+	let indent: String = before[line_start..]
+		.chars()
+		.map(|c| if c == '\t' { '\t' } else { ' ' })
+		.collect();
+	let carets = "^".repeat(covered.chars().count().max(1));
+	let number = span.line.to_string();
+	let gutter = " ".repeat(number.len());
+	Some(format!(
+		"{gutter} |\n{number} | {line}\n{gutter} | {indent}{carets}"
+	))
 }
 
 impl std::error::Error for Error {}
@@ -14,16 +93,131 @@ impl std::error::Error for Error {}
 impl Display for Error {
 	fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
 		match self {
-			Error::SyntaxError(s) => write!(f, "Syntax error: {s}"),
+			Error::SyntaxError(s, span) => write!(
+				f,
+				"Syntax error at line {}, column {}: {s}",
+				span.line, span.column
+			),
 			Error::TypeError(t) => write!(f, "Type error: could not interpret result as {t}"),
 			Error::MalformedType(s) => write!(f, "Malformed type: {s}"),
+			Error::StepLimitExceeded(n, e) => write!(
+				f,
+				"Step limit exceeded: no normal form found after {n} steps. Partial result:\n{}",
+				e.to_named()
+			),
+			Error::Cancelled(n, e) => write!(
+				f,
+				"Evaluation cancelled after {n} steps. Partial result:\n{}",
+				e.to_named()
+			),
+			Error::UnsupportedStrategy(s) => write!(f, "Unsupported strategy: {s}"),
+			Error::NonTermination(s) => write!(f, "Evaluation does not terminate: {s}"),
+			Error::FreeVariable(x, _) => write!(f, "Free variable: `{x}` is not bound"),
+			Error::NumeralTooLarge(n, max, _) => {
+				write!(f, "Numeral too large: {n} is more than {max}")
+			}
+			Error::Annotated(e, snippet) => write!(f, "{e}\n{snippet}"),
+		}
+	}
+}
+
+/// An error as passed to JavaScript, with the part of the program that it is
+/// about so that the editor can point it out.
+#[wasm_bindgen]
+#[derive(Clone, Debug, PartialEq)]
+pub struct Diagnostic {
+	message: String,
+	span: Option<Span>,
+}
+
+#[wasm_bindgen]
+impl Diagnostic {
+	/// The full error message, as shown to the user.
+	#[wasm_bindgen(getter)]
+	pub fn message(&self) -> String {
+		self.message.clone()
+	}
+
+	/// The part of the program that the error is about, if any. The offsets
+	/// are in bytes of the program's UTF-8 encoding.
+	#[wasm_bindgen(getter)]
+	pub fn span(&self) -> Option<Span> {
+		self.span
+	}
+
+	/// Lets the error be used as a string, as errors used to be.
+	#[wasm_bindgen(js_name = toString)]
+	pub fn to_js_string(&self) -> String {
+		self.message.clone()
+	}
+}
+
+impl From<Error> for Diagnostic {
+	fn from(value: Error) -> Self {
+		Diagnostic {
+			message: value.to_string(),
+			span: value.span(),
 		}
 	}
 }
 
 impl From<Error> for JsValue {
 	fn from(value: Error) -> Self {
-		// TODO: Pass back an object instead?
-		JsValue::from_str(&value.to_string())
+		Diagnostic::from(value).into()
+	}
+}
+
+#[cfg(test)]
+mod error_tests {
+	use crate::error::Error;
+	use crate::lex::{lex, Span};
+	use crate::parse::parse;
+
+	fn parse_error(src: &str) -> String {
+		let e = lex(src).and_then(|mut tokens| parse(&mut tokens));
+		e.unwrap_err().annotate(src).to_string()
+	}
+
+	#[test]
+	fn annotate_syntax_error() -> () {
+		assert_eq!(
+			"Syntax error at line 2, column 11: unexpected token \")\"\n  |\n2 | where f = )\n  |           ^",
+			parse_error("\\x.x\nwhere f = )")
+		);
+		assert_eq!(
+			"Syntax error at line 1, column 4: expected \".\" but got \"where\"\n  |\n1 | \\x where\n  |    ^^^^^",
+			parse_error("\\x where")
+		);
+	}
+
+	#[test]
+	fn annotate_keeps_tabs() -> () {
+		assert_eq!(
+			"Syntax error at line 1, column 5: invalid character: '§'\n  |\n1 | \tλé §\n  | \t   ^",
+			parse_error("\tλé §")
+		);
+	}
+
+	#[test]
+	fn annotate_end_of_program() -> () {
+		assert_eq!(
+			"Syntax error at line 1, column 4: expected \".\" but got \"<END>\"\n  |\n1 | \\x \n  |    ^",
+			parse_error("\\x ")
+		);
+	}
+
+	#[test]
+	fn annotate_other_errors() -> () {
+		let e = Error::TypeError("bool".to_owned());
+		assert_eq!(Error::TypeError("bool".to_owned()), e.annotate("x"));
+		// A span that is not in the program is ignored
+		let span = Span {
+			start: 5,
+			end: 6,
+			line: 1,
+			column: 6,
+		};
+		let e = Error::SyntaxError("oops".to_owned(), span);
+		assert_eq!(Error::SyntaxError("oops".to_owned(), span), e.annotate("x"));
 	}
 }
@@ -1,114 +1,767 @@
-use crate::debruijn::DBExpr;
+use wasm_bindgen::prelude::*;
 
-impl DBExpr {
-	pub fn eval(&self) -> Box<DBExpr> {
-		let mut ee = Box::new(self.clone());
-		loop {
-			match try_beta_reduce(&ee) {
-				Some(eee) => ee = eee,
-				None => break,
+use crate::debruijn::{DBExpr, FastMap, Term};
+use crate::divergence::DivergenceDetector;
+use crate::error::Error;
+use crate::machine;
+use crate::nbe;
+use crate::optimal;
+
+/// The order in which redexes are contracted. Each strategy also determines
+/// the kind of normal form at which evaluation stops.
+#[wasm_bindgen]
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub enum Strategy {
+	/// Contract the leftmost-outermost redex first. Stops at a beta normal
+	/// form, and finds one whenever it exists.
+	#[default]
+	NormalOrder,
+	/// Contract the leftmost-innermost redex first, i.e., normalize the
+	/// function and the argument before substituting. Stops at a beta normal
+	/// form, but may diverge even if one exists.
+	ApplicativeOrder,
+	/// Contract the leftmost-outermost redex, but never reduce under a lambda
+	/// or inside an argument. Stops at a weak head normal form.
+	CallByName,
+	/// Reduce the argument to a value before substituting, and never reduce
+	/// under a lambda. Stops at a weak normal form.
+	CallByValue,
+	/// Reaches the same weak head normal form as call-by-name, but each
+	/// argument is evaluated at most once. Rewriting the whole term cannot
+	/// share arguments, so the substitution engine runs it on the abstract
+	/// machine with sharing instead, and it cannot be traced or evaluated a
+	/// few steps at a time (see `check_stepwise`).
+	CallByNeed,
+	/// Contract the leftmost-outermost redex, reducing under lambdas but never
+	/// inside an argument. Stops at a head normal form.
+	HeadReduction,
+	/// Contract every redex in the expression at once in each step
+	/// (Takahashi's complete development, see `DBExpr::develop`). Stops at a
+	/// beta normal form.
+	CompleteDevelopment,
+}
+
+impl Strategy {
+	pub(crate) fn reduces_under_lambda(&self) -> bool {
+		matches!(
+			self,
+			Strategy::NormalOrder | Strategy::ApplicativeOrder | Strategy::HeadReduction
+		)
+	}
+
+	pub(crate) fn reduces_arguments(&self) -> bool {
+		matches!(
+			self,
+			Strategy::NormalOrder | Strategy::ApplicativeOrder | Strategy::CallByValue
+		)
+	}
+
+	/// Whether the function and argument are reduced before the redex itself.
+	fn is_innermost(&self) -> bool {
+		matches!(self, Strategy::ApplicativeOrder | Strategy::CallByValue)
+	}
+}
+
+/// The method used to evaluate expressions. All engines give the same result
+/// for a given strategy.
+#[wasm_bindgen]
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub enum Engine {
+	/// Rewrite the whole expression after each beta reduction.
+	#[default]
+	Substitution,
+	/// Use an environment-based abstract machine (see `machine::eval`).
+	/// Arguments are only shared between their uses for call-by-need.
+	Machine,
+	/// Use the same abstract machine, but always share arguments between their
+	/// uses, so that each argument is evaluated at most once.
+	Graph,
+	/// Use normalization by evaluation (see `nbe::eval`). Only supports
+	/// normal order.
+	Nbe,
+	/// Use optimal reduction (see `optimal::eval`). Only supports normal
+	/// order. This engine is experimental.
+	Optimal,
+}
+
+/// A step from a node of an expression to one of its children.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum Direction {
+	/// From a function abstraction to its body.
+	Body,
+	/// From a function application to the function.
+	Function,
+	/// From a function application to the argument.
+	Argument,
+}
+
+/// One step of a reduction sequence.
+#[derive(Clone, Debug, PartialEq)]
+pub struct TraceStep {
+	pub expr: Term,
+	/// Position of the redex contracted to get to the next step, or `None` if
+	/// this is the last step. With complete development, which contracts all
+	/// redexes at once, this is always the empty path.
+	pub redex: Option<Vec<Direction>>,
+}
+
+/// Statistics about the cost of an evaluation (see `DBExpr::eval_with_stats`).
+#[wasm_bindgen]
+#[derive(Clone, Copy, Debug, Default, PartialEq)]
+pub struct EvalStats {
+	/// Number of beta reductions performed.
+	pub beta_reductions: usize,
+	/// Number of variable occurrences replaced by an argument.
+	pub substitutions: usize,
+	/// Largest number of nodes (variables, functions and applications) in the
+	/// expression at any step, including the original expression.
+	pub peak_size: usize,
+	/// Number of nodes in the normal form.
+	pub final_size: usize,
+	/// Largest nesting depth of the expression at any step.
+	pub max_depth: usize,
+	/// Number of interactions (i.e., graph rewrites) performed by the optimal
+	/// reduction engine (see `optimal::eval_with_stats`). Zero for the other
+	/// engines.
+	pub interactions: usize,
+	/// Time taken, in milliseconds.
+	pub time_ms: f64,
+}
+
+impl EvalStats {
+	/// Records an expression visited during evaluation.
+	pub(crate) fn record(&mut self, e: &Term) {
+		self.peak_size = self.peak_size.max(e.size());
+		self.max_depth = self.max_depth.max(e.depth());
+	}
+
+	/// Records the contraction of the redex at `path` in `before`, which gave
+	/// `after`.
+	pub(crate) fn record_step(&mut self, before: &Term, path: &[Direction], after: &Term) {
+		self.beta_reductions += 1;
+		if let DBExpr::App(f, _) = subterm(before, path).as_ref() {
+			if let DBExpr::Fun(body) = f.as_ref() {
+				self.substitutions += count_occurrences(body, 0);
 			}
 		}
+		self.record(after);
+	}
+}
+
+#[cfg(target_arch = "wasm32")]
+#[wasm_bindgen]
+extern "C" {
+	#[wasm_bindgen(js_namespace = performance)]
+	fn now() -> f64;
+}
+
+/// Measures elapsed time. `std::time::Instant` is not available in the
+/// browser, so `performance.now()` is used there instead.
+pub(crate) struct Timer {
+	#[cfg(target_arch = "wasm32")]
+	start: f64,
+	#[cfg(not(target_arch = "wasm32"))]
+	start: std::time::Instant,
+}
+
+impl Timer {
+	#[cfg(target_arch = "wasm32")]
+	pub(crate) fn start() -> Self {
+		Timer { start: now() }
+	}
+
+	#[cfg(not(target_arch = "wasm32"))]
+	pub(crate) fn start() -> Self {
+		Timer {
+			start: std::time::Instant::now(),
+		}
+	}
+
+	#[cfg(target_arch = "wasm32")]
+	pub(crate) fn elapsed_ms(&self) -> f64 {
+		now() - self.start
+	}
+
+	#[cfg(not(target_arch = "wasm32"))]
+	pub(crate) fn elapsed_ms(&self) -> f64 {
+		self.start.elapsed().as_secs_f64() * 1000.0
+	}
+}
+
+impl DBExpr {
+	pub fn eval(&self) -> Term {
+		let mut ee = Term::new(self.clone());
+		let mut path = Vec::new();
+		while let Some(eee) = try_beta_reduce(&ee, Strategy::NormalOrder, &mut path) {
+			ee = eee;
+			path.clear();
+		}
 		ee
 	}
+
+	/// Like `eval`, but gives up after `max_steps` beta reductions.
+	/// In that case, the error contains the partially reduced expression.
+	pub fn eval_with_limit(&self, max_steps: usize) -> Result<Term, Error> {
+		self.eval_with_strategy(Strategy::NormalOrder, Some(max_steps))
+	}
+
+	/// Reduces the expression using the given strategy until it reaches the
+	/// corresponding normal form. If `max_steps` is given, evaluation stops
+	/// with an error after that many beta reductions. Evaluation also stops
+	/// early with `Error::NonTermination` if the expression is found to loop
+	/// forever (see `DivergenceDetector`). Call-by-need is evaluated by the
+	/// abstract machine with sharing, without that check.
+	pub fn eval_with_strategy(
+		&self,
+		strategy: Strategy,
+		max_steps: Option<usize>,
+	) -> Result<Term, Error> {
+		if strategy == Strategy::CallByNeed {
+			return machine::eval(self, strategy, max_steps, true);
+		}
+		self.reduce(strategy, max_steps, None)
+	}
+
+	/// Like `eval_with_strategy`, but also measures the cost of evaluation.
+	/// Does not support call-by-need.
+	pub fn eval_with_stats(
+		&self,
+		strategy: Strategy,
+		max_steps: Option<usize>,
+	) -> Result<(Term, EvalStats), Error> {
+		check_stepwise(strategy)?;
+		let timer = Timer::start();
+		let mut stats = EvalStats::default();
+		let nf = self.reduce(strategy, max_steps, Some(&mut stats))?;
+		stats.time_ms = timer.elapsed_ms();
+		Ok((nf, stats))
+	}
+
+	fn reduce(
+		&self,
+		strategy: Strategy,
+		max_steps: Option<usize>,
+		mut stats: Option<&mut EvalStats>,
+	) -> Result<Term, Error> {
+		let mut ee = Term::new(self.clone());
+		let mut steps = 0;
+		let mut path = Vec::new();
+		let mut detector = DivergenceDetector::new(&ee, strategy);
+		if let Some(stats) = stats.as_deref_mut() {
+			stats.record(&ee);
+		}
+		while let Some(eee) = try_beta_reduce(&ee, strategy, &mut path) {
+			if Some(steps) == max_steps {
+				return Err(Error::StepLimitExceeded(steps, ee));
+			}
+			detector.check(&ee, &path)?;
+			if let Some(stats) = stats.as_deref_mut() {
+				stats.record_step(&ee, &path, &eee);
+			}
+			ee = eee;
+			steps += 1;
+			path.clear();
+		}
+		if let Some(stats) = stats {
+			stats.final_size = ee.size();
+		}
+		Ok(ee)
+	}
+
+	/// Like `eval_with_strategy`, but allows choosing the evaluation engine.
+	pub fn eval_with_engine(
+		&self,
+		engine: Engine,
+		strategy: Strategy,
+		max_steps: Option<usize>,
+	) -> Result<Term, Error> {
+		match engine {
+			Engine::Substitution => self.eval_with_strategy(strategy, max_steps),
+			Engine::Machine => {
+				machine::eval(self, strategy, max_steps, strategy == Strategy::CallByNeed)
+			}
+			Engine::Graph => machine::eval(self, strategy, max_steps, true),
+			Engine::Nbe => nbe::eval(self, strategy, max_steps),
+			Engine::Optimal => optimal::eval(self, strategy, max_steps),
+		}
+	}
+
+	/// Like `eval_with_strategy`, but returns every intermediate expression
+	/// along with the position of the redex contracted at each step.
+	/// The first step is the original expression and the last step is the
+	/// normal form. Does not support call-by-need.
+	pub fn trace(
+		&self,
+		strategy: Strategy,
+		max_steps: Option<usize>,
+	) -> Result<Vec<TraceStep>, Error> {
+		check_stepwise(strategy)?;
+		let mut trace = Vec::new();
+		let mut ee = Term::new(self.clone());
+		let mut path = Vec::new();
+		let mut detector = DivergenceDetector::new(&ee, strategy);
+		while let Some(eee) = try_beta_reduce(&ee, strategy, &mut path) {
+			if Some(trace.len()) == max_steps {
+				return Err(Error::StepLimitExceeded(trace.len(), ee));
+			}
+			detector.check(&ee, &path)?;
+			trace.push(TraceStep {
+				expr: ee,
+				redex: Some(path.clone()),
+			});
+			ee = eee;
+			path.clear();
+		}
+		trace.push(TraceStep {
+			expr: ee,
+			redex: None,
+		});
+		Ok(trace)
+	}
 }
 
-/// Attempts to apply a single beta reduction to the given expression.
+/// Checks that the strategy can be carried out by rewriting the whole
+/// expression one step at a time. Call-by-need cannot, since it shares
+/// arguments between their uses.
+pub(crate) fn check_stepwise(strategy: Strategy) -> Result<(), Error> {
+	match strategy {
+		Strategy::CallByNeed => Err(Error::UnsupportedStrategy(
+			"call-by-need cannot be run one step at a time, since it shares arguments".to_owned(),
+		)),
+		_ => Ok(()),
+	}
+}
+
+/// Attempts to apply a single beta reduction to the given expression, choosing
+/// the redex according to the given strategy.
 /// Returns `None` if no reduction is possible.
-/// Otherwise, returns the reduced expression.
-fn try_beta_reduce(e: &DBExpr) -> Option<Box<DBExpr>> {
-	match e {
-		DBExpr::Var(_) => None,
-		DBExpr::Fun(body) => match try_beta_reduce(body) {
-			Some(nb) => Some(Box::new(DBExpr::Fun(nb))),
-			None => None,
-		},
-		DBExpr::App(f, arg) => {
-			match f.as_ref() {
-				DBExpr::Fun(body) => return Some(Box::new(replace(body, arg, 0))),
-				_ => (),
+/// Otherwise, returns the reduced expression and appends the position of the
+/// contracted redex to `path`.
+pub(crate) fn try_beta_reduce(
+	e: &Term,
+	strategy: Strategy,
+	path: &mut Vec<Direction>,
+) -> Option<Term> {
+	if strategy == Strategy::CompleteDevelopment {
+		return e.has_redex().then(|| develop(e));
+	}
+	// The subterms along the path being searched, each with the number of its
+	// children that have been searched so far. An explicit stack is used so
+	// that deep terms cannot overflow the call stack.
+	let base = path.len();
+	let mut stack = vec![(e, 0)];
+	loop {
+		let top = stack.len() - 1;
+		let (t, searched) = stack[top];
+		let next = match (t.as_ref(), searched) {
+			_ if !t.has_redex() => None,
+			(DBExpr::Fun(body), 0) if strategy.reduces_under_lambda() => {
+				Some((Direction::Body, body))
+			}
+			(DBExpr::App(f, _), 0) => match f.as_ref() {
+				DBExpr::Fun(_) if !strategy.is_innermost() => break,
+				_ => Some((Direction::Function, f)),
+			},
+			(DBExpr::App(_, a), 1) if strategy.reduces_arguments() => {
+				Some((Direction::Argument, a))
 			}
-			match try_beta_reduce(f) {
-				Some(nf) => return Some(Box::new(DBExpr::App(nf, arg.clone()))),
-				None => (),
+			// For innermost strategies, the function and the argument have
+			// already been searched
+			(DBExpr::App(f, _), _) if matches!(**f, DBExpr::Fun(_)) => break,
+			_ => None,
+		};
+		match next {
+			Some((d, child)) => {
+				stack[top].1 += 1;
+				path.push(d);
+				stack.push((child, 0));
 			}
-			match try_beta_reduce(arg) {
-				Some(na) => Some(Box::new(DBExpr::App(f.clone(), na))),
-				None => None,
+			None => {
+				stack.pop();
+				if stack.is_empty() {
+					return None;
+				}
+				path.pop();
 			}
 		}
 	}
-}
 
-fn replace(e: &DBExpr, arg: &DBExpr, depth: usize) -> DBExpr {
-	match e {
-		DBExpr::Var(i) if *i == depth =>
-		// This variable is bound by the lambda we're getting rid of
-		{
-			increment_free_vars(arg, depth, 0)
+	let (redex, _) = stack.pop().unwrap();
+	let mut result = match redex.as_ref() {
+		DBExpr::App(f, arg) => match f.as_ref() {
+			DBExpr::Fun(body) => replace(body, arg, 0),
+			_ => panic!("Expected function"),
 		},
-		DBExpr::Var(i) if *i > depth =>
-		// This variable is bound by a lambda outside the one we're getting
-		// rid of
-		{
-			DBExpr::Var(*i - 1)
+		_ => panic!("Expected function application"),
+	};
+	// Put the result back in place of the redex
+	for (i, (parent, _)) in stack.iter().enumerate().rev() {
+		result = match (path[base + i], parent.as_ref()) {
+			(Direction::Body, DBExpr::Fun(_)) => Term::new(DBExpr::Fun(result)),
+			(Direction::Function, DBExpr::App(_, a)) => Term::new(DBExpr::App(result, a.clone())),
+			(Direction::Argument, DBExpr::App(f, _)) => Term::new(DBExpr::App(f.clone(), result)),
+			_ => panic!("Invalid path"),
+		};
+	}
+	Some(result)
+}
+
+/// Contracts every redex present in `e` at once. Redexes created by the
+/// contractions themselves are left for the next step.
+fn develop(e: &Term) -> Term {
+	enum Work<'e> {
+		Visit(&'e Term),
+		Build(&'e Term),
+	}
+	// Developing a subterm does not depend on its context, so shared subterms
+	// only need to be developed once
+	let mut cache: FastMap<usize, Term> = FastMap::default();
+	let mut work = vec![Work::Visit(e)];
+	let mut results = Vec::new();
+	while let Some(w) = work.pop() {
+		match w {
+			Work::Visit(t) => {
+				if !t.has_redex() {
+					results.push(t.clone());
+					continue;
+				}
+				if let Some(r) = cache.get(&t.id()) {
+					results.push(r.clone());
+					continue;
+				}
+				work.push(Work::Build(t));
+				match t.as_ref() {
+					DBExpr::Fun(body) => work.push(Work::Visit(body)),
+					DBExpr::App(f, a) => {
+						work.push(Work::Visit(a));
+						match f.as_ref() {
+							DBExpr::Fun(body) => work.push(Work::Visit(body)),
+							_ => work.push(Work::Visit(f)),
+						}
+					}
+					DBExpr::Var(_) => unreachable!("variables have no redexes"),
+				}
+			}
+			Work::Build(t) => {
+				let r = match t.as_ref() {
+					DBExpr::App(f, _) => {
+						let a = results.pop().unwrap();
+						let g = results.pop().unwrap();
+						match f.as_ref() {
+							DBExpr::Fun(_) => replace(&g, &a, 0),
+							_ => Term::new(DBExpr::App(g, a)),
+						}
+					}
+					_ => Term::new(DBExpr::Fun(results.pop().unwrap())),
+				};
+				if t.is_shared() {
+					cache.insert(t.id(), r.clone());
+				}
+				results.push(r);
+			}
 		}
-		DBExpr::Var(i) =>
-		// This variable is bound by a lambda inside the one we're getting
-		// rid of
-		{
-			DBExpr::Var(*i)
+	}
+	results.pop().unwrap()
+}
+
+impl DBExpr {
+	/// Contracts all the redexes in the expression at once, giving its
+	/// complete development. For example, `(\x.x x) ((\y.y) z)` becomes
+	/// `z z` in a single step, whereas normal order takes three.
+	pub fn develop(&self) -> Term {
+		develop(&Term::new(self.clone()))
+	}
+
+	/// Applies eta reduction (`\x.f x` to `f`, if `x` is not free in `f`)
+	/// everywhere in the expression. Applied to a beta normal form, this gives
+	/// the beta-eta normal form.
+	pub fn eta_reduce(&self) -> Term {
+		let var0 = Term::new(DBExpr::Var(0));
+		rebuild(
+			&Term::new(self.clone()),
+			0,
+			|_, _| None,
+			|e| match &e {
+				DBExpr::Fun(nb) => match nb.as_ref() {
+					DBExpr::App(f, a) if *a == var0 && !has_free_var(f, 0) => {
+						// Since the variable being replaced does not appear in
+						// f, this just renumbers the other free variables
+						replace(f, &var0, 0)
+					}
+					_ => Term::new(e),
+				},
+				_ => Term::new(e),
+			},
+			&mut Cache::default(),
+		)
+	}
+
+	/// Applies eta expansion (`f` to `\x.f x`) at the head of the expression
+	/// until it starts with at least `n` lambdas. For example, `\s.s` with
+	/// `n = 2` becomes `\s.\z.s z`. Subterms are left alone, since their
+	/// expected number of lambdas depends on their type; see
+	/// `interpret_as::eta_expand_as` for the eta-long form of a data type.
+	pub fn eta_expand_head(&self, n: usize) -> Term {
+		let e = Term::new(self.clone());
+		let mut k = 0;
+		let mut body = &e;
+		while let DBExpr::Fun(b) = body.as_ref() {
+			k += 1;
+			body = b;
+		}
+		if k >= n {
+			return e;
+		}
+		let m = n - k;
+		let mut e = increment_free_vars(body, m, 0);
+		for i in (0..m).rev() {
+			e = Term::new(DBExpr::App(e, Term::new(DBExpr::Var(i))));
+		}
+		for _ in 0..n {
+			e = Term::new(DBExpr::Fun(e));
+		}
+		e
+	}
+}
+
+/// Returns the subterm of `e` at the given position.
+pub(crate) fn subterm<'e>(e: &'e Term, path: &[Direction]) -> &'e Term {
+	let mut sub = e;
+	for d in path {
+		sub = match (d, sub.as_ref()) {
+			(Direction::Body, DBExpr::Fun(body)) => body,
+			(Direction::Function, DBExpr::App(f, _)) => f,
+			(Direction::Argument, DBExpr::App(_, a)) => a,
+			_ => panic!("Invalid path"),
+		};
+	}
+	sub
+}
+
+/// Counts the occurrences of the variable with index `i` in `e`.
+fn count_occurrences(e: &Term, i: usize) -> usize {
+	let mut count = 0;
+	let mut stack = vec![(e, i)];
+	while let Some((t, i)) = stack.pop() {
+		if t.free_vars() <= i {
+			continue;
+		}
+		match t.as_ref() {
+			DBExpr::Var(j) => count += (*j == i) as usize,
+			DBExpr::Fun(body) => stack.push((body, i + 1)),
+			DBExpr::App(f, a) => stack.extend([(a, i), (f, i)]),
 		}
-		DBExpr::Fun(body) => DBExpr::Fun(Box::new(replace(body, arg, depth + 1))),
-		DBExpr::App(f, a) => DBExpr::App(
-			Box::new(replace(f, arg, depth)),
-			Box::new(replace(a, arg, depth)),
-		),
 	}
+	count
+}
+
+/// Checks whether the variable with index `i` appears free in `e`.
+fn has_free_var(e: &Term, i: usize) -> bool {
+	let mut stack = vec![(e, i)];
+	while let Some((t, i)) = stack.pop() {
+		if t.free_vars() <= i {
+			continue;
+		}
+		match t.as_ref() {
+			DBExpr::Var(j) if *j == i => return true,
+			DBExpr::Var(_) => {}
+			DBExpr::Fun(body) => stack.push((body, i + 1)),
+			DBExpr::App(f, a) => stack.extend([(a, i), (f, i)]),
+		}
+	}
+	false
+}
+
+/// Results of `rebuild` for each subterm and depth.
+type Cache = FastMap<(usize, usize), Term>;
+
+/// Rebuilds `e` from the bottom up. `leaf` is called on each subterm along
+/// with its depth (i.e., `depth` plus the number of lambdas between `e` and
+/// the subterm), and returns the result for that subterm if it should not be
+/// rebuilt from its children. Otherwise, `build` is called on the subterm with
+/// its children replaced.
+///
+/// An explicit stack is used so that deep terms cannot overflow the call
+/// stack, and results are cached so that shared subterms are only rebuilt
+/// once.
+fn rebuild(
+	e: &Term,
+	depth: usize,
+	mut leaf: impl FnMut(&Term, usize) -> Option<Term>,
+	mut build: impl FnMut(DBExpr) -> Term,
+	cache: &mut Cache,
+) -> Term {
+	enum Work<'e> {
+		Visit(&'e Term, usize),
+		Build(&'e Term, usize),
+	}
+	let mut work = vec![Work::Visit(e, depth)];
+	let mut results = Vec::new();
+	while let Some(w) = work.pop() {
+		match w {
+			Work::Visit(t, d) => {
+				let cached = || match t.is_shared() {
+					true => cache.get(&(t.id(), d)).cloned(),
+					false => None,
+				};
+				if let Some(r) = leaf(t, d).or_else(cached) {
+					results.push(r);
+					continue;
+				}
+				match t.as_ref() {
+					DBExpr::Var(_) => results.push(build(t.as_ref().clone())),
+					DBExpr::Fun(body) => {
+						work.push(Work::Build(t, d));
+						work.push(Work::Visit(body, d + 1));
+					}
+					DBExpr::App(f, a) => {
+						work.push(Work::Build(t, d));
+						work.push(Work::Visit(a, d));
+						work.push(Work::Visit(f, d));
+					}
+				}
+			}
+			Work::Build(t, d) => {
+				let r = match t.as_ref() {
+					DBExpr::App(_, _) => {
+						let a = results.pop().unwrap();
+						let f = results.pop().unwrap();
+						build(DBExpr::App(f, a))
+					}
+					_ => build(DBExpr::Fun(results.pop().unwrap())),
+				};
+				// A subterm that is not shared can only be visited once
+				if t.is_shared() {
+					cache.insert((t.id(), d), r.clone());
+				}
+				results.push(r);
+			}
+		}
+	}
+	results.pop().unwrap()
+}
+
+fn replace(e: &Term, arg: &Term, depth: usize) -> Term {
+	// The argument with its free variables incremented by each depth
+	let mut args = FastMap::default();
+	rebuild(
+		e,
+		depth,
+		|t, d| {
+			if t.free_vars() <= d {
+				// All the variables are bound by a lambda inside the one we're
+				// getting rid of
+				return Some(t.clone());
+			}
+			match t.as_ref() {
+				// This variable is bound by the lambda we're getting rid of
+				DBExpr::Var(i) if *i == d => Some(
+					args.entry(d)
+						.or_insert_with(|| increment_free_vars(arg, d, 0))
+						.clone(),
+				),
+				// This variable is bound by a lambda outside the one we're
+				// getting rid of
+				DBExpr::Var(i) => Some(Term::new(DBExpr::Var(*i - 1))),
+				_ => None,
+			}
+		},
+		Term::new,
+		&mut Cache::default(),
+	)
 }
 
-fn increment_free_vars(e: &DBExpr, n: usize, depth: usize) -> DBExpr {
-	match e {
-		DBExpr::Var(i) if *i >= depth => DBExpr::Var(*i + n),
-		DBExpr::Var(i) => DBExpr::Var(*i),
-		DBExpr::Fun(body) => DBExpr::Fun(Box::new(increment_free_vars(body, n, depth + 1))),
-		DBExpr::App(f, a) => DBExpr::App(
-			Box::new(increment_free_vars(f, n, depth)),
-			Box::new(increment_free_vars(a, n, depth)),
-		),
+fn increment_free_vars(e: &Term, n: usize, depth: usize) -> Term {
+	if n == 0 {
+		return e.clone();
 	}
+	rebuild(
+		e,
+		depth,
+		|t, d| match t.as_ref() {
+			_ if t.free_vars() <= d => Some(t.clone()),
+			DBExpr::Var(i) => Some(Term::new(DBExpr::Var(*i + n))),
+			_ => None,
+		},
+		Term::new,
+		&mut Cache::default(),
+	)
 }
 
 #[cfg(test)]
 mod eval_tests {
-	use crate::debruijn::DBExpr;
+	use crate::debruijn::{DBExpr, Term};
+	use crate::error::Error;
+	use crate::eval::{Direction, Strategy, TraceStep};
+	use crate::lex;
+	use crate::parse;
+
+	fn parse(e: &str) -> Term {
+		let mut stream = lex::lex(e).unwrap();
+		parse::parse(&mut stream).unwrap().to_debruijn()
+	}
+
+	#[test]
+	fn eval_deep_numeral() -> () {
+		// 2^17, computed by doubling 1 seventeen times. The result is too deep
+		// to be evaluated or printed recursively.
+		let e = parse(&format!(
+			"{}1{} where D = \\n.\\s.\\z.n s (n s z) where 1 = \\s.\\z.s z",
+			"D (".repeat(17),
+			")".repeat(17)
+		));
+		let nf = e
+			.eval_with_strategy(Strategy::ApplicativeOrder, None)
+			.unwrap();
+		let expected = format!(
+			"\\a.\\b.{}a b{}",
+			"a (".repeat(131_071),
+			")".repeat(131_071)
+		);
+		assert_eq!(expected, nf.to_named().to_string());
+	}
+
+	#[test]
+	fn eval_shared_subterms() -> () {
+		// \z.(\x.x x ... x) z, with 2^64 occurrences of x stored as 64 nested
+		// applications of a shared subterm
+		let mut body = Term::new(DBExpr::Var(0));
+		for _ in 0..64 {
+			body = Term::new(DBExpr::App(body.clone(), body));
+		}
+		let e = Term::new(DBExpr::Fun(Term::new(DBExpr::App(
+			Term::new(DBExpr::Fun(body.clone())),
+			Term::new(DBExpr::Var(0)),
+		))));
+		assert_eq!(Term::new(DBExpr::Fun(body)), e.eval());
+	}
 
 	#[test]
 	fn eval_identity() -> () {
-		let id = Box::new(DBExpr::Fun(Box::new(DBExpr::Var(0))));
+		let id = Term::new(DBExpr::Fun(Term::new(DBExpr::Var(0))));
 		assert_eq!(id, id.eval());
 	}
 
 	#[test]
 	fn eval_app_inside_fun() -> () {
 		// \(\1 (\0) 0)(\0)
-		let e = Box::new(DBExpr::Fun(Box::new(DBExpr::App(
-			Box::new(DBExpr::Fun(Box::new(DBExpr::App(
-				Box::new(DBExpr::App(
-					Box::new(DBExpr::Var(1)),
-					Box::new(DBExpr::Fun(Box::new(DBExpr::Var(0)))),
+		let e = Term::new(DBExpr::Fun(Term::new(DBExpr::App(
+			Term::new(DBExpr::Fun(Term::new(DBExpr::App(
+				Term::new(DBExpr::App(
+					Term::new(DBExpr::Var(1)),
+					Term::new(DBExpr::Fun(Term::new(DBExpr::Var(0)))),
 				)),
-				Box::new(DBExpr::Var(0)),
+				Term::new(DBExpr::Var(0)),
 			)))),
-			Box::new(DBExpr::Fun(Box::new(DBExpr::Var(0)))),
+			Term::new(DBExpr::Fun(Term::new(DBExpr::Var(0)))),
 		))));
 		// \0 (\0) (\0)
-		let expected = Box::new(DBExpr::Fun(Box::new(DBExpr::App(
-			Box::new(DBExpr::App(
-				Box::new(DBExpr::Var(0)),
-				Box::new(DBExpr::Fun(Box::new(DBExpr::Var(0)))),
+		let expected = Term::new(DBExpr::Fun(Term::new(DBExpr::App(
+			Term::new(DBExpr::App(
+				Term::new(DBExpr::Var(0)),
+				Term::new(DBExpr::Fun(Term::new(DBExpr::Var(0)))),
 			)),
-			Box::new(DBExpr::Fun(Box::new(DBExpr::Var(0)))),
+			Term::new(DBExpr::Fun(Term::new(DBExpr::Var(0)))),
 		))));
 		assert_eq!(expected, e.eval());
 	}
@@ -116,25 +769,27 @@ mod eval_tests {
 	#[test]
 	fn eval_succ_0() -> () {
 		// \\0
-		let zero = Box::new(DBExpr::Fun(Box::new(DBExpr::Fun(Box::new(DBExpr::Var(0))))));
+		let zero = Term::new(DBExpr::Fun(Term::new(DBExpr::Fun(Term::new(DBExpr::Var(
+			0,
+		))))));
 		// \\\1(2 1 0)
-		let succ = Box::new(DBExpr::Fun(Box::new(DBExpr::Fun(Box::new(DBExpr::Fun(
-			Box::new(DBExpr::App(
-				Box::new(DBExpr::Var(1)),
-				Box::new(DBExpr::App(
-					Box::new(DBExpr::App(
-						Box::new(DBExpr::Var(2)),
-						Box::new(DBExpr::Var(1)),
+		let succ = Term::new(DBExpr::Fun(Term::new(DBExpr::Fun(Term::new(DBExpr::Fun(
+			Term::new(DBExpr::App(
+				Term::new(DBExpr::Var(1)),
+				Term::new(DBExpr::App(
+					Term::new(DBExpr::App(
+						Term::new(DBExpr::Var(2)),
+						Term::new(DBExpr::Var(1)),
 					)),
-					Box::new(DBExpr::Var(0)),
+					Term::new(DBExpr::Var(0)),
 				)),
 			)),
 		))))));
-		let f = Box::new(DBExpr::App(succ, zero));
+		let f = Term::new(DBExpr::App(succ, zero));
 		// \\1(0)
-		let one = Box::new(DBExpr::Fun(Box::new(DBExpr::Fun(Box::new(DBExpr::App(
-			Box::new(DBExpr::Var(1)),
-			Box::new(DBExpr::Var(0)),
+		let one = Term::new(DBExpr::Fun(Term::new(DBExpr::Fun(Term::new(DBExpr::App(
+			Term::new(DBExpr::Var(1)),
+			Term::new(DBExpr::Var(0)),
 		))))));
 		assert_eq!(one, f.eval());
 	}
@@ -142,32 +797,398 @@ mod eval_tests {
 	#[test]
 	fn eval_succ_1() -> () {
 		// \\1(0)
-		let one = Box::new(DBExpr::Fun(Box::new(DBExpr::Fun(Box::new(DBExpr::App(
-			Box::new(DBExpr::Var(1)),
-			Box::new(DBExpr::Var(0)),
+		let one = Term::new(DBExpr::Fun(Term::new(DBExpr::Fun(Term::new(DBExpr::App(
+			Term::new(DBExpr::Var(1)),
+			Term::new(DBExpr::Var(0)),
 		))))));
 		// \\\1(2 1 0)
-		let succ = Box::new(DBExpr::Fun(Box::new(DBExpr::Fun(Box::new(DBExpr::Fun(
-			Box::new(DBExpr::App(
-				Box::new(DBExpr::Var(1)),
-				Box::new(DBExpr::App(
-					Box::new(DBExpr::App(
-						Box::new(DBExpr::Var(2)),
-						Box::new(DBExpr::Var(1)),
+		let succ = Term::new(DBExpr::Fun(Term::new(DBExpr::Fun(Term::new(DBExpr::Fun(
+			Term::new(DBExpr::App(
+				Term::new(DBExpr::Var(1)),
+				Term::new(DBExpr::App(
+					Term::new(DBExpr::App(
+						Term::new(DBExpr::Var(2)),
+						Term::new(DBExpr::Var(1)),
 					)),
-					Box::new(DBExpr::Var(0)),
+					Term::new(DBExpr::Var(0)),
 				)),
 			)),
 		))))));
-		let f = Box::new(DBExpr::App(succ, one));
+		let f = Term::new(DBExpr::App(succ, one));
 		// \\1(1(0))
-		let one = Box::new(DBExpr::Fun(Box::new(DBExpr::Fun(Box::new(DBExpr::App(
-			Box::new(DBExpr::Var(1)),
-			Box::new(DBExpr::App(
-				Box::new(DBExpr::Var(1)),
-				Box::new(DBExpr::Var(0)),
+		let one = Term::new(DBExpr::Fun(Term::new(DBExpr::Fun(Term::new(DBExpr::App(
+			Term::new(DBExpr::Var(1)),
+			Term::new(DBExpr::App(
+				Term::new(DBExpr::Var(1)),
+				Term::new(DBExpr::Var(0)),
 			)),
 		))))));
 		assert_eq!(one, f.eval());
 	}
+
+	#[test]
+	fn eval_with_limit_normal_form() -> () {
+		// (\0) (\0)
+		let e = Term::new(DBExpr::App(
+			Term::new(DBExpr::Fun(Term::new(DBExpr::Var(0)))),
+			Term::new(DBExpr::Fun(Term::new(DBExpr::Var(0)))),
+		));
+		let id = Term::new(DBExpr::Fun(Term::new(DBExpr::Var(0))));
+		assert_eq!(Ok(id.clone()), e.eval_with_limit(1));
+		assert_eq!(Ok(id.clone()), e.eval_with_limit(100));
+		assert_eq!(Ok(id.clone()), id.eval_with_limit(0));
+	}
+
+	#[test]
+	fn eval_with_limit_partial_result() -> () {
+		// (\0) ((\0) (\0))
+		let id = Term::new(DBExpr::Fun(Term::new(DBExpr::Var(0))));
+		let e = Term::new(DBExpr::App(
+			id.clone(),
+			Term::new(DBExpr::App(id.clone(), id.clone())),
+		));
+		assert_eq!(
+			Err(Error::StepLimitExceeded(0, e.clone())),
+			e.eval_with_limit(0)
+		);
+		assert_eq!(
+			Err(Error::StepLimitExceeded(
+				1,
+				Term::new(DBExpr::App(id.clone(), id.clone()))
+			)),
+			e.eval_with_limit(1)
+		);
+		assert_eq!(Ok(id), e.eval_with_limit(2));
+	}
+
+	#[test]
+	fn eval_with_limit_omega() -> () {
+		// (\0 0) (\0 0)
+		let w = Term::new(DBExpr::Fun(Term::new(DBExpr::App(
+			Term::new(DBExpr::Var(0)),
+			Term::new(DBExpr::Var(0)),
+		))));
+		let omega = Term::new(DBExpr::App(w.clone(), w));
+		assert_eq!(
+			Err(Error::NonTermination(
+				"term repeats after 1 step".to_owned()
+			)),
+			omega.eval_with_limit(1000)
+		);
+	}
+
+	#[test]
+	fn eval_with_limit_growing() -> () {
+		let e = parse("(\\x.x x x) (\\x.x x x)");
+		assert!(matches!(
+			e.eval_with_limit(100),
+			Err(Error::StepLimitExceeded(100, _))
+		));
+	}
+
+	#[test]
+	fn eval_call_by_need_shares() -> () {
+		let e = parse("(\\x.x x) ((\\y.y) (\\z.z))");
+		assert_eq!(
+			Ok(parse("\\z.z")),
+			e.eval_with_strategy(Strategy::CallByNeed, Some(3))
+		);
+		assert!(e.eval_with_strategy(Strategy::CallByName, Some(3)).is_err());
+		assert!(matches!(
+			e.trace(Strategy::CallByNeed, None),
+			Err(Error::UnsupportedStrategy(_))
+		));
+		assert!(matches!(
+			e.eval_with_stats(Strategy::CallByNeed, None),
+			Err(Error::UnsupportedStrategy(_))
+		));
+	}
+
+	#[test]
+	fn eval_strategies_discarded_omega() -> () {
+		let e = parse("(\\x.\\y.y) ((\\x.x x) (\\x.x x))");
+		let id = parse("\\y.y");
+		for s in [
+			Strategy::NormalOrder,
+			Strategy::CallByName,
+			Strategy::CallByNeed,
+			Strategy::HeadReduction,
+		] {
+			assert_eq!(Ok(id.clone()), e.eval_with_strategy(s, Some(100)));
+		}
+		for s in [Strategy::ApplicativeOrder, Strategy::CallByValue] {
+			assert!(matches!(
+				e.eval_with_strategy(s, Some(100)),
+				Err(Error::NonTermination(_))
+			));
+		}
+	}
+
+	#[test]
+	fn eval_strategies_under_lambda() -> () {
+		let e = parse("\\x.(\\y.y) x");
+		let reduced = parse("\\x.x");
+		for s in [
+			Strategy::NormalOrder,
+			Strategy::ApplicativeOrder,
+			Strategy::HeadReduction,
+		] {
+			assert_eq!(Ok(reduced.clone()), e.eval_with_strategy(s, None));
+		}
+		for s in [
+			Strategy::CallByName,
+			Strategy::CallByValue,
+			Strategy::CallByNeed,
+		] {
+			assert_eq!(Ok(e.clone()), e.eval_with_strategy(s, None));
+		}
+	}
+
+	#[test]
+	fn eval_strategies_inside_argument() -> () {
+		let e = parse("\\x.x ((\\y.y) x)");
+		let reduced = parse("\\x.x x");
+		assert_eq!(
+			Ok(e.clone()),
+			e.eval_with_strategy(Strategy::HeadReduction, None)
+		);
+		assert_eq!(
+			Ok(reduced.clone()),
+			e.eval_with_strategy(Strategy::NormalOrder, None)
+		);
+		assert_eq!(
+			Ok(reduced.clone()),
+			e.eval_with_strategy(Strategy::ApplicativeOrder, None)
+		);
+	}
+
+	#[test]
+	fn eval_strategies_argument_order() -> () {
+		// Call-by-value reduces the argument before substituting it, whereas
+		// call-by-name substitutes it unevaluated
+		let e = parse("(\\x.\\y.x) ((\\z.z) (\\z.z))");
+		assert_eq!(
+			Ok(parse("\\y.\\z.z")),
+			e.eval_with_strategy(Strategy::CallByValue, None)
+		);
+		assert_eq!(
+			Ok(parse("\\y.(\\z.z) (\\z.z)")),
+			e.eval_with_strategy(Strategy::CallByName, None)
+		);
+	}
+
+	#[test]
+	fn eval_strategies_agree_on_normal_form() -> () {
+		let e = parse("+ 2 2 where + = \\m.\\n.\\s.\\z.m s (n s z) where 2 = \\s.\\z.s (s z)");
+		let four = parse("\\s.\\z.s (s (s (s z)))");
+		for s in [Strategy::NormalOrder, Strategy::ApplicativeOrder] {
+			assert_eq!(Ok(four.clone()), e.eval_with_strategy(s, None));
+		}
+	}
+
+	#[test]
+	fn trace_normal_order() -> () {
+		let e = parse("\\x.(\\y.y) ((\\y.y) x)");
+		let expected = vec![
+			TraceStep {
+				expr: e.clone(),
+				redex: Some(vec![Direction::Body]),
+			},
+			TraceStep {
+				expr: parse("\\x.(\\y.y) x"),
+				redex: Some(vec![Direction::Body]),
+			},
+			TraceStep {
+				expr: parse("\\x.x"),
+				redex: None,
+			},
+		];
+		assert_eq!(Ok(expected), e.trace(Strategy::NormalOrder, None));
+	}
+
+	#[test]
+	fn trace_applicative_order() -> () {
+		let e = parse("\\x.(\\y.y) ((\\y.y) x)");
+		let expected = vec![
+			TraceStep {
+				expr: e.clone(),
+				redex: Some(vec![Direction::Body, Direction::Argument]),
+			},
+			TraceStep {
+				expr: parse("\\x.(\\y.y) x"),
+				redex: Some(vec![Direction::Body]),
+			},
+			TraceStep {
+				expr: parse("\\x.x"),
+				redex: None,
+			},
+		];
+		assert_eq!(Ok(expected), e.trace(Strategy::ApplicativeOrder, None));
+	}
+
+	#[test]
+	fn trace_redex_in_function() -> () {
+		let e = parse("(\\x.x) (\\x.x) (\\y.y)");
+		let trace = e.trace(Strategy::NormalOrder, None).unwrap();
+		assert_eq!(3, trace.len());
+		assert_eq!(Some(vec![Direction::Function]), trace[0].redex);
+		assert_eq!(Some(vec![]), trace[1].redex);
+		assert_eq!(parse("\\y.y"), trace[2].expr);
+	}
+
+	#[test]
+	fn develop_contracts_all_redexes() -> () {
+		let e = parse("\\z.(\\x.x x) ((\\y.y) z)");
+		assert_eq!(parse("\\z.z z"), e.develop());
+	}
+
+	#[test]
+	fn develop_leaves_created_redexes() -> () {
+		// The outer application only becomes a redex once the inner one is
+		// contracted
+		let e = parse("(\\x.x) (\\x.x) (\\y.y)");
+		assert_eq!(parse("(\\x.x) (\\y.y)"), e.develop());
+		assert_eq!(parse("\\x.x"), e.develop().develop());
+	}
+
+	#[test]
+	fn develop_normal_form() -> () {
+		let e = parse("\\x.x (\\y.y x)");
+		assert_eq!(Term::new(e.as_ref().clone()), e.develop());
+	}
+
+	#[test]
+	fn trace_complete_development() -> () {
+		let e = parse("\\x.(\\y.y) ((\\y.y) x)");
+		let expected = vec![
+			TraceStep {
+				expr: e.clone(),
+				redex: Some(vec![]),
+			},
+			TraceStep {
+				expr: parse("\\x.x"),
+				redex: None,
+			},
+		];
+		assert_eq!(Ok(expected), e.trace(Strategy::CompleteDevelopment, None));
+	}
+
+	#[test]
+	fn eval_complete_development_agrees_with_normal_order() -> () {
+		let defs = "where + = \\m.\\n.\\s.\\z.m s (n s z) where * = \\m.\\n.\\s.m (n s)
+			where 2 = \\s.\\z.s (s z) where K = \\x.\\y.x where W = (\\x.x x) (\\x.x x)";
+		for t in ["+ 2 2", "* 2 (+ 2 2)", "K (\\x.x) (+ 2 2)"] {
+			let e = parse(&format!("{t} {defs}"));
+			assert_eq!(
+				Ok(e.eval()),
+				e.eval_with_strategy(Strategy::CompleteDevelopment, None),
+				"{t}"
+			);
+		}
+		// The diverging argument is developed too, but is discarded after two
+		// steps
+		let e = parse(&format!("K (\\x.x) W {defs}"));
+		assert_eq!(
+			Ok(parse("\\x.x")),
+			e.eval_with_strategy(Strategy::CompleteDevelopment, Some(1000))
+		);
+	}
+
+	#[test]
+	fn eval_with_stats_counts() -> () {
+		// (\x.x x) (\y.y) -> (\y.y) (\y.y) -> \y.y
+		let e = parse("(\\x.x x) (\\y.y)");
+		let (nf, stats) = e.eval_with_stats(Strategy::NormalOrder, None).unwrap();
+		assert_eq!(parse("\\y.y"), nf);
+		assert_eq!(2, stats.beta_reductions);
+		assert_eq!(3, stats.substitutions);
+		assert_eq!(7, stats.peak_size);
+		assert_eq!(2, stats.final_size);
+		assert_eq!(4, stats.max_depth);
+		assert!(stats.time_ms >= 0.0);
+	}
+
+	#[test]
+	fn eval_with_stats_discarded_argument() -> () {
+		let e = parse("(\\x.\\y.y) (\\z.z z)");
+		let (_, stats) = e.eval_with_stats(Strategy::NormalOrder, None).unwrap();
+		assert_eq!(1, stats.beta_reductions);
+		assert_eq!(0, stats.substitutions);
+	}
+
+	#[test]
+	fn eval_with_stats_step_limit() -> () {
+		let e = parse("(\\x.x x x) (\\x.x x x)");
+		assert!(matches!(
+			e.eval_with_stats(Strategy::NormalOrder, Some(10)),
+			Err(Error::StepLimitExceeded(10, _))
+		));
+	}
+
+	#[test]
+	fn trace_step_limit() -> () {
+		let e = parse("(\\x.x x x) (\\x.x x x)");
+		assert!(matches!(
+			e.trace(Strategy::NormalOrder, Some(10)),
+			Err(Error::StepLimitExceeded(10, _))
+		));
+	}
+
+	#[test]
+	fn eta_reduce_simple() -> () {
+		assert_eq!(parse("\\f.f"), parse("\\f.\\x.f x").eta_reduce());
+	}
+
+	#[test]
+	fn eta_reduce_bound_var() -> () {
+		// x appears in the function, so this is not an eta redex
+		let e = parse("\\x.x x");
+		assert_eq!(e, e.eta_reduce());
+	}
+
+	#[test]
+	fn eta_reduce_nested() -> () {
+		assert_eq!(parse("\\f.f"), parse("\\f.\\x.\\y.f x y").eta_reduce());
+		assert_eq!(parse("\\s.s"), parse("\\s.\\z.s z").eta_reduce());
+		assert_eq!(
+			parse("\\a.\\b.a (\\c.b) a"),
+			parse("\\a.\\b.a (\\c.b) a").eta_reduce()
+		);
+		assert_eq!(
+			parse("\\a.\\b.b a"),
+			parse("\\a.\\b.b (\\c.a c)").eta_reduce()
+		);
+	}
+
+	#[test]
+	fn eta_expand_church() -> () {
+		assert_eq!(parse("\\s.\\z.s z"), parse("\\s.s").eta_expand_head(2));
+		assert_eq!(parse("\\s.\\z.s z"), parse("\\x.x").eta_expand_head(2));
+		assert_eq!(
+			parse("\\a.\\b.\\c.a b c"),
+			parse("\\a.a").eta_expand_head(3)
+		);
+	}
+
+	#[test]
+	fn eta_expand_already_long_enough() -> () {
+		let e = parse("\\s.\\z.s (s z)");
+		assert_eq!(e, e.eta_expand_head(0));
+		assert_eq!(e, e.eta_expand_head(1));
+		assert_eq!(e, e.eta_expand_head(2));
+	}
+
+	#[test]
+	fn eta_expand_head_leaves_subterms() -> () {
+		assert_eq!(
+			parse("\\f.\\x.f (\\s.s) x"),
+			parse("\\f.f (\\s.s)").eta_expand_head(2)
+		);
+	}
+
+	#[test]
+	fn eta_expand_then_reduce() -> () {
+		let e = parse("\\x.x (\\y.y)");
+		assert_eq!(e, e.eta_expand_head(4).eta_reduce());
+	}
 }
@@ -1,7 +1,10 @@
 use std::collections::VecDeque;
+use std::fmt::Display;
 use std::iter::Peekable;
 use std::slice::Iter;
 
+use crate::debruijn::{DBExpr, Term};
+use crate::emit::EmitOptions;
 use crate::error::Error;
 use crate::lex::{lex_type, TypeToken};
 use crate::parse::Expr;
@@ -11,10 +14,32 @@ pub enum DataType {
 	Expr,
 	Boolean,
 	ChurchNumeral,
+	/// A list of Church numerals, which are the code points of the characters.
+	String,
+	/// A Church numeral, which is the code point of the character.
+	Char,
 	Tuple(Vec<DataType>),
 	List(Box<DataType>),
 }
 
+/// Writes the type as it would appear in a type annotation.
+impl Display for DataType {
+	fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+		match self {
+			DataType::Expr => write!(f, "{}", TypeToken::Expr),
+			DataType::Boolean => write!(f, "{}", TypeToken::Bool),
+			DataType::ChurchNumeral => write!(f, "{}", TypeToken::Church),
+			DataType::String => write!(f, "{}", TypeToken::String),
+			DataType::Char => write!(f, "{}", TypeToken::Char),
+			DataType::Tuple(elem_types) => {
+				let elems: Vec<String> = elem_types.iter().map(|t| t.to_string()).collect();
+				write!(f, "{}[{}]", TypeToken::Tuple, elems.join(", "))
+			}
+			DataType::List(t) => write!(f, "{}[{t}]", TypeToken::List),
+		}
+	}
+}
+
 impl TryFrom<&str> for DataType {
 	type Error = crate::error::Error;
 
@@ -46,6 +71,8 @@ fn parse_type(tokens: &mut Peekable<Iter<TypeToken>>) -> Result<DataType, Error>
 		Some(TypeToken::Expr) => Ok(DataType::Expr),
 		Some(TypeToken::Bool) => Ok(DataType::Boolean),
 		Some(TypeToken::Church) => Ok(DataType::ChurchNumeral),
+		Some(TypeToken::String) => Ok(DataType::String),
+		Some(TypeToken::Char) => Ok(DataType::Char),
 		Some(TypeToken::Tuple) => parse_tuple_contents(tokens),
 		Some(TypeToken::List) => parse_list_contents(tokens),
 	}
@@ -124,22 +151,124 @@ fn parse_list_contents(tokens: &mut Peekable<Iter<TypeToken>>) -> Result<DataTyp
 	Ok(DataType::List(Box::new(t)))
 }
 
+/// Eta-expands the expression so that it has the binder structure expected for
+/// the given type. For example, `\s.s` becomes `\s.\z.s z` for a Church
+/// numeral. Parts of the expression that do not have the expected shape are
+/// left alone, so the result can still be rejected by `interpret_as`.
+pub fn eta_expand_as(e: &DBExpr, dt: &DataType) -> Term {
+	match dt {
+		DataType::Expr => Term::new(e.clone()),
+		DataType::Boolean | DataType::ChurchNumeral | DataType::Char => e.eta_expand_head(2),
+		DataType::String => eta_expand_as(e, &DataType::List(Box::new(DataType::Char))),
+		DataType::Tuple(elem_types) => {
+			let ee = e.eta_expand_head(1);
+			match ee.as_ref() {
+				DBExpr::Fun(body) => match eta_expand_tuple_elems(body, elem_types) {
+					Some(nb) => Term::new(DBExpr::Fun(nb)),
+					None => ee,
+				},
+				_ => ee,
+			}
+		}
+		DataType::List(t) => {
+			// The cells of the list, from the first one. Loop rather than
+			// recurse on the tail, so that long lists cannot overflow the stack
+			let mut heads = Vec::new();
+			let mut e = Term::new(e.clone());
+			let mut rest = loop {
+				if is_nil_db(&e) {
+					break e;
+				}
+				let ee = e.eta_expand_head(1);
+				let (head, tail) = match ee.as_ref() {
+					DBExpr::Fun(body) => match body.as_ref() {
+						DBExpr::App(lhs, tail) => match lhs.as_ref() {
+							DBExpr::App(p, head) if **p == DBExpr::Var(0) => (head, tail),
+							_ => break ee,
+						},
+						_ => break ee,
+					},
+					_ => break ee,
+				};
+				heads.push(eta_expand_as(head, t));
+				e = tail.clone();
+			};
+			for head in heads.into_iter().rev() {
+				rest = Term::new(DBExpr::Fun(Term::new(DBExpr::App(
+					Term::new(DBExpr::App(Term::new(DBExpr::Var(0)), head)),
+					rest,
+				))));
+			}
+			rest
+		}
+	}
+}
+
+/// Eta-expands the elements of the body of a tuple, i.e., `0 a1 ... an`.
+/// Returns `None` if the body does not have exactly the expected number of
+/// elements.
+fn eta_expand_tuple_elems(body: &DBExpr, elem_types: &[DataType]) -> Option<Term> {
+	match (elem_types.split_last(), body) {
+		(None, DBExpr::Var(0)) => Some(Term::new(DBExpr::Var(0))),
+		(Some((dt, rest)), DBExpr::App(lhs, rhs)) => Some(Term::new(DBExpr::App(
+			eta_expand_tuple_elems(lhs, rest)?,
+			eta_expand_as(rhs, dt),
+		))),
+		_ => None,
+	}
+}
+
+fn is_nil_db(e: &DBExpr) -> bool {
+	match e {
+		DBExpr::Fun(body) => match body.as_ref() {
+			DBExpr::Fun(body) => match body.as_ref() {
+				DBExpr::Fun(body) => **body == DBExpr::Var(1),
+				_ => false,
+			},
+			_ => false,
+		},
+		_ => false,
+	}
+}
+
 pub fn interpret_as(e: &Expr, dt: &DataType) -> Result<String, ()> {
+	interpret(e, dt, &EmitOptions::default())
+}
+
+/// Like `interpret_as`, but converts expressions to strings with the given
+/// options, and the error says which type was expected and, if possible, why
+/// the expression does not have it.
+pub fn interpret_as_with_options(
+	e: &Expr,
+	dt: &DataType,
+	options: &EmitOptions,
+) -> Result<String, Error> {
+	interpret(e, dt, options).map_err(|()| {
+		Error::TypeError(match explain_text_error(e, dt) {
+			Some(reason) => format!("{dt} ({reason})"),
+			None => dt.to_string(),
+		})
+	})
+}
+
+fn interpret(e: &Expr, dt: &DataType, options: &EmitOptions) -> Result<String, ()> {
 	match dt {
-		DataType::Expr => Ok(e.to_string()),
+		DataType::Expr => Ok(options.emit(e)),
 		DataType::Boolean => interpret_as_bool(e),
 		DataType::ChurchNumeral => interpret_as_church(e),
-		DataType::Tuple(elem_types) => interpret_as_tuple(e, elem_types),
-		DataType::List(t) => interpret_as_list(e, t),
+		DataType::String => decode_string(e).map(|s| format!("{s:?}")).map_err(|_| ()),
+		DataType::Char => decode_char(e).map(|c| format!("{c:?}")).ok_or(()),
+		DataType::Tuple(elem_types) => interpret_as_tuple(e, elem_types, options),
+		DataType::List(t) => interpret_as_list(e, t, options),
 	}
 }
 
 fn interpret_as_bool(e: &Expr) -> Result<String, ()> {
 	match e {
-		Expr::Fun(t, body) => match body.as_ref() {
-			Expr::Fun(f, body) => match body.as_ref() {
-				Expr::Var(c) if c == t => Ok("true".to_owned()),
-				Expr::Var(c) if c == f => Ok("false".to_owned()),
+		Expr::Fun(t, body, _) => match body.as_ref() {
+			Expr::Fun(f, body, _) => match body.as_ref() {
+				Expr::Var(c, _) if c == t => Ok("true".to_owned()),
+				Expr::Var(c, _) if c == f => Ok("false".to_owned()),
 				_ => Err(()),
 			},
 			_ => Err(()),
@@ -149,43 +278,86 @@ fn interpret_as_bool(e: &Expr) -> Result<String, ()> {
 }
 
 fn interpret_as_church(e: &Expr) -> Result<String, ()> {
+	church_value(e).map(|n| n.to_string()).ok_or(())
+}
+
+/// Returns the number that `e` encodes, if it is a Church numeral.
+fn church_value(e: &Expr) -> Option<usize> {
 	match e {
-		Expr::Fun(s, body) => match body.as_ref() {
-			Expr::Fun(z, body) => {
+		Expr::Fun(s, body, _) => match body.as_ref() {
+			Expr::Fun(z, body, _) => {
 				let mut n = 0;
 				let mut e = body;
 				loop {
 					match e.as_ref() {
-						Expr::Var(x) if x == z => break Ok(n.to_string()),
-						Expr::App(a, b) => {
+						Expr::Var(x, _) if x == z => break Some(n),
+						Expr::App(a, b, _) => {
 							match a.as_ref() {
-								Expr::Var(x) if x == s => {}
-								_ => break Err(()),
+								Expr::Var(x, _) if x == s => {}
+								_ => break None,
 							}
 							n += 1;
 							e = b;
 						}
-						_ => break Err(()),
+						_ => break None,
 					}
 				}
 			}
-			_ => Err(()),
+			_ => None,
 		},
-		_ => Err(()),
+		_ => None,
+	}
+}
+
+fn decode_char(e: &Expr) -> Option<char> {
+	char::from_u32(church_value(e)?.try_into().ok()?)
+}
+
+/// Decodes a list of Church numerals as the code points of a string. If this
+/// fails, returns an explanation.
+fn decode_string(e: &Expr) -> Result<String, String> {
+	let elems = list_elems(e).ok_or("the result is not a list")?;
+	let mut s = String::new();
+	for (i, elem) in elems.into_iter().enumerate() {
+		match church_value(elem) {
+			None => return Err(format!("element {i} is not a Church numeral")),
+			Some(n) => match u32::try_from(n).ok().and_then(char::from_u32) {
+				None => return Err(format!("element {i} ({n}) is not a Unicode code point")),
+				Some(c) => s.push(c),
+			},
+		}
+	}
+	Ok(s)
+}
+
+/// Explains why `e` cannot be interpreted as `dt`, for strings and characters,
+/// which can fail in more ways than the other types.
+pub fn explain_text_error(e: &Expr, dt: &DataType) -> Option<String> {
+	match dt {
+		DataType::String => decode_string(e).err(),
+		DataType::Char if decode_char(e).is_none() => Some(match church_value(e) {
+			None => "the result is not a Church numeral".to_owned(),
+			Some(n) => format!("{n} is not a Unicode code point"),
+		}),
+		_ => None,
 	}
 }
 
-fn interpret_as_tuple(e: &Expr, elem_types: &Vec<DataType>) -> Result<String, ()> {
+fn interpret_as_tuple(
+	e: &Expr,
+	elem_types: &Vec<DataType>,
+	options: &EmitOptions,
+) -> Result<String, ()> {
 	match e {
-		Expr::Fun(s, body) => {
+		Expr::Fun(s, body, _) => {
 			let mut ets = elem_types.clone();
 			let mut e = body;
 			let mut elems: VecDeque<String> = VecDeque::new();
 			loop {
 				match (ets.last(), e.as_ref()) {
-					(None, Expr::Var(p)) if p == s => break,
-					(Some(dt), Expr::App(lhs, rhs)) => {
-						let elem = interpret_as(rhs, dt)?;
+					(None, Expr::Var(p, _)) if p == s => break,
+					(Some(dt), Expr::App(lhs, rhs, _)) => {
+						let elem = interpret(rhs, dt, options)?;
 						elems.push_front(elem);
 						ets.truncate(ets.len() - 1);
 						e = lhs;
@@ -199,42 +371,48 @@ fn interpret_as_tuple(e: &Expr, elem_types: &Vec<DataType>) -> Result<String, ()
 	}
 }
 
-fn interpret_as_list(e: &Expr, dt: &DataType) -> Result<String, ()> {
-	Ok(format!("[{}]", interpret_as_list_without_brackets(e, dt)?))
+fn interpret_as_list(e: &Expr, dt: &DataType, options: &EmitOptions) -> Result<String, ()> {
+	let elems = list_elems(e)
+		.ok_or(())?
+		.into_iter()
+		.map(|head| interpret(head, dt, options))
+		.collect::<Result<Vec<_>, ()>>()?;
+	Ok(format!("[{}]", elems.join(", ")))
 }
 
-fn interpret_as_list_without_brackets(e: &Expr, dt: &DataType) -> Result<String, ()> {
-	match e {
-		_ if is_nil(e) => Ok("".to_owned()),
-		Expr::Fun(s, body) => match body.as_ref() {
-			Expr::App(lhs, tail) => match lhs.as_ref() {
-				Expr::App(p, head) => match p.as_ref() {
-					Expr::Var(p) if p == s => {
-						let head_str = interpret_as(head, dt)?;
-						let tail_str = interpret_as_list_without_brackets(tail, dt)?;
-						if tail_str.trim().is_empty() {
-							Ok(head_str)
-						} else {
-							Ok(format!("{head_str}, {tail_str}"))
-						}
-					}
-					_ => Err(()),
+/// Returns the elements of `e`, if it is a list.
+fn list_elems(e: &Expr) -> Option<Vec<&Expr>> {
+	let mut elems = Vec::new();
+	let mut e = e;
+	// Loop rather than recurse on the tail, so that long lists cannot
+	// overflow the stack
+	while !is_nil(e) {
+		let (head, tail) = match e {
+			Expr::Fun(s, body, _) => match body.as_ref() {
+				Expr::App(lhs, tail, _) => match lhs.as_ref() {
+					Expr::App(p, head, _) => match p.as_ref() {
+						Expr::Var(p, _) if p == s => (head, tail),
+						_ => return None,
+					},
+					_ => return None,
 				},
-				_ => Err(()),
+				_ => return None,
 			},
-			_ => Err(()),
-		},
-		_ => Err(()),
+			_ => return None,
+		};
+		elems.push(head.as_ref());
+		e = tail;
 	}
+	Some(elems)
 }
 
 fn is_nil(e: &Expr) -> bool {
 	match e {
-		Expr::Fun(_, body) => match body.as_ref() {
+		Expr::Fun(_, body, _) => match body.as_ref() {
 			// Check if body is true
-			Expr::Fun(t, body) => match body.as_ref() {
-				Expr::Fun(_, body) => match body.as_ref() {
-					Expr::Var(c) if c == t => true,
+			Expr::Fun(t, body, _) => match body.as_ref() {
+				Expr::Fun(_, body, _) => match body.as_ref() {
+					Expr::Var(c, _) if c == t => true,
 					_ => false,
 				},
 				_ => false,
@@ -264,6 +442,15 @@ mod parse_tests {
 		assert_eq!(DataType::try_from("church"), Ok(DataType::ChurchNumeral));
 	}
 
+	#[test]
+	fn test_parse_string_char() {
+		assert_eq!(DataType::try_from("string"), Ok(DataType::String));
+		assert_eq!(
+			DataType::try_from("list[char]"),
+			Ok(DataType::List(Box::new(DataType::Char)))
+		);
+	}
+
 	#[test]
 	fn test_parse_empty_tuple() {
 		assert_eq!(
@@ -396,6 +583,8 @@ mod parse_tests {
 
 #[cfg(test)]
 mod interpret_as_tests {
+	use crate::emit::EmitOptions;
+	use crate::error::Error;
 	use crate::interpret_as::*;
 	use crate::lex;
 	use crate::parse;
@@ -641,4 +830,142 @@ mod interpret_as_tests {
 			Ok("[2, 1, 0, 1, 2]".to_owned())
 		);
 	}
+
+	fn eval_and_interpret_as(e: &str, dt: &DataType) -> Result<String, ()> {
+		let e = parse(e).to_debruijn().eval();
+		interpret_as(&eta_expand_as(&e, dt).to_named(), dt)
+	}
+
+	#[test]
+	fn test_interpret_eta_reduced_church() {
+		let dt = DataType::ChurchNumeral;
+		assert_eq!(interpret_as(&parse("\\s.s"), &dt), Err(()));
+		assert_eq!(eval_and_interpret_as("\\s.s", &dt), Ok("1".to_owned()));
+		assert_eq!(eval_and_interpret_as("\\x.x", &dt), Ok("1".to_owned()));
+		assert_eq!(
+			eval_and_interpret_as("\\s.\\z.s z", &dt),
+			Ok("1".to_owned())
+		);
+		assert_eq!(eval_and_interpret_as("\\x.x x", &dt), Err(()));
+	}
+
+	#[test]
+	fn test_interpret_eta_reduced_tuple_elems() {
+		let dt = DataType::Tuple(vec![DataType::ChurchNumeral, DataType::Boolean]);
+		assert_eq!(
+			eval_and_interpret_as("\\p.p (\\s.s) (\\t.\\f.f)", &dt),
+			Ok("(1, false)".to_owned())
+		);
+		// Wrong number of elements
+		assert_eq!(eval_and_interpret_as("\\p.p (\\s.s)", &dt), Err(()));
+	}
+
+	#[test]
+	fn test_interpret_eta_reduced_list_elems() {
+		let dt = DataType::List(Box::new(DataType::ChurchNumeral));
+		assert_eq!(
+			eval_and_interpret_as("\\p.p (\\s.s) (\\p.p (\\s.\\z.z) (\\_.\\t.\\f.t))", &dt),
+			Ok("[1, 0]".to_owned())
+		);
+		assert_eq!(
+			eval_and_interpret_as("\\_.\\t.\\f.t", &dt),
+			Ok("[]".to_owned())
+		);
+	}
+
+	#[test]
+	fn test_eta_expand_as_subterms() {
+		let dt = DataType::List(Box::new(DataType::Tuple(vec![
+			DataType::ChurchNumeral,
+			DataType::Boolean,
+		])));
+		let e = parse("\\c.c (\\p.p (\\s.s) (\\t.\\f.f)) (\\_.\\t.\\f.t)").to_debruijn();
+		assert_eq!(
+			eta_expand_as(&e, &dt),
+			parse("\\c.c (\\p.p (\\s.\\z.s z) (\\t.\\f.f)) (\\_.\\t.\\f.t)").to_debruijn()
+		);
+	}
+
+	#[test]
+	fn test_interpret_literals() {
+		let two = "\\s.\\z.s (s z)";
+		let cases = [
+			("true", DataType::Boolean, "true"),
+			("false", DataType::Boolean, "false"),
+			("<>", DataType::Tuple(Vec::new()), "()"),
+			("[]", DataType::List(Box::new(DataType::Boolean)), "[]"),
+			(
+				"[true, false, true]",
+				DataType::List(Box::new(DataType::Boolean)),
+				"[true, false, true]",
+			),
+			(
+				&format!("<{two}, [false], <>>"),
+				DataType::Tuple(vec![
+					DataType::ChurchNumeral,
+					DataType::List(Box::new(DataType::Boolean)),
+					DataType::Tuple(Vec::new()),
+				]),
+				"(2, [false], ())",
+			),
+		];
+		for (e, dt, expected) in cases {
+			assert_eq!(interpret_as(&parse(e), &dt), Ok(expected.to_owned()), "{e}");
+		}
+	}
+
+	#[test]
+	fn test_interpret_string_and_char() {
+		let dt = DataType::String;
+		assert_eq!(interpret_as(&parse("\"\""), &dt), Ok("\"\"".to_owned()));
+		assert_eq!(
+			interpret_as(&parse("\"h\\\"i\\n\""), &dt),
+			Ok("\"h\\\"i\\n\"".to_owned())
+		);
+		assert_eq!(
+			interpret_as(&parse("'\\''"), &DataType::Char),
+			Ok("'\\''".to_owned())
+		);
+		assert_eq!(
+			interpret_as(&parse("[\"ab\", \"\"]"), &DataType::List(Box::new(dt))),
+			Ok("[\"ab\", \"\"]".to_owned())
+		);
+	}
+
+	#[test]
+	fn test_explain_text_error() {
+		let dt = DataType::String;
+		assert_eq!(
+			explain_text_error(&parse("['a', true]"), &dt),
+			Some("element 1 is not a Church numeral".to_owned())
+		);
+		assert_eq!(
+			explain_text_error(&parse("\\x.x"), &dt),
+			Some("the result is not a list".to_owned())
+		);
+		assert_eq!(explain_text_error(&parse("\"a\""), &dt), None);
+		assert_eq!(
+			explain_text_error(&parse("true"), &DataType::Char),
+			Some("the result is not a Church numeral".to_owned())
+		);
+	}
+
+	#[test]
+	fn test_interpret_as_with_options_error() {
+		let options = EmitOptions::default();
+		let dt = DataType::List(Box::new(DataType::Tuple(vec![
+			DataType::Boolean,
+			DataType::ChurchNumeral,
+		])));
+		assert_eq!(
+			interpret_as_with_options(&parse("\\x.x"), &dt, &options),
+			Err(Error::TypeError("list[tuple[bool, church]]".to_owned()))
+		);
+		assert_eq!(
+			interpret_as_with_options(&parse("[true]"), &DataType::String, &options),
+			Err(Error::TypeError(
+				"string (element 0 is not a Church numeral)".to_owned()
+			))
+		);
+	}
 }
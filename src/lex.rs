@@ -1,5 +1,6 @@
 use crate::error::Error;
 use std::{collections::VecDeque, fmt::Display, iter::Peekable, str::Chars};
+use wasm_bindgen::prelude::*;
 
 #[derive(Debug, PartialEq)]
 pub enum TypeToken {
@@ -8,6 +9,8 @@ pub enum TypeToken {
 	// TODO: rename these to "nat" or something, since the booleans are
 	//       apparently also referred to as Church booleans
 	Church,
+	String,
+	Char,
 	Tuple,
 	List,
 	LeftSquareBracket,
@@ -21,6 +24,8 @@ impl Display for TypeToken {
 			TypeToken::Expr => write!(f, "expr"),
 			TypeToken::Bool => write!(f, "bool"),
 			TypeToken::Church => write!(f, "church"),
+			TypeToken::String => write!(f, "string"),
+			TypeToken::Char => write!(f, "char"),
 			TypeToken::Tuple => write!(f, "tuple"),
 			TypeToken::List => write!(f, "list"),
 			TypeToken::LeftSquareBracket => write!(f, "["),
@@ -37,7 +42,19 @@ pub enum Token {
 	Dot,
 	Lpar,
 	Rpar,
+	Lbracket,
+	Rbracket,
+	Langle,
+	Rangle,
+	Comma,
+	True,
+	False,
+	Str(String),
+	Char(char),
 	Where,
+	Let,
+	In,
+	Rec,
 	Def,
 	End,
 	Comment(String),
@@ -51,7 +68,19 @@ impl Display for Token {
 			Token::Dot => write!(f, "."),
 			Token::Lpar => write!(f, "("),
 			Token::Rpar => write!(f, ")"),
+			Token::Lbracket => write!(f, "["),
+			Token::Rbracket => write!(f, "]"),
+			Token::Langle => write!(f, "<"),
+			Token::Rangle => write!(f, ">"),
+			Token::Comma => write!(f, ","),
+			Token::True => write!(f, "true"),
+			Token::False => write!(f, "false"),
+			Token::Str(s) => write!(f, "{s:?}"),
+			Token::Char(c) => write!(f, "{c:?}"),
 			Token::Where => write!(f, "where"),
+			Token::Let => write!(f, "let"),
+			Token::In => write!(f, "in"),
+			Token::Rec => write!(f, "rec"),
 			Token::Def => write!(f, "="),
 			Token::End => write!(f, "<END>"),
 			Token::Comment(s) => write!(f, "{{{s}}}"),
@@ -59,40 +88,167 @@ impl Display for Token {
 	}
 }
 
+impl Token {
+	/// Whether the token is a word that would otherwise be an identifier.
+	pub fn is_keyword(&self) -> bool {
+		matches!(
+			self,
+			Token::Where | Token::Let | Token::In | Token::Rec | Token::True | Token::False
+		)
+	}
+}
+
+/// A part of the source code, from byte `start` to byte `end` (exclusive).
+/// The line and column where it starts are counted from 1, and columns count
+/// characters rather than bytes.
+#[wasm_bindgen]
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+#[cfg_attr(feature = "arbitrary", derive(arbitrary::Arbitrary))]
+pub struct Span {
+	pub start: usize,
+	pub end: usize,
+	pub line: usize,
+	pub column: usize,
+}
+
+impl Span {
+	/// The empty span at the start of the source code.
+	pub fn origin() -> Span {
+		Span {
+			start: 0,
+			end: 0,
+			line: 1,
+			column: 1,
+		}
+	}
+
+	/// The empty span just after `text`, if `text` starts where this span
+	/// does.
+	pub fn after(self, text: &str) -> Span {
+		let mut span = Span {
+			end: self.start,
+			..self
+		};
+		text.chars().for_each(|c| span.advance(c));
+		span
+	}
+
+	/// Moves this empty span past `c`.
+	fn advance(&mut self, c: char) {
+		self.start += c.len_utf8();
+		self.end = self.start;
+		if c == '\n' {
+			self.line += 1;
+			self.column = 1;
+		} else {
+			self.column += 1;
+		}
+	}
+
+	/// The span from the start of this one to the start of `other`.
+	fn to(self, other: Span) -> Span {
+		Span {
+			end: other.start,
+			..self
+		}
+	}
+
+	/// The span from the start of this one to the end of `other`.
+	pub fn through(self, other: Span) -> Span {
+		Span {
+			end: other.end,
+			..self
+		}
+	}
+}
+
 #[derive(Debug, PartialEq)]
 pub struct TokenStream {
-	pub tokens: VecDeque<Token>,
+	pub tokens: VecDeque<(Token, Span)>,
+	/// Where `Token::End` is, just after the last character.
+	pub end: Span,
+	/// The span of the token most recently returned by `next`.
+	prev: Span,
 }
 
 impl TokenStream {
 	pub fn next(&mut self) -> Token {
 		match self.tokens.pop_front() {
-			None => Token::End,
-			Some(tok) => tok,
+			None => {
+				self.prev = self.end;
+				Token::End
+			}
+			Some((tok, span)) => {
+				self.prev = span;
+				tok
+			}
 		}
 	}
 
 	pub fn peek(&self) -> &Token {
-		match self.tokens.get(0) {
+		self.peek_nth(0)
+	}
+
+	/// Returns the token `n` positions after the next one without consuming
+	/// anything.
+	pub fn peek_nth(&self, n: usize) -> &Token {
+		match self.tokens.get(n) {
 			None => &Token::End,
-			Some(tok) => tok,
+			Some((tok, _)) => tok,
 		}
 	}
 
+	/// The span of the next token.
+	pub fn span(&self) -> Span {
+		self.tokens.front().map_or(self.end, |(_, span)| *span)
+	}
+
+	/// The span of the token most recently returned by `next`.
+	pub fn prev_span(&self) -> Span {
+		self.prev
+	}
+
 	pub fn all(&self) -> Vec<Token> {
-		self.tokens.iter().cloned().collect()
+		self.tokens.iter().map(|(tok, _)| tok.clone()).collect()
 	}
 
 	pub fn remove_comments(&mut self) {
-		self.tokens = self
-			.tokens
-			.iter()
-			.filter(|t| match t {
-				Token::Comment(_) => false,
-				_ => true,
-			})
-			.cloned()
-			.collect();
+		self.tokens.retain(|(t, _)| !matches!(t, Token::Comment(_)));
+	}
+}
+
+/// Tokens without positions, as in tests, all get the default span.
+impl From<VecDeque<Token>> for TokenStream {
+	fn from(tokens: VecDeque<Token>) -> Self {
+		TokenStream {
+			tokens: tokens.into_iter().map(|t| (t, Span::default())).collect(),
+			end: Span::default(),
+			prev: Span::default(),
+		}
+	}
+}
+
+/// The characters of the source code, along with the position of the next
+/// one.
+struct Cursor<'a> {
+	chars: Peekable<Chars<'a>>,
+	/// The empty span just before the next character.
+	pos: Span,
+}
+
+impl Cursor<'_> {
+	fn peek(&mut self) -> Option<&char> {
+		self.chars.peek()
+	}
+}
+
+impl Iterator for Cursor<'_> {
+	type Item = char;
+
+	fn next(&mut self) -> Option<char> {
+		let c = self.chars.next()?;
+		self.pos.advance(c);
+		Some(c)
 	}
 }
 
@@ -121,6 +277,8 @@ pub fn lex_type(dt: &str) -> Result<Vec<TypeToken>, Error> {
 					"expr" => TypeToken::Expr,
 					"bool" => TypeToken::Bool,
 					"church" => TypeToken::Church,
+					"string" => TypeToken::String,
+					"char" => TypeToken::Char,
 					"tuple" => TypeToken::Tuple,
 					"list" => TypeToken::List,
 					s => {
@@ -141,29 +299,86 @@ pub fn lex_type(dt: &str) -> Result<Vec<TypeToken>, Error> {
 	Ok(tokens)
 }
 
-const IDENT_SPECIAL_CHARS: [char; 25] = [
-	'~', '`', '!', '@', '#', '$', '%', '^', '&', '*', '-', '_', '+', '[', ']', '|', ':', ';', '\'',
-	'"', ',', '<', '>', '/', '?',
+const IDENT_SPECIAL_CHARS: [char; 19] = [
+	'~', '`', '!', '@', '#', '$', '%', '^', '&', '*', '-', '_', '+', '|', ':', ';', '\'', '/', '?',
 ];
 
 pub fn lex(code: &str) -> Result<TokenStream, Error> {
+	lex_at(code, Span::origin())
+}
+
+/// Like `lex`, but for code that starts at `origin` in a larger program, such
+/// as the expressions in an assertion, so that the spans are positions in the
+/// whole program.
+pub fn lex_at(code: &str, origin: Span) -> Result<TokenStream, Error> {
 	let mut tokens = VecDeque::new();
-	let mut chars = code.chars().peekable();
-	while let Some(c) = chars.next() {
-		match c {
-			'\\' => {
-				tokens.push_back(Token::Lambda);
-			}
-			'.' => {
-				tokens.push_back(Token::Dot);
-			}
-			'(' => {
-				tokens.push_back(Token::Lpar);
+	let mut chars = Cursor {
+		chars: code.chars().peekable(),
+		pos: origin,
+	};
+	loop {
+		let start = chars.pos;
+		let Some(c) = chars.next() else {
+			break;
+		};
+		let tok = match c {
+			'\\' | 'λ' => Token::Lambda,
+			'.' => Token::Dot,
+			'(' => Token::Lpar,
+			')' => Token::Rpar,
+			'[' => Token::Lbracket,
+			']' => Token::Rbracket,
+			'<' => Token::Langle,
+			'>' => Token::Rangle,
+			',' => Token::Comma,
+			'"' => {
+				let mut s = String::new();
+				loop {
+					let at = chars.pos;
+					match chars.next() {
+						Some('"') => break,
+						Some('\\') => s.push(lex_escape(&mut chars, at)?),
+						Some(c) => s.push(c),
+						None => {
+							return Err(Error::SyntaxError(
+								"unclosed string".to_owned(),
+								start.to(start.after("\"")),
+							))
+						}
+					}
+				}
+				Token::Str(s)
 			}
-			')' => {
-				tokens.push_back(Token::Rpar);
+			// Primes can appear in identifiers, but not at the start
+			'\'' => {
+				let at = chars.pos;
+				let c = match chars.next() {
+					Some('\\') => lex_escape(&mut chars, at)?,
+					Some('\'') => {
+						return Err(Error::SyntaxError(
+							"empty character literal".to_owned(),
+							start.to(chars.pos),
+						))
+					}
+					Some(c) => c,
+					None => {
+						return Err(Error::SyntaxError(
+							"unclosed character literal".to_owned(),
+							start.to(at),
+						))
+					}
+				};
+				match chars.next() {
+					Some('\'') => Token::Char(c),
+					_ => {
+						return Err(Error::SyntaxError(
+							"unclosed character literal".to_owned(),
+							start.to(at),
+						))
+					}
+				}
 			}
-			'=' => tokens.push_back(Token::Def),
+			'=' => Token::Def,
 			'{' => {
 				let mut n: usize = 0;
 				let mut s: Vec<char> = Vec::new();
@@ -173,29 +388,83 @@ pub fn lex(code: &str) -> Result<TokenStream, Error> {
 						Some('}') if n == 0 => break,
 						Some('}') => n -= 1,
 						Some('{') => n += 1,
-						None => return Err(Error::SyntaxError("unclosed comment".to_owned())),
+						None => {
+							return Err(Error::SyntaxError(
+								"unclosed comment".to_owned(),
+								start.to(start.after("{")),
+							))
+						}
 						_ => {}
 					}
 					s.push(c.unwrap());
 				}
-				tokens.push_back(Token::Comment(s.iter().collect::<String>()));
+				Token::Comment(s.iter().collect::<String>())
 			}
 			c if is_ident_char(&c) => {
 				let name = lex_ident(c, &mut chars);
-				let tok = match name.as_str() {
+				match name.as_str() {
 					"where" => Token::Where,
+					"let" => Token::Let,
+					"in" => Token::In,
+					"rec" => Token::Rec,
+					"true" => Token::True,
+					"false" => Token::False,
 					_ => Token::Ident(name),
-				};
-				tokens.push_back(tok);
+				}
+			}
+			c if c.is_whitespace() => continue,
+			c => {
+				return Err(Error::SyntaxError(
+					format!("invalid character: '{c}'"),
+					start.to(chars.pos),
+				))
+			}
+		};
+		tokens.push_back((tok, start.to(chars.pos)));
+	}
+	Ok(TokenStream {
+		tokens,
+		end: chars.pos,
+		prev: origin,
+	})
+}
+
+/// Reads the rest of an escape sequence in a string or character literal,
+/// after the backslash, which is at `start`.
+fn lex_escape(chars: &mut Cursor, start: Span) -> Result<char, Error> {
+	let c = chars.next();
+	let error =
+		|message: String, chars: &Cursor| Err(Error::SyntaxError(message, start.to(chars.pos)));
+	match c {
+		Some('n') => Ok('\n'),
+		Some('r') => Ok('\r'),
+		Some('t') => Ok('\t'),
+		Some('0') => Ok('\0'),
+		Some(c @ ('\\' | '"' | '\'')) => Ok(c),
+		Some('u') => {
+			let mut digits = String::new();
+			if chars.next() == Some('{') {
+				for c in chars.by_ref() {
+					if c == '}' {
+						break;
+					}
+					digits.push(c);
+				}
+			}
+			match u32::from_str_radix(&digits, 16)
+				.ok()
+				.and_then(char::from_u32)
+			{
+				Some(c) => Ok(c),
+				None => error(format!("invalid unicode escape \"\\u{{{digits}}}\""), chars),
 			}
-			c if c.is_whitespace() => {}
-			c => return Err(Error::SyntaxError(format!("invalid character: '{c}'"))),
 		}
+		Some(c) => error(format!("invalid escape sequence \"\\{c}\""), chars),
+		None => error("unclosed string".to_owned(), chars),
 	}
-	Ok(TokenStream { tokens: tokens })
 }
 
-fn lex_ident(first: char, chars: &mut Peekable<Chars>) -> String {
+fn lex_ident(first: char, chars: &mut Cursor) -> String {
 	let mut s = vec![first];
 	loop {
 		match chars.peek() {
@@ -209,8 +478,14 @@ fn lex_ident(first: char, chars: &mut Peekable<Chars>) -> String {
 	s.iter().collect()
 }
 
+/// Whether `c` can appear in an identifier. Besides ASCII letters and digits
+/// and the special characters, this allows any Unicode identifier character,
+/// such as Greek letters, and superscripts and subscripts like `x₁`.
 fn is_ident_char(c: &char) -> bool {
-	c.is_ascii_alphanumeric() || IDENT_SPECIAL_CHARS.contains(&c)
+	c.is_ascii_alphanumeric()
+		|| IDENT_SPECIAL_CHARS.contains(c)
+		|| (*c != 'λ' && unicode_ident::is_xid_continue(*c))
+		|| matches!(c, '¹' | '²' | '³' | '\u{2070}'..='\u{209f}')
 }
 
 #[cfg(test)]
@@ -232,6 +507,14 @@ mod lex_type_tests {
 		assert_eq!(lex_type("church"), Ok(vec![TypeToken::Church]));
 	}
 
+	#[test]
+	fn test_lex_string_char() {
+		assert_eq!(
+			lex_type("string char"),
+			Ok(vec![TypeToken::String, TypeToken::Char])
+		);
+	}
+
 	#[test]
 	fn test_lex_1_tuple() {
 		assert_eq!(
@@ -333,6 +616,15 @@ mod lex_type_tests {
 mod lex_tests {
 	use crate::lex::*;
 
+	fn span(start: usize, end: usize, line: usize, column: usize) -> Span {
+		Span {
+			start,
+			end,
+			line,
+			column,
+		}
+	}
+
 	#[test]
 	fn lex_comment() -> () {
 		assert_eq!(
@@ -356,7 +648,10 @@ mod lex_tests {
 	fn lex_unclosed_comment() -> () {
 		assert_eq!(
 			lex(r#"{ Hello there!"#),
-			Err(Error::SyntaxError("unclosed comment".to_owned()))
+			Err(Error::SyntaxError(
+				"unclosed comment".to_owned(),
+				span(0, 1, 1, 1)
+			))
 		);
 	}
 
@@ -364,23 +659,82 @@ mod lex_tests {
 	fn lex_unclosed_nested_comment() -> () {
 		assert_eq!(
 			lex(r#"{ { Hello there! }"#),
-			Err(Error::SyntaxError("unclosed comment".to_owned()))
+			Err(Error::SyntaxError(
+				"unclosed comment".to_owned(),
+				span(0, 1, 1, 1)
+			))
 		);
 	}
 
 	#[test]
 	fn lex_invalid_char() -> () {
 		assert_eq!(
-			lex(r#"λx.x"#),
-			Err(Error::SyntaxError("invalid character: 'λ'".to_owned()))
+			lex("x\n  §x.x"),
+			Err(Error::SyntaxError(
+				"invalid character: '§'".to_owned(),
+				span(4, 6, 2, 3)
+			))
 		)
 	}
 
+	#[test]
+	fn lex_spans() -> () {
+		let stream = lex("\\x.\n\tλy  \"é\"").unwrap();
+		let spans: Vec<Span> = stream.tokens.iter().map(|(_, span)| *span).collect();
+		assert_eq!(
+			vec![
+				span(0, 1, 1, 1),
+				span(1, 2, 1, 2),
+				span(2, 3, 1, 3),
+				span(5, 7, 2, 2),
+				span(7, 8, 2, 3),
+				span(10, 14, 2, 6),
+			],
+			spans
+		);
+		assert_eq!(span(14, 14, 2, 9), stream.end);
+	}
+
+	#[test]
+	fn lex_at_origin() -> () {
+		let origin = Span::origin().after("{:assert\n ");
+		let stream = lex_at("x", origin).unwrap();
+		assert_eq!(span(10, 11, 2, 2), stream.span());
+	}
+
 	#[test]
 	fn lex_lambda() -> () {
 		assert_eq!(vec![Token::Lambda], lex(r#"\"#).unwrap().all());
 	}
 
+	#[test]
+	fn lex_unicode_lambda() -> () {
+		assert_eq!(
+			vec![
+				Token::Lambda,
+				Token::Ident("x".to_owned()),
+				Token::Dot,
+				Token::Ident("x".to_owned())
+			],
+			lex("λx.x").unwrap().all()
+		);
+	}
+
+	#[test]
+	fn lex_unicode_ident() -> () {
+		assert_eq!(
+			vec![
+				Token::Lambda,
+				Token::Ident("α".to_owned()),
+				Token::Ident("x₁".to_owned()),
+				Token::Ident("Σ²".to_owned()),
+				Token::Lambda,
+				Token::Ident("ñ".to_owned()),
+			],
+			lex("λα x₁ Σ² λñ").unwrap().all()
+		);
+	}
+
 	#[test]
 	fn lex_simple_ident() -> () {
 		assert_eq!(vec![Token::Ident("a".to_owned())], lex("a").unwrap().all());
@@ -414,21 +768,45 @@ mod lex_tests {
 				Token::Ident("--".to_owned()),
 				Token::Ident("__".to_owned()),
 				Token::Ident("++".to_owned()),
-				Token::Ident("[[".to_owned()),
-				Token::Ident("]]".to_owned()),
 				Token::Ident("||".to_owned()),
 				Token::Ident("::".to_owned()),
 				Token::Ident(";;".to_owned()),
-				Token::Ident("''".to_owned()),
-				Token::Ident("\"\"".to_owned()),
-				Token::Ident(",,".to_owned()),
-				Token::Ident("<<".to_owned()),
-				Token::Ident(">>".to_owned()),
+				Token::Ident("a''".to_owned()),
 				Token::Ident("//".to_owned()),
 				Token::Ident("??".to_owned()),
 				Token::Dot,
 			],
-			lex("ab CD 12 ~~ `` !! @@ ## $$ %% ^^ && ** -- __ ++ [[ ]] || :: ;; '' \"\" ,, << >> // ?? .").unwrap().all(),
+			lex("ab CD 12 ~~ `` !! @@ ## $$ %% ^^ && ** -- __ ++ || :: ;; a'' // ?? .")
+				.unwrap()
+				.all(),
+		);
+	}
+
+	#[test]
+	fn lex_former_ident_chars() -> () {
+		// These used to be identifiers, but are now used by literals
+		assert_eq!(
+			vec![
+				Token::Lbracket,
+				Token::Lbracket,
+				Token::Rbracket,
+				Token::Rbracket,
+				Token::Str(String::new()),
+				Token::Comma,
+				Token::Comma,
+				Token::Langle,
+				Token::Langle,
+				Token::Rangle,
+				Token::Rangle,
+			],
+			lex("[[ ]] \"\" ,, << >>").unwrap().all()
+		);
+		assert_eq!(
+			Err(Error::SyntaxError(
+				"empty character literal".to_owned(),
+				span(0, 2, 1, 1)
+			)),
+			lex("''")
 		);
 	}
 
@@ -452,6 +830,70 @@ mod lex_tests {
 		assert_eq!(vec![Token::Where], lex("where").unwrap().all());
 	}
 
+	#[test]
+	fn lex_let_in() -> () {
+		assert_eq!(
+			vec![Token::Let, Token::Rec, Token::In],
+			lex("let rec in").unwrap().all()
+		);
+		assert_eq!(
+			vec![Token::Ident("letter".to_owned())],
+			lex("letter").unwrap().all()
+		);
+	}
+
+	#[test]
+	fn lex_literals() -> () {
+		assert_eq!(
+			vec![
+				Token::Lbracket,
+				Token::True,
+				Token::Comma,
+				Token::Langle,
+				Token::Ident("a".to_owned()),
+				Token::Comma,
+				Token::False,
+				Token::Rangle,
+				Token::Rbracket,
+			],
+			lex("[true,<a, false>]").unwrap().all()
+		);
+	}
+
+	#[test]
+	fn lex_string_and_char() -> () {
+		assert_eq!(
+			vec![
+				Token::Str("a \"b\"\n\u{e9}".to_owned()),
+				Token::Char('c'),
+				Token::Char('\''),
+				Token::Str(String::new()),
+			],
+			lex(r#""a \"b\"\n\u{e9}" 'c' '\'' """#).unwrap().all()
+		);
+	}
+
+	#[test]
+	fn lex_malformed_string_and_char() -> () {
+		let error = |s: &str, start, end| {
+			Err(Error::SyntaxError(
+				s.to_owned(),
+				span(start, end, 1, start + 1),
+			))
+		};
+		assert_eq!(lex(r#""abc"#), error("unclosed string", 0, 1));
+		assert_eq!(
+			lex(r#"x "\q""#),
+			error("invalid escape sequence \"\\q\"", 3, 5)
+		);
+		assert_eq!(
+			lex(r#""\u{110000}""#),
+			error("invalid unicode escape \"\\u{110000}\"", 1, 11)
+		);
+		assert_eq!(lex("''"), error("empty character literal", 0, 2));
+		assert_eq!(lex("'ab'"), error("unclosed character literal", 0, 1));
+	}
+
 	#[test]
 	fn lex_def() -> () {
 		assert_eq!(vec![Token::Def], lex("=").unwrap().all());
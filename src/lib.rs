@@ -1,13 +1,28 @@
 pub mod debruijn;
+mod divergence;
 pub mod emit;
+pub mod equiv;
 pub mod error;
 pub mod eval;
 pub mod interpret_as;
 pub mod lex;
+pub mod literals;
+pub mod machine;
+pub mod nbe;
+pub mod optimal;
+#[cfg(feature = "parallel")]
+pub mod parallel;
 pub mod parse;
 
+use crate::debruijn::Term;
+use crate::divergence::DivergenceDetector;
+use crate::emit::EmitOptions;
+use crate::equiv::Equivalence;
 use crate::error::Error;
-use interpret_as::interpret_as;
+use crate::eval::{check_stepwise, try_beta_reduce, Engine, EvalStats, Strategy, Timer};
+use crate::literals::NumeralEncoding;
+use crate::parse::{Check, Expr};
+use interpret_as::{eta_expand_as, interpret_as, interpret_as_with_options, DataType};
 use wasm_bindgen::prelude::*;
 
 pub fn set_panic_hook() {
@@ -21,19 +36,564 @@ pub fn set_panic_hook() {
 	console_error_panic_hook::set_once();
 }
 
+/// Options for `eval_lambda_with_options`.
+#[wasm_bindgen]
+#[derive(Clone, Debug, Default)]
+pub struct EvalOptions {
+	max_steps: Option<usize>,
+	strategy: Strategy,
+	eta: bool,
+	engine: Engine,
+	collapse_lambdas: bool,
+	unicode_lambda: bool,
+	numerals: Option<NumeralEncoding>,
+	#[cfg(feature = "parallel")]
+	parallel: bool,
+}
+
+#[wasm_bindgen]
+impl EvalOptions {
+	#[wasm_bindgen(constructor)]
+	pub fn new() -> EvalOptions {
+		EvalOptions::default()
+	}
+
+	/// Maximum number of beta reductions to perform before giving up.
+	/// If not set, evaluation continues until a normal form is found.
+	#[wasm_bindgen(getter)]
+	pub fn max_steps(&self) -> Option<usize> {
+		self.max_steps
+	}
+
+	#[wasm_bindgen(setter)]
+	pub fn set_max_steps(&mut self, max_steps: Option<usize>) {
+		self.max_steps = max_steps;
+	}
+
+	/// The reduction strategy, which also determines the normal form at which
+	/// evaluation stops. Defaults to normal order.
+	#[wasm_bindgen(getter)]
+	pub fn strategy(&self) -> Strategy {
+		self.strategy
+	}
+
+	#[wasm_bindgen(setter)]
+	pub fn set_strategy(&mut self, strategy: Strategy) {
+		self.strategy = strategy;
+	}
+
+	/// Whether to also apply eta reduction to the result, giving a beta-eta
+	/// normal form. Disabled by default.
+	#[wasm_bindgen(getter)]
+	pub fn eta(&self) -> bool {
+		self.eta
+	}
+
+	#[wasm_bindgen(setter)]
+	pub fn set_eta(&mut self, eta: bool) {
+		self.eta = eta;
+	}
+
+	/// The method used to evaluate the expression. Defaults to substitution.
+	/// `trace_lambda` and `Evaluation` always use substitution, and
+	/// `eval_lambda_with_stats` only supports substitution and optimal
+	/// reduction.
+	#[wasm_bindgen(getter)]
+	pub fn engine(&self) -> Engine {
+		self.engine
+	}
+
+	#[wasm_bindgen(setter)]
+	pub fn set_engine(&mut self, engine: Engine) {
+		self.engine = engine;
+	}
+
+	/// Whether to write nested lambdas in results with a single backslash, as
+	/// in `\x y.x`. Disabled by default.
+	#[wasm_bindgen(getter)]
+	pub fn collapse_lambdas(&self) -> bool {
+		self.collapse_lambdas
+	}
+
+	#[wasm_bindgen(setter)]
+	pub fn set_collapse_lambdas(&mut self, collapse_lambdas: bool) {
+		self.collapse_lambdas = collapse_lambdas;
+	}
+
+	/// Whether to write lambdas in results as `λ` instead of a backslash.
+	/// Disabled by default.
+	#[wasm_bindgen(getter)]
+	pub fn unicode_lambda(&self) -> bool {
+		self.unicode_lambda
+	}
+
+	#[wasm_bindgen(setter)]
+	pub fn set_unicode_lambda(&mut self, unicode_lambda: bool) {
+		self.unicode_lambda = unicode_lambda;
+	}
+
+	/// If set, numeric literals such as `3` that are not bound otherwise are
+	/// replaced by numerals with this encoding. A `{:numerals}` comment in the
+	/// program takes precedence. Not set by default.
+	#[wasm_bindgen(getter)]
+	pub fn numerals(&self) -> Option<NumeralEncoding> {
+		self.numerals
+	}
+
+	#[wasm_bindgen(setter)]
+	pub fn set_numerals(&mut self, numerals: Option<NumeralEncoding>) {
+		self.numerals = numerals;
+	}
+}
+
+#[cfg(feature = "parallel")]
+impl EvalOptions {
+	/// Whether `eval_lambda_with_options` uses the parallel evaluator (see
+	/// `parallel::eval`) instead of the chosen engine. Only available natively,
+	/// with the `parallel` feature. Disabled by default.
+	pub fn parallel(&self) -> bool {
+		self.parallel
+	}
+
+	pub fn set_parallel(&mut self, parallel: bool) {
+		self.parallel = parallel;
+	}
+}
+
+impl EvalOptions {
+	fn emit_options(&self) -> EmitOptions {
+		EmitOptions {
+			collapse_lambdas: self.collapse_lambdas,
+			unicode_lambda: self.unicode_lambda,
+		}
+	}
+}
+
 #[wasm_bindgen]
 pub fn eval_lambda(src: &str) -> Result<String, Error> {
+	eval_lambda_with_options(src, &EvalOptions::new())
+}
+
+#[wasm_bindgen]
+pub fn eval_lambda_with_options(src: &str, options: &EvalOptions) -> Result<String, Error> {
 	set_panic_hook();
-	let mut stream = lex::lex(src)?;
+	let e = parse_program(src, options)?;
+	let evaluated = eval_term(&e, options)?;
+	format_result(src, evaluated, options)
+}
+
+fn eval_term(e: &Term, options: &EvalOptions) -> Result<Term, Error> {
+	#[cfg(feature = "parallel")]
+	if options.parallel {
+		return parallel::eval(e, options.strategy, options.max_steps);
+	}
+	e.eval_with_engine(options.engine, options.strategy, options.max_steps)
+}
+
+/// The output of a program along with statistics about its evaluation.
+#[wasm_bindgen]
+#[derive(Clone, Debug, PartialEq)]
+pub struct EvalResult {
+	output: String,
+	stats: EvalStats,
+}
+
+#[wasm_bindgen]
+impl EvalResult {
+	#[wasm_bindgen(getter)]
+	pub fn output(&self) -> String {
+		self.output.clone()
+	}
+
+	#[wasm_bindgen(getter)]
+	pub fn stats(&self) -> EvalStats {
+		self.stats
+	}
+}
+
+/// Like `eval_lambda_with_options`, but also returns statistics about the
+/// evaluation. Uses the substitution engine, unless the optimal reduction
+/// engine is selected, in which case the number of interactions is reported
+/// as well.
+#[wasm_bindgen]
+pub fn eval_lambda_with_stats(src: &str, options: &EvalOptions) -> Result<EvalResult, Error> {
+	set_panic_hook();
+	let e = parse_program(src, options)?;
+	let (evaluated, stats) = match options.engine {
+		Engine::Optimal => optimal::eval_with_stats(&e, options.strategy, options.max_steps)?,
+		_ => e.eval_with_stats(options.strategy, options.max_steps)?,
+	};
+	let output = format_result(src, evaluated, options)?;
+	Ok(EvalResult { output, stats })
+}
+
+/// An evaluation that can be advanced a few steps at a time, so that long
+/// computations can report progress and be stopped. Always uses the
+/// substitution engine, so call-by-need is not supported.
+#[wasm_bindgen]
+pub struct Evaluation {
+	src: String,
+	options: EvalOptions,
+	expr: Term,
+	steps: usize,
+	detector: DivergenceDetector,
+	stats: EvalStats,
+	state: EvaluationState,
+}
+
+#[derive(Debug, PartialEq)]
+enum EvaluationState {
+	Running,
+	Done,
+	/// Stopped by `Evaluation::cancel`.
+	Cancelled,
+	/// Stopped because of this error.
+	Failed(Error),
+}
+
+#[wasm_bindgen]
+impl Evaluation {
+	#[wasm_bindgen(constructor)]
+	pub fn new(src: &str, options: &EvalOptions) -> Result<Evaluation, Error> {
+		set_panic_hook();
+		check_stepwise(options.strategy)?;
+		let expr = parse_program(src, options)?;
+		let detector = DivergenceDetector::new(&expr, options.strategy);
+		let mut stats = EvalStats::default();
+		stats.record(&expr);
+		Ok(Evaluation {
+			src: src.to_owned(),
+			options: options.clone(),
+			expr,
+			steps: 0,
+			detector,
+			stats,
+			state: EvaluationState::Running,
+		})
+	}
+
+	/// Performs at most `n` beta reductions and returns whether the
+	/// expression has reached a normal form.
+	/// Does nothing once evaluation is done or has been stopped, either by
+	/// `cancel` or by an error.
+	pub fn step(&mut self, n: usize) -> Result<bool, Error> {
+		if self.state != EvaluationState::Running {
+			return Ok(self.is_done());
+		}
+		let timer = Timer::start();
+		let result = self.advance(n);
+		self.stats.time_ms += timer.elapsed_ms();
+		if let Err(e) = result {
+			self.state = EvaluationState::Failed(e.clone());
+			return Err(e);
+		}
+		Ok(self.is_done())
+	}
+
+	fn advance(&mut self, n: usize) -> Result<(), Error> {
+		let mut path = Vec::new();
+		for _ in 0..n {
+			let Some(next) = try_beta_reduce(&self.expr, self.options.strategy, &mut path) else {
+				self.state = EvaluationState::Done;
+				self.stats.final_size = self.expr.size();
+				return Ok(());
+			};
+			if Some(self.steps) == self.options.max_steps {
+				return Err(Error::StepLimitExceeded(self.steps, self.expr.clone()));
+			}
+			self.detector.check(&self.expr, &path)?;
+			self.stats.record_step(&self.expr, &path, &next);
+			self.expr = next;
+			self.steps += 1;
+			path.clear();
+		}
+		Ok(())
+	}
+
+	/// Whether the expression has reached a normal form.
+	pub fn is_done(&self) -> bool {
+		self.state == EvaluationState::Done
+	}
+
+	/// Whether evaluation was cancelled or stopped because of an error.
+	pub fn is_stopped(&self) -> bool {
+		matches!(
+			self.state,
+			EvaluationState::Cancelled | EvaluationState::Failed(_)
+		)
+	}
+
+	/// Stops the evaluation. The current expression can still be read.
+	pub fn cancel(&mut self) {
+		if self.state == EvaluationState::Running {
+			self.state = EvaluationState::Cancelled;
+		}
+	}
+
+	/// Number of beta reductions performed so far.
+	#[wasm_bindgen(getter)]
+	pub fn steps(&self) -> usize {
+		self.steps
+	}
+
+	/// The current expression, which is the normal form once evaluation is
+	/// done.
+	#[wasm_bindgen(getter)]
+	pub fn current(&self) -> String {
+		self.options.emit_options().emit(&self.expr.to_named())
+	}
+
+	/// The output of the program, as returned by `eval_lambda_with_options`.
+	/// Only available once evaluation is done. Otherwise, this is the error
+	/// that stopped evaluation, `Error::Cancelled` if it was cancelled, or
+	/// `Error::StepLimitExceeded` if it is still running.
+	pub fn output(&self) -> Result<String, Error> {
+		match &self.state {
+			EvaluationState::Done => format_result(&self.src, self.expr.clone(), &self.options),
+			EvaluationState::Running => {
+				Err(Error::StepLimitExceeded(self.steps, self.expr.clone()))
+			}
+			EvaluationState::Cancelled => Err(Error::Cancelled(self.steps, self.expr.clone())),
+			EvaluationState::Failed(e) => Err(e.clone()),
+		}
+	}
+
+	#[wasm_bindgen(getter)]
+	pub fn stats(&self) -> EvalStats {
+		self.stats
+	}
+}
+
+/// One step of a derivation, in the form shown to the user.
+#[wasm_bindgen]
+#[derive(Clone, Debug, PartialEq)]
+pub struct DerivationStep {
+	expr: String,
+	/// Positions of the redexes contracted in this step. There is more than
+	/// one only with complete development.
+	redexes: Vec<(usize, usize)>,
+}
+
+#[wasm_bindgen]
+impl DerivationStep {
+	#[wasm_bindgen(getter)]
+	pub fn expr(&self) -> String {
+		self.expr.clone()
+	}
+
+	/// Start of the (first) redex contracted in this step, as a UTF-16 index
+	/// into `expr`. Not set for the last step.
+	#[wasm_bindgen(getter)]
+	pub fn redex_start(&self) -> Option<usize> {
+		self.redexes.first().map(|(start, _)| *start)
+	}
+
+	/// End (exclusive) of the (first) redex contracted in this step, as a
+	/// UTF-16 index into `expr`. Not set for the last step.
+	#[wasm_bindgen(getter)]
+	pub fn redex_end(&self) -> Option<usize> {
+		self.redexes.first().map(|(_, end)| *end)
+	}
+
+	/// Starts of all the redexes contracted in this step, from left to right.
+	/// Nested redexes, which complete development also contracts, are part
+	/// of the enclosing one.
+	#[wasm_bindgen(getter)]
+	pub fn redex_starts(&self) -> Vec<usize> {
+		self.redexes.iter().map(|(start, _)| *start).collect()
+	}
+
+	/// Ends (exclusive) of all the redexes contracted in this step, in the
+	/// same order as `redex_starts`.
+	#[wasm_bindgen(getter)]
+	pub fn redex_ends(&self) -> Vec<usize> {
+		self.redexes.iter().map(|(_, end)| *end).collect()
+	}
+}
+
+/// Evaluates the program and returns every intermediate expression, with the
+/// position of the redex contracted at each step.
+#[wasm_bindgen]
+pub fn trace_lambda(src: &str, options: &EvalOptions) -> Result<Vec<DerivationStep>, Error> {
+	set_panic_hook();
+	let e = parse_program(src, options)?;
+	let trace = e.trace(options.strategy, options.max_steps)?;
+	let emit_options = options.emit_options();
+	let steps = trace
+		.iter()
+		.map(|step| {
+			let named = step.expr.to_named();
+			let (expr, redexes) = match &step.redex {
+				Some(_) if options.strategy == Strategy::CompleteDevelopment => {
+					emit_options.emit_with_redex_spans(&named)
+				}
+				Some(path) => {
+					let (expr, redex) = emit_options.emit_with_span(&named, path);
+					(expr, redex.into_iter().collect())
+				}
+				None => (emit_options.emit(&named), Vec::new()),
+			};
+			// JavaScript indexes strings by UTF-16 code units, which differ
+			// from byte offsets once `expr` contains `λ`.
+			let utf16 = |i: usize| expr[..i].encode_utf16().count();
+			let redexes = redexes
+				.into_iter()
+				.map(|(start, end)| (utf16(start), utf16(end)))
+				.collect();
+			DerivationStep { expr, redexes }
+		})
+		.collect();
+	Ok(steps)
+}
+
+/// Checks whether two programs are the same up to the names of bound
+/// variables (see `equiv::alpha_eq`). Free variables are allowed.
+#[wasm_bindgen]
+pub fn alpha_eq_lambda(a: &str, b: &str) -> Result<bool, Error> {
+	set_panic_hook();
+	let (a, b) = (parse_expr(a, None)?, parse_expr(b, None)?);
+	Ok(equiv::alpha_eq(&a, &b))
+}
+
+/// Checks whether two programs are beta-equivalent, performing at most `fuel`
+/// beta reductions on each (see `equiv::beta_eq`). Free variables are allowed.
+#[wasm_bindgen]
+pub fn beta_eq_lambda(a: &str, b: &str, fuel: usize) -> Result<Equivalence, Error> {
+	set_panic_hook();
+	let (a, b) = (parse_expr(a, None)?, parse_expr(b, None)?);
+	Ok(equiv::beta_eq(&a, &b, fuel))
+}
+
+/// The outcome of an `{:assert ...}` or `{:assert-type ...}` comment.
+#[wasm_bindgen]
+#[derive(Clone, Debug, PartialEq)]
+pub struct AssertionResult {
+	directive: String,
+	passed: bool,
+	actual: Option<String>,
+}
+
+#[wasm_bindgen]
+impl AssertionResult {
+	/// The text of the comment, without the braces.
+	#[wasm_bindgen(getter)]
+	pub fn directive(&self) -> String {
+		self.directive.clone()
+	}
+
+	#[wasm_bindgen(getter)]
+	pub fn passed(&self) -> bool {
+		self.passed
+	}
+
+	/// If the assertion failed, the normal form that was found instead (of the
+	/// left-hand side, or of the whole program for `assert-type`), or the
+	/// error that prevented finding it.
+	#[wasm_bindgen(getter)]
+	pub fn actual(&self) -> Option<String> {
+		self.actual.clone()
+	}
+}
+
+/// Checks the `{:assert <e1> == <e2>}` and `{:assert-type <t>}` comments in
+/// the program. The expressions in assertions can use the program's `where`
+/// declarations, and are evaluated according to the options.
+#[wasm_bindgen]
+pub fn check_assertions(src: &str, options: &EvalOptions) -> Result<Vec<AssertionResult>, Error> {
+	set_panic_hook();
+	let annotate = |e: Error| e.annotate(src);
+	let mut stream = lex::lex(src).map_err(annotate)?;
+	let assertions = parse::find_assertions(&stream).map_err(annotate)?;
+	let numerals = parse::find_numerals(&stream)
+		.map_err(annotate)?
+		.or(options.numerals);
 	stream.remove_comments();
-	let e = parse::parse(&mut stream)?;
-	let evaluated = e.to_debruijn().eval().to_named();
-	stream = lex::lex(src)?;
+	let (program, decls) = parse::parse_with_decls(&mut stream).map_err(annotate)?;
+	let normalize = |e: &Expr| -> Result<Term, Error> {
+		let e = expand_numerals(parse::inline_decls(e, &decls), numerals).map_err(annotate)?;
+		let e = to_closed_debruijn(&e).map_err(annotate)?;
+		let nf = eval_term(&e, options)?;
+		Ok(if options.eta { nf.eta_reduce() } else { nf })
+	};
+	let emit_options = options.emit_options();
+	let mut results = Vec::new();
+	for assertion in assertions {
+		let actual = match &assertion.check {
+			Check::Equal(lhs, rhs) => match (normalize(lhs), normalize(rhs)) {
+				(Ok(lhs), Ok(rhs)) if lhs == rhs => None,
+				(Ok(lhs), Ok(_)) => Some(emit_options.emit(&lhs.to_named())),
+				(Err(e), _) | (_, Err(e)) => Some(e.to_string()),
+			},
+			// A malformed type only fails its own assertion
+			Check::Type(t) => match DataType::try_from(t.as_str()) {
+				Ok(datatype) => match normalize(&program) {
+					Ok(nf) => {
+						let expanded = eta_expand_as(&nf, &datatype).to_named();
+						match interpret_as(&expanded, &datatype) {
+							Ok(_) => None,
+							Err(()) => Some(emit_options.emit(&nf.to_named())),
+						}
+					}
+					Err(e) => Some(e.to_string()),
+				},
+				Err(e) => Some(e.to_string()),
+			},
+		};
+		results.push(AssertionResult {
+			directive: assertion.directive,
+			passed: actual.is_none(),
+			actual,
+		});
+	}
+	Ok(results)
+}
+
+/// Applies the remaining options to the evaluated program and interprets it
+/// according to the program's type annotation.
+fn format_result(src: &str, evaluated: Term, options: &EvalOptions) -> Result<String, Error> {
+	let evaluated = if options.eta {
+		evaluated.eta_reduce()
+	} else {
+		evaluated
+	};
+	let mut stream = lex::lex(src)?;
 	let datatype_str = parse::find_type_annotation(&mut stream).unwrap_or("expr".to_owned());
 	let datatype = datatype_str.as_str().try_into()?;
-	let out = interpret_as(&evaluated, &datatype);
-	match out {
-		Ok(s) => Ok(s),
-		Err(()) => Err(Error::TypeError(datatype_str)),
+	let expanded = eta_expand_as(&evaluated, &datatype).to_named();
+	interpret_as_with_options(&expanded, &datatype, &options.emit_options())
+}
+
+fn parse_program(src: &str, options: &EvalOptions) -> Result<Term, Error> {
+	let e = parse_expr(src, options.numerals)?;
+	to_closed_debruijn(&e).map_err(|e| e.annotate(src))
+}
+
+/// Converts the expression to de Bruijn indices, or returns an error at the
+/// first variable in it that is not bound.
+fn to_closed_debruijn(e: &Expr) -> Result<Term, Error> {
+	match parse::free_occurrences(e).first() {
+		Some(&(x, span)) => Err(Error::FreeVariable(x.to_owned(), span)),
+		None => Ok(e.to_debruijn()),
+	}
+}
+
+/// Parses the program. Numeric literals are expanded with the encoding given
+/// by a `{:numerals}` comment, or else by `numerals`. Syntax errors show the
+/// line of the program where they are.
+fn parse_expr(src: &str, numerals: Option<NumeralEncoding>) -> Result<Box<Expr>, Error> {
+	let annotate = |e: Error| e.annotate(src);
+	let mut stream = lex::lex(src).map_err(annotate)?;
+	let numerals = parse::find_numerals(&stream)
+		.map_err(annotate)?
+		.or(numerals);
+	stream.remove_comments();
+	let e = parse::parse(&mut stream).map_err(annotate)?;
+	expand_numerals(e, numerals).map_err(annotate)
+}
+
+fn expand_numerals(e: Box<Expr>, numerals: Option<NumeralEncoding>) -> Result<Box<Expr>, Error> {
+	match numerals {
+		Some(encoding) => literals::expand_numerals(&e, encoding),
+		None => Ok(e),
 	}
 }
@@ -0,0 +1,285 @@
+use wasm_bindgen::prelude::*;
+
+use crate::error::Error;
+use crate::lex::Span;
+use std::collections::HashSet;
+
+use crate::parse::{free_occurrences, free_vars, fresh, inline_decls, Decl, Expr};
+
+/// How numeric literals such as `3` are turned into expressions.
+#[wasm_bindgen]
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub enum NumeralEncoding {
+	/// `n` is `\s.\z.s (... (s z))`, with `n` applications of `s`.
+	#[default]
+	Church,
+	/// `0` is `\s.\z.z` and `n + 1` is `\s.\z.s n`.
+	Scott,
+	/// `n` is the list of its bits as booleans, least significant first, so
+	/// `0` is the empty list and `6` is `[false, true, true]`.
+	Binary,
+}
+
+impl TryFrom<&str> for NumeralEncoding {
+	type Error = Error;
+
+	fn try_from(value: &str) -> Result<Self, Self::Error> {
+		match value {
+			"church" => Ok(NumeralEncoding::Church),
+			"scott" => Ok(NumeralEncoding::Scott),
+			"binary" => Ok(NumeralEncoding::Binary),
+			// The caller knows where the name is (see `Error::at`)
+			s => Err(Error::SyntaxError(
+				format!("unknown numeral encoding \"{s}\""),
+				Span::default(),
+			)),
+		}
+	}
+}
+
+/// The largest numeric literal in the Church and Scott encodings, whose size
+/// grows with the number. Binary numerals can go up to `usize::MAX`.
+pub const MAX_UNARY_NUMERAL: usize = 1_000_000;
+
+/// Replaces the free variables of `e` that are natural numbers written in
+/// decimal, like `0` or `42`, by their encoding. Fails if one of them is too
+/// large for the encoding.
+pub fn expand_numerals(e: &Expr, encoding: NumeralEncoding) -> Result<Box<Expr>, Error> {
+	let max = match encoding {
+		NumeralEncoding::Church | NumeralEncoding::Scott => MAX_UNARY_NUMERAL,
+		NumeralEncoding::Binary => usize::MAX,
+	};
+	let mut numerals: Vec<Decl> = Vec::new();
+	let mut seen = HashSet::new();
+	for (x, span) in free_occurrences(e) {
+		if !is_numeral(x) || !seen.insert(x) {
+			continue;
+		}
+		let n = match x.parse() {
+			Ok(n) if n <= max => n,
+			_ => return Err(Error::NumeralTooLarge(x.to_owned(), max, span)),
+		};
+		numerals.push((x.to_owned(), numeral(n, encoding)));
+	}
+	Ok(inline_decls(e, &numerals))
+}
+
+/// Whether `x` is a natural number without leading zeros.
+fn is_numeral(x: &str) -> bool {
+	!(x.starts_with('0') && x != "0") && x.bytes().all(|b| b.is_ascii_digit())
+}
+
+/// Returns the encoding of `n`.
+pub fn numeral(n: usize, encoding: NumeralEncoding) -> Box<Expr> {
+	match encoding {
+		NumeralEncoding::Church => {
+			let mut e = var("z");
+			for _ in 0..n {
+				e = app(var("s"), e);
+			}
+			fun("s", fun("z", e))
+		}
+		NumeralEncoding::Scott => {
+			let mut e = fun("s", fun("z", var("z")));
+			for _ in 0..n {
+				e = fun("s", fun("z", app(var("s"), e)));
+			}
+			e
+		}
+		NumeralEncoding::Binary => {
+			let mut bits = Vec::new();
+			let mut n = n;
+			while n > 0 {
+				bits.push(*boolean(n % 2 == 1));
+				n /= 2;
+			}
+			list(bits)
+		}
+	}
+}
+
+/// The code point of `c`, as a Church numeral. Fails if it is larger than
+/// `MAX_UNARY_NUMERAL`.
+pub fn character(c: char) -> Result<Box<Expr>, Error> {
+	if c as usize > MAX_UNARY_NUMERAL {
+		// The caller knows where the literal is (see `Error::at`)
+		return Err(Error::SyntaxError(
+			format!(
+				"character literal is too large: code point {} is more than {MAX_UNARY_NUMERAL}",
+				c as usize
+			),
+			Span::default(),
+		));
+	}
+	Ok(numeral(c as usize, NumeralEncoding::Church))
+}
+
+/// The list of the code points of `s`, as Church numerals. Fails if one of
+/// them is too large, or if the numerals and the cells of the list add up to
+/// more than `MAX_UNARY_NUMERAL`.
+pub fn string(s: &str) -> Result<Box<Expr>, Error> {
+	let total = s.chars().map(|c| c as usize + 1).sum::<usize>();
+	if total > MAX_UNARY_NUMERAL {
+		// The caller knows where the literal is (see `Error::at`)
+		return Err(Error::SyntaxError(
+			format!("string literal is too large: its code points add up to more than {MAX_UNARY_NUMERAL}"),
+			Span::default(),
+		));
+	}
+	Ok(list(
+		s.chars()
+			.map(|c| character(c).map(|e| *e))
+			.collect::<Result<_, _>>()?,
+	))
+}
+
+/// `\t.\f.t` or `\t.\f.f`.
+pub fn boolean(b: bool) -> Box<Expr> {
+	fun("t", fun("f", var(if b { "t" } else { "f" })))
+}
+
+/// The list `[e1, ..., en]`, which is `\p.p e1 (... (\p.p en nil))`, where
+/// `nil` is `\x.\t.\f.t`.
+pub fn list(elems: Vec<Expr>) -> Box<Expr> {
+	let p = binder("p", &elems);
+	let mut e = fun("x", boolean(true));
+	for elem in elems.into_iter().rev() {
+		e = fun(&p, app(app(var(&p), Box::new(elem)), e));
+	}
+	e
+}
+
+/// The tuple `<e1, ..., en>`, which is `\s.s e1 ... en`.
+pub fn tuple(elems: Vec<Expr>) -> Box<Expr> {
+	let s = binder("s", &elems);
+	let mut e = var(&s);
+	for elem in elems {
+		e = app(e, Box::new(elem));
+	}
+	fun(&s, e)
+}
+
+/// Returns a name for a variable bound around the elements that does not
+/// capture any of their free variables.
+fn binder(base: &str, elems: &[Expr]) -> String {
+	let free: HashSet<&str> = elems.iter().flat_map(free_vars).collect();
+	fresh(base, &free)
+}
+
+fn var(x: &str) -> Box<Expr> {
+	Box::new(Expr::Var(x.to_owned(), Span::default()))
+}
+
+fn fun(x: &str, body: Box<Expr>) -> Box<Expr> {
+	Box::new(Expr::Fun(x.to_owned(), body, Span::default()))
+}
+
+fn app(f: Box<Expr>, a: Box<Expr>) -> Box<Expr> {
+	Box::new(Expr::App(f, a, Span::default()))
+}
+
+#[cfg(test)]
+mod literals_tests {
+	use crate::error::Error;
+	use crate::lex::{self, Span};
+	use crate::literals::{character, expand_numerals, string, NumeralEncoding, MAX_UNARY_NUMERAL};
+	use crate::parse::{self, Expr};
+
+	fn parse(e: &str) -> Box<Expr> {
+		let mut stream = lex::lex(e).unwrap();
+		parse::parse(&mut stream).unwrap()
+	}
+
+	fn expand(e: &str, encoding: NumeralEncoding) -> Box<Expr> {
+		expand_numerals(&parse(e), encoding).unwrap()
+	}
+
+	#[test]
+	fn expand_church() -> () {
+		assert_eq!(
+			parse("f (\\s.\\z.z) (\\s.\\z.s (s (s z)))"),
+			expand("f 0 3", NumeralEncoding::Church)
+		);
+	}
+
+	#[test]
+	fn expand_scott() -> () {
+		assert_eq!(
+			parse("\\s.\\z.s (\\s.\\z.s (\\s.\\z.z))"),
+			expand("2", NumeralEncoding::Scott)
+		);
+	}
+
+	#[test]
+	fn expand_binary() -> () {
+		let f = "\\t.\\f.f";
+		let t = "\\t.\\f.t";
+		let nil = "\\x.\\t.\\f.t";
+		assert_eq!(parse(nil), expand("0", NumeralEncoding::Binary));
+		assert_eq!(
+			parse(&format!("\\p.p ({f}) (\\p.p ({t}) (\\p.p ({t}) ({nil})))")),
+			expand("6", NumeralEncoding::Binary)
+		);
+	}
+
+	#[test]
+	fn expand_too_large() -> () {
+		// The error is at the first use of the numeral
+		let too_large = |n: &str, max, start: usize| {
+			let span = Span {
+				start,
+				end: start + n.len(),
+				line: 1,
+				column: start + 1,
+			};
+			Err(Error::NumeralTooLarge(n.to_owned(), max, span))
+		};
+		let e = parse("f 1000001 1000000 1000001");
+		assert_eq!(
+			too_large("1000001", MAX_UNARY_NUMERAL, 2),
+			expand_numerals(&e, NumeralEncoding::Church)
+		);
+		assert_eq!(
+			too_large("1000001", MAX_UNARY_NUMERAL, 2),
+			expand_numerals(&e, NumeralEncoding::Scott)
+		);
+		// Binary numerals only grow with the number of digits
+		assert!(expand_numerals(&e, NumeralEncoding::Binary).is_ok());
+		assert_eq!(
+			too_large("100000000000000000000", usize::MAX, 0),
+			expand_numerals(&parse("100000000000000000000"), NumeralEncoding::Binary)
+		);
+	}
+
+	#[test]
+	fn literals_too_large() -> () {
+		assert!(character('\u{F4240}').is_ok());
+		assert!(matches!(
+			character('\u{F4241}'),
+			Err(Error::SyntaxError(_, _))
+		));
+		assert!(matches!(
+			character('\u{10FFFF}'),
+			Err(Error::SyntaxError(_, _))
+		));
+		// Each character on its own is small enough, but not all of them
+		assert!(string(&"\u{FFFF}".repeat(15)).is_ok());
+		assert!(matches!(
+			string(&"\u{FFFF}".repeat(16)),
+			Err(Error::SyntaxError(_, _))
+		));
+		assert!(string(&"\0".repeat(1_000_001)).is_err());
+	}
+
+	#[test]
+	fn expand_only_free_numerals() -> () {
+		assert_eq!(
+			parse("(\\1.1) (\\s.\\z.s z) 01 x2"),
+			expand("(\\1.1) 1 01 x2", NumeralEncoding::Church)
+		);
+		assert_eq!(
+			parse("\\s.\\z.z"),
+			expand("0 where 0 = \\s.\\z.z", NumeralEncoding::Scott)
+		);
+	}
+}
@@ -0,0 +1,782 @@
+use std::cell::RefCell;
+use std::rc::Rc;
+
+use crate::debruijn::{DBExpr, Term};
+use crate::error::Error;
+use crate::eval::Strategy;
+
+/// A value on the machine. Values refer to subterms of the original
+/// expression and carry an environment instead of being substituted into.
+enum Value<'a> {
+	/// An argument that has not been evaluated yet (only created by the Krivine
+	/// machine). If sharing is enabled, its value is saved the first time it is
+	/// needed.
+	Thunk(Thunk<'a>),
+	/// A function abstraction. Contains the body of the function.
+	Lam(&'a DBExpr, Env<'a>),
+	/// A free variable, identified by its de Bruijn level, applied to some
+	/// arguments. These are created when reducing under a lambda.
+	Neutral(usize, Vec<Rc<Value<'a>>>),
+}
+
+struct Thunk<'a> {
+	term: &'a DBExpr,
+	env: Env<'a>,
+	/// The weak head normal form of the term, if it has already been
+	/// evaluated. This is never a thunk itself. The term and environment are
+	/// kept so that unevaluated parts of the result can be read back exactly
+	/// as they would appear without sharing.
+	value: RefCell<Option<Rc<Value<'a>>>>,
+}
+
+impl<'a> Value<'a> {
+	fn thunk(term: &'a DBExpr, env: Env<'a>) -> Rc<Value<'a>> {
+		Rc::new(Value::Thunk(Thunk {
+			term,
+			env,
+			value: RefCell::new(None),
+		}))
+	}
+}
+
+/// The values of the variables in scope. The value of the variable with de
+/// Bruijn index `i` is the `i`th element of the list.
+#[derive(Clone)]
+struct Env<'a>(Option<Rc<EnvNode<'a>>>);
+
+struct EnvNode<'a> {
+	value: Rc<Value<'a>>,
+	next: Env<'a>,
+}
+
+impl<'a> Env<'a> {
+	fn empty() -> Self {
+		Env(None)
+	}
+
+	fn push(&self, value: Rc<Value<'a>>) -> Self {
+		Env(Some(Rc::new(EnvNode {
+			value,
+			next: self.clone(),
+		})))
+	}
+
+	fn get(&self, i: usize) -> &Rc<Value<'a>> {
+		let mut node = self.0.as_ref();
+		for _ in 0..i {
+			node = node.and_then(|n| n.next.0.as_ref());
+		}
+		match node {
+			Some(n) => &n.value,
+			None => panic!("Free variable {i} in expression"),
+		}
+	}
+}
+
+/// A value or environment that is being freed (see `free`).
+enum Garbage<'a> {
+	Value(Rc<Value<'a>>),
+	Env(Rc<EnvNode<'a>>),
+}
+
+impl<'a> Value<'a> {
+	/// Moves the values and environments that this value refers to into
+	/// `garbage`, leaving it with none.
+	fn unlink(&mut self, garbage: &mut Vec<Garbage<'a>>) {
+		match self {
+			Value::Thunk(th) => {
+				garbage.extend(th.env.0.take().map(Garbage::Env));
+				garbage.extend(th.value.get_mut().take().map(Garbage::Value));
+			}
+			Value::Lam(_, env) => garbage.extend(env.0.take().map(Garbage::Env)),
+			Value::Neutral(_, args) => garbage.extend(args.drain(..).map(Garbage::Value)),
+		}
+	}
+}
+
+/// Frees the values and environments that are no longer used with an explicit
+/// stack, since long chains of thunks and environments would overflow the
+/// stack if they were dropped recursively.
+fn free(mut garbage: Vec<Garbage>) {
+	while let Some(g) = garbage.pop() {
+		match g {
+			Garbage::Value(v) => {
+				if let Some(mut v) = Rc::into_inner(v) {
+					v.unlink(&mut garbage);
+				}
+			}
+			Garbage::Env(node) => {
+				if let Some(EnvNode { value, mut next }) = Rc::into_inner(node) {
+					garbage.push(Garbage::Value(value));
+					garbage.extend(next.0.take().map(Garbage::Env));
+				}
+			}
+		}
+	}
+}
+
+impl Drop for Value<'_> {
+	fn drop(&mut self) {
+		let mut garbage = Vec::new();
+		self.unlink(&mut garbage);
+		free(garbage);
+	}
+}
+
+impl Drop for Env<'_> {
+	fn drop(&mut self) {
+		free(self.0.take().map(Garbage::Env).into_iter().collect());
+	}
+}
+
+/// State shared by all the machines during one evaluation.
+struct Machine {
+	steps: usize,
+	max_steps: Option<usize>,
+	/// Whether thunks are updated with their value once evaluated.
+	sharing: bool,
+}
+
+impl Machine {
+	/// Records a beta reduction. Returns false if no more reductions are
+	/// allowed.
+	fn consume(&mut self) -> bool {
+		if Some(self.steps) == self.max_steps {
+			return false;
+		}
+		self.steps += 1;
+		true
+	}
+}
+
+/// Evaluation ran out of fuel. Contains the partially reduced expression.
+struct OutOfFuel(Term);
+
+/// Evaluates the expression using an abstract machine: the Krivine machine for
+/// call-by-name (and, by reducing under lambdas, normal order and head
+/// reduction) or the CEK machine for call-by-value. The result is the same as
+/// `DBExpr::eval_with_strategy`, but arguments are kept in environments rather
+/// than being substituted into the term at every step.
+///
+/// If `sharing` is enabled, each argument is evaluated at most once and its
+/// value is shared between all the places where it is used (i.e., call-by-need
+/// graph reduction). This does not change the result, but can save many steps.
+pub fn eval(
+	e: &DBExpr,
+	strategy: Strategy,
+	max_steps: Option<usize>,
+	sharing: bool,
+) -> Result<Term, Error> {
+	let mut m = Machine {
+		steps: 0,
+		max_steps,
+		sharing,
+	};
+	let result = match strategy {
+		Strategy::ApplicativeOrder => {
+			return Err(Error::UnsupportedStrategy(
+				"the abstract machine does not support applicative order".to_owned(),
+			))
+		}
+		Strategy::CompleteDevelopment => {
+			return Err(Error::UnsupportedStrategy(
+				"the abstract machine does not support complete development".to_owned(),
+			))
+		}
+		Strategy::CallByValue => cek(e, Env::empty(), 0, &mut m).map(|v| quote(&v, 0)),
+		Strategy::NormalOrder
+		| Strategy::CallByName
+		| Strategy::CallByNeed
+		| Strategy::HeadReduction => {
+			krivine(e, Env::empty(), 0, &mut m).and_then(|v| normalize(&v, 0, strategy, &mut m))
+		}
+	};
+	result.map_err(|OutOfFuel(partial)| Error::StepLimitExceeded(m.steps, partial))
+}
+
+/// An entry on the Krivine machine's stack.
+enum StackItem<'a> {
+	/// An argument to the current term.
+	Arg(Rc<Value<'a>>),
+	/// Once the current term is evaluated, save the result in this thunk.
+	Update(Rc<Value<'a>>),
+}
+
+/// Runs the Krivine machine until the term reaches weak head normal form.
+/// `depth` is the number of lambdas we are currently under.
+fn krivine<'a>(
+	term: &'a DBExpr,
+	env: Env<'a>,
+	depth: usize,
+	m: &mut Machine,
+) -> Result<Rc<Value<'a>>, OutOfFuel> {
+	let (mut term, mut env) = (term, env);
+	// The arguments the current term is applied to, with the first argument
+	// at the end
+	let mut stack: Vec<StackItem<'a>> = Vec::new();
+	loop {
+		let mut v = match term {
+			DBExpr::App(f, a) => {
+				// Pass variables on as they are, rather than as a thunk that
+				// looks them up, so that lookups do not go through longer and
+				// longer chains of thunks
+				let arg = match a.as_ref() {
+					DBExpr::Var(i) => env.get(*i).clone(),
+					_ => Value::thunk(a, env.clone()),
+				};
+				stack.push(StackItem::Arg(arg));
+				term = f;
+				continue;
+			}
+			DBExpr::Fun(body) => Rc::new(Value::Lam(body, env.clone())),
+			DBExpr::Var(i) => {
+				let v = env.get(*i).clone();
+				match v.as_ref() {
+					Value::Thunk(th) => match &*th.value.borrow() {
+						Some(w) => w.clone(),
+						None => {
+							term = th.term;
+							env = th.env.clone();
+							if m.sharing {
+								stack.push(StackItem::Update(v.clone()));
+							}
+							continue;
+						}
+					},
+					_ => v,
+				}
+			}
+		};
+		// The current term is in weak head normal form, so apply it to the
+		// arguments on the stack
+		loop {
+			match stack.pop() {
+				None => return Ok(v),
+				Some(StackItem::Update(th)) => {
+					if let Value::Thunk(th) = th.as_ref() {
+						*th.value.borrow_mut() = Some(v.clone());
+					}
+				}
+				Some(StackItem::Arg(arg)) => match v.as_ref() {
+					Value::Lam(body, e) => {
+						if !m.consume() {
+							let redex =
+								Term::new(DBExpr::App(quote(&v, depth), quote(&arg, depth)));
+							return Err(OutOfFuel(plug_stack(redex, &stack, depth)));
+						}
+						env = e.push(arg);
+						term = body;
+						break;
+					}
+					Value::Neutral(l, args) => {
+						let mut all_args = args.clone();
+						all_args.push(arg);
+						v = Rc::new(Value::Neutral(*l, all_args));
+					}
+					Value::Thunk(_) => panic!("Unexpected thunk in weak head normal form"),
+				},
+			}
+		}
+	}
+}
+
+/// Puts the given expression back into the context described by the Krivine
+/// machine's stack.
+fn plug_stack(e: Term, stack: &[StackItem], depth: usize) -> Term {
+	stack.iter().rev().fold(e, |e, item| match item {
+		StackItem::Arg(a) => Term::new(DBExpr::App(e, quote(a, depth))),
+		StackItem::Update(_) => e,
+	})
+}
+
+/// A pending step of the CEK machine.
+enum Frame<'a> {
+	/// Evaluate this argument next.
+	Arg(&'a DBExpr, Env<'a>),
+	/// Apply this function to the value that was just computed.
+	Call(Rc<Value<'a>>),
+}
+
+/// Runs the CEK machine until the term reaches a value.
+/// `depth` is the number of lambdas we are currently under.
+fn cek<'a>(
+	term: &'a DBExpr,
+	env: Env<'a>,
+	depth: usize,
+	m: &mut Machine,
+) -> Result<Rc<Value<'a>>, OutOfFuel> {
+	let (mut term, mut env) = (term, env);
+	let mut frames: Vec<Frame<'a>> = Vec::new();
+	loop {
+		let mut v = loop {
+			match term {
+				DBExpr::App(f, a) => {
+					frames.push(Frame::Arg(a, env.clone()));
+					term = f;
+				}
+				DBExpr::Fun(body) => break Rc::new(Value::Lam(body, env.clone())),
+				DBExpr::Var(i) => break env.get(*i).clone(),
+			}
+		};
+		loop {
+			match frames.pop() {
+				None => return Ok(v),
+				Some(Frame::Arg(a, e)) => {
+					frames.push(Frame::Call(v));
+					term = a;
+					env = e;
+					break;
+				}
+				Some(Frame::Call(f)) => match f.as_ref() {
+					Value::Lam(body, e) => {
+						if !m.consume() {
+							let redex = Term::new(DBExpr::App(quote(&f, depth), quote(&v, depth)));
+							return Err(OutOfFuel(plug(redex, &frames, depth)));
+						}
+						term = body;
+						env = e.push(v);
+						break;
+					}
+					Value::Neutral(l, args) => {
+						let mut all_args = args.clone();
+						all_args.push(v);
+						v = Rc::new(Value::Neutral(*l, all_args));
+					}
+					Value::Thunk(_) => panic!("Unexpected thunk in the CEK machine"),
+				},
+			}
+		}
+	}
+}
+
+/// Puts the given expression back into the context described by the CEK
+/// machine's frames.
+fn plug(e: Term, frames: &[Frame], depth: usize) -> Term {
+	frames.iter().rev().fold(e, |e, frame| match frame {
+		Frame::Arg(a, env) => Term::new(DBExpr::App(e, quote_term(a, env, depth))),
+		Frame::Call(f) => Term::new(DBExpr::App(quote(f, depth), e)),
+	})
+}
+
+/// A pending step of `normalize`, for the part of the result around the
+/// value that is being normalized.
+enum Context<'a> {
+	/// Wrap the result in a function abstraction.
+	Fun,
+	/// Apply `head` to the result, then normalize the rest of the arguments.
+	/// `next` is the index of the argument that is being normalized.
+	Args {
+		head: Term,
+		args: Vec<Rc<Value<'a>>>,
+		next: usize,
+		depth: usize,
+	},
+}
+
+/// Reads back a value in weak head normal form, reducing further as required
+/// by the strategy. Uses an explicit stack since normal forms can be deep.
+fn normalize<'a>(
+	v: &Rc<Value<'a>>,
+	depth: usize,
+	strategy: Strategy,
+	m: &mut Machine,
+) -> Result<Term, OutOfFuel> {
+	let mut contexts: Vec<Context<'a>> = Vec::new();
+	let (mut v, mut depth) = (v.clone(), depth);
+	loop {
+		let w = match force(&v, depth, m) {
+			Ok(w) => w,
+			Err(OutOfFuel(partial)) => return Err(OutOfFuel(unwind(partial, contexts))),
+		};
+		let mut result = match w.as_ref() {
+			Value::Lam(body, env) if strategy.reduces_under_lambda() => {
+				let env = env.push(Rc::new(Value::Neutral(depth, Vec::new())));
+				contexts.push(Context::Fun);
+				v = Value::thunk(body, env);
+				depth += 1;
+				continue;
+			}
+			Value::Neutral(l, args) if strategy.reduces_arguments() && !args.is_empty() => {
+				contexts.push(Context::Args {
+					head: Term::new(DBExpr::Var(depth - 1 - l)),
+					args: args.clone(),
+					next: 0,
+					depth,
+				});
+				v = args[0].clone();
+				continue;
+			}
+			_ => quote(&w, depth),
+		};
+		// Put the result back into its context until there is another value
+		// to normalize
+		loop {
+			match contexts.pop() {
+				None => return Ok(result),
+				Some(Context::Fun) => result = Term::new(DBExpr::Fun(result)),
+				Some(Context::Args {
+					head,
+					args,
+					next,
+					depth: d,
+				}) => {
+					let head = Term::new(DBExpr::App(head, result));
+					if next + 1 == args.len() {
+						result = head;
+					} else {
+						(v, depth) = (args[next + 1].clone(), d);
+						contexts.push(Context::Args {
+							head,
+							args,
+							next: next + 1,
+							depth,
+						});
+						break;
+					}
+				}
+			}
+		}
+	}
+}
+
+/// Puts a partial result back into the contexts of `normalize`, leaving the
+/// arguments that were not normalized yet as they are.
+fn unwind(partial: Term, contexts: Vec<Context>) -> Term {
+	contexts
+		.into_iter()
+		.rev()
+		.fold(partial, |e, context| match context {
+			Context::Fun => Term::new(DBExpr::Fun(e)),
+			Context::Args {
+				head,
+				args,
+				next,
+				depth,
+			} => args[next + 1..]
+				.iter()
+				.fold(Term::new(DBExpr::App(head, e)), |e, a| {
+					Term::new(DBExpr::App(e, quote(a, depth)))
+				}),
+		})
+}
+
+/// Evaluates a value to weak head normal form. If sharing is enabled and the
+/// value is a thunk, the thunk is updated with the result.
+fn force<'a>(v: &Rc<Value<'a>>, depth: usize, m: &mut Machine) -> Result<Rc<Value<'a>>, OutOfFuel> {
+	let th = match v.as_ref() {
+		Value::Thunk(th) => th,
+		_ => return Ok(v.clone()),
+	};
+	if let Some(w) = &*th.value.borrow() {
+		return Ok(w.clone());
+	}
+	let w = krivine(th.term, th.env.clone(), depth, m)?;
+	if m.sharing {
+		*th.value.borrow_mut() = Some(w.clone());
+	}
+	Ok(w)
+}
+
+/// A pending step of reading back a value (see `read_back`).
+enum ReadBack<'a> {
+	Value(Rc<Value<'a>>, usize),
+	Term(&'a DBExpr, Env<'a>, usize),
+	/// Wrap the last result in a function abstraction.
+	Fun,
+	/// Apply the second to last result to the last one.
+	App,
+}
+
+/// Converts a value back into an expression without reducing it.
+fn quote(v: &Rc<Value>, depth: usize) -> Term {
+	read_back(ReadBack::Value(v.clone(), depth))
+}
+
+/// Substitutes the environment into the term without reducing it.
+fn quote_term<'a>(term: &'a DBExpr, env: &Env<'a>, depth: usize) -> Term {
+	read_back(ReadBack::Term(term, env.clone(), depth))
+}
+
+/// Reads back a value or a term in an environment with an explicit stack,
+/// since values can be nested too deeply to do it recursively.
+fn read_back(item: ReadBack) -> Term {
+	let mut todo = vec![item];
+	let mut results: Vec<Term> = Vec::new();
+	while let Some(item) = todo.pop() {
+		match item {
+			ReadBack::Value(v, depth) => match v.as_ref() {
+				Value::Thunk(th) => todo.push(ReadBack::Term(th.term, th.env.clone(), depth)),
+				Value::Lam(body, env) => {
+					let env = env.push(Rc::new(Value::Neutral(depth, Vec::new())));
+					todo.push(ReadBack::Fun);
+					todo.push(ReadBack::Term(body, env, depth + 1));
+				}
+				Value::Neutral(l, args) => {
+					results.push(Term::new(DBExpr::Var(depth - 1 - l)));
+					for a in args.iter().rev() {
+						todo.push(ReadBack::App);
+						todo.push(ReadBack::Value(a.clone(), depth));
+					}
+				}
+			},
+			ReadBack::Term(DBExpr::Var(i), env, depth) => {
+				todo.push(ReadBack::Value(env.get(*i).clone(), depth))
+			}
+			ReadBack::Term(DBExpr::Fun(body), env, depth) => {
+				let env = env.push(Rc::new(Value::Neutral(depth, Vec::new())));
+				todo.push(ReadBack::Fun);
+				todo.push(ReadBack::Term(body, env, depth + 1));
+			}
+			ReadBack::Term(DBExpr::App(f, a), env, depth) => {
+				todo.push(ReadBack::App);
+				todo.push(ReadBack::Term(a, env.clone(), depth));
+				todo.push(ReadBack::Term(f, env, depth));
+			}
+			ReadBack::Fun => {
+				let body = results.pop().unwrap();
+				results.push(Term::new(DBExpr::Fun(body)));
+			}
+			ReadBack::App => {
+				let a = results.pop().unwrap();
+				let f = results.pop().unwrap();
+				results.push(Term::new(DBExpr::App(f, a)));
+			}
+		}
+	}
+	results.pop().unwrap()
+}
+
+#[cfg(test)]
+mod machine_tests {
+	use crate::debruijn::Term;
+	use crate::error::Error;
+	use crate::eval::Strategy;
+	use crate::lex;
+	use crate::machine;
+	use crate::parse;
+
+	fn parse(e: &str) -> Term {
+		let mut stream = lex::lex(e).unwrap();
+		parse::parse(&mut stream).unwrap().to_debruijn()
+	}
+
+	const DEFS: &str = "
+		where    + = \\m.\\n.\\s.\\z.m s (n s z)
+		where    * = \\m.\\n.\\s.m (n s)
+		where    ^ = \\m.\\n.n m
+		where pred = \\n.\\f.\\x.n (\\g.\\h.h (g f)) (\\u.x) (\\u.u)
+		where succ = \\n.\\s.\\z.s (n s z)
+		where    K = \\x.\\y.x
+		where    S = \\x.\\y.\\z.x z (y z)
+		where    Y = \\f.(\\x.f (x x)) (\\x.f (x x))
+		where    W = (\\x.x x) (\\x.x x)
+		where    0 = \\s.\\z.z
+		where    2 = \\s.\\z.s (s z)
+		where    3 = \\s.\\z.s (s (s z))";
+
+	const TERMS: [&str; 13] = [
+		"\\x.x",
+		"(\\x.x) (\\y.y)",
+		"\\x.(\\y.y) x",
+		"\\x.x ((\\y.y) x)",
+		"(\\x.\\y.x) ((\\z.z) (\\z.z))",
+		"K (\\x.x) W",
+		"S K K",
+		"succ 0",
+		"+ 2 3",
+		"* 2 3",
+		"^ 2 3",
+		"pred 3",
+		"Y (\\f.\\n.n (\\x.f x) 0) 0",
+	];
+
+	const STRATEGIES: [Strategy; 5] = [
+		Strategy::NormalOrder,
+		Strategy::CallByName,
+		Strategy::CallByValue,
+		Strategy::CallByNeed,
+		Strategy::HeadReduction,
+	];
+
+	#[test]
+	fn machine_matches_substitution() -> () {
+		for t in TERMS {
+			let e = parse(&format!("{t} {DEFS}"));
+			for s in STRATEGIES {
+				match e.eval_with_strategy(s, Some(1000)) {
+					Ok(expected) => {
+						assert_eq!(
+							Ok(expected),
+							machine::eval(&e, s, Some(1000), false),
+							"{t} ({s:?})"
+						)
+					}
+					Err(_) => assert!(
+						machine::eval(&e, s, Some(1000), false).is_err(),
+						"{t} ({s:?})"
+					),
+				}
+			}
+		}
+	}
+
+	#[test]
+	fn machine_discarded_omega() -> () {
+		let e = parse(&format!("K (\\x.x) W {DEFS}"));
+		assert_eq!(
+			Ok(parse("\\x.x")),
+			machine::eval(&e, Strategy::CallByName, None, false)
+		);
+		assert!(matches!(
+			machine::eval(&e, Strategy::CallByValue, Some(100), false),
+			Err(Error::StepLimitExceeded(100, _))
+		));
+	}
+
+	#[test]
+	fn machine_long_omega() -> () {
+		// Each step must take constant time for this to finish quickly
+		let e = parse("(\\x.x x) (\\x.x x)");
+		for s in [Strategy::NormalOrder, Strategy::CallByName] {
+			assert_eq!(
+				Err(Error::StepLimitExceeded(100000, e.clone())),
+				machine::eval(&e, s, Some(100000), false)
+			);
+		}
+	}
+
+	#[test]
+	fn machine_deep_normal_form() -> () {
+		// 2^17, which is too deep to be normalized or read back recursively
+		let e = parse(&format!(
+			"{}1{} where D = \\n.\\s.\\z.n s (n s z) where 1 = \\s.\\z.s z",
+			"D (".repeat(17),
+			")".repeat(17)
+		));
+		let expected = format!(
+			"\\a.\\b.{}a b{}",
+			"a (".repeat(131_071),
+			")".repeat(131_071)
+		);
+		for sharing in [false, true] {
+			let nf = machine::eval(&e, Strategy::NormalOrder, None, sharing).unwrap();
+			assert_eq!(expected, nf.to_named().to_string());
+		}
+	}
+
+	#[test]
+	fn machine_partial_result_krivine() -> () {
+		// (\x.x) ((\x.x) (\y.y))
+		let e = parse("(\\x.x) ((\\x.x) (\\y.y))");
+		assert_eq!(
+			Err(Error::StepLimitExceeded(0, e.clone())),
+			machine::eval(&e, Strategy::NormalOrder, Some(0), false)
+		);
+		assert_eq!(
+			Err(Error::StepLimitExceeded(1, parse("(\\x.x) (\\y.y)"))),
+			machine::eval(&e, Strategy::NormalOrder, Some(1), false)
+		);
+	}
+
+	#[test]
+	fn machine_partial_result_under_lambda() -> () {
+		let e = parse("\\a.a ((\\x.x) a) ((\\x.x) a)");
+		assert_eq!(
+			Err(Error::StepLimitExceeded(1, parse("\\a.a a ((\\x.x) a)"))),
+			machine::eval(&e, Strategy::NormalOrder, Some(1), false)
+		);
+	}
+
+	#[test]
+	fn machine_partial_result_cek() -> () {
+		let e = parse("(\\x.x) ((\\x.x) (\\y.y))");
+		assert_eq!(
+			Err(Error::StepLimitExceeded(0, e.clone())),
+			machine::eval(&e, Strategy::CallByValue, Some(0), false)
+		);
+		assert_eq!(
+			Err(Error::StepLimitExceeded(1, parse("(\\x.x) (\\y.y)"))),
+			machine::eval(&e, Strategy::CallByValue, Some(1), false)
+		);
+	}
+
+	#[test]
+	fn machine_applicative_order_unsupported() -> () {
+		assert!(matches!(
+			machine::eval(&parse("\\x.x"), Strategy::ApplicativeOrder, None, false),
+			Err(Error::UnsupportedStrategy(_))
+		));
+	}
+
+	#[test]
+	fn sharing_matches_substitution() -> () {
+		for t in TERMS {
+			let e = parse(&format!("{t} {DEFS}"));
+			for s in STRATEGIES {
+				match e.eval_with_strategy(s, Some(1000)) {
+					Ok(expected) => assert_eq!(
+						Ok(expected),
+						machine::eval(&e, s, Some(1000), true),
+						"{t} ({s:?})"
+					),
+					Err(_) => assert!(
+						machine::eval(&e, s, Some(1000), true).is_err(),
+						"{t} ({s:?})"
+					),
+				}
+			}
+		}
+	}
+
+	#[test]
+	fn sharing_evaluates_argument_once() -> () {
+		// Without sharing, (\y.y) (\z.z) is reduced twice
+		let e = parse("(\\x.\\f.f x x) ((\\y.y) (\\z.z))");
+		let expected = parse("\\f.f (\\z.z) (\\z.z)");
+		assert_eq!(
+			Ok(expected.clone()),
+			machine::eval(&e, Strategy::NormalOrder, Some(2), true)
+		);
+		assert!(machine::eval(&e, Strategy::NormalOrder, Some(2), false).is_err());
+		assert_eq!(
+			Ok(expected),
+			machine::eval(&e, Strategy::NormalOrder, Some(3), false)
+		);
+	}
+
+	#[test]
+	fn sharing_partial_result() -> () {
+		let e = parse("(\\x.x x) ((\\y.y) (\\z.z))");
+		assert_eq!(
+			Err(Error::StepLimitExceeded(
+				1,
+				parse("(\\y.y) (\\z.z) ((\\y.y) (\\z.z))")
+			)),
+			machine::eval(&e, Strategy::CallByNeed, Some(1), true)
+		);
+	}
+
+	#[test]
+	fn sharing_long_omega() -> () {
+		let e = parse("(\\x.x x) (\\x.x x)");
+		for s in [Strategy::NormalOrder, Strategy::CallByNeed] {
+			assert_eq!(
+				Err(Error::StepLimitExceeded(50000, e.clone())),
+				machine::eval(&e, s, Some(50000), true)
+			);
+		}
+	}
+
+	#[test]
+	fn sharing_large_numerals() -> () {
+		let e = parse(&format!("* (* 3 3) (+ 3 (* 3 3)) {DEFS}"));
+		let mut expected = "z".to_owned();
+		for _ in 0..(9 * 12) {
+			expected = format!("s ({expected})");
+		}
+		assert_eq!(
+			Ok(parse(&format!("\\s.\\z.{expected}"))),
+			machine::eval(&e, Strategy::NormalOrder, None, true)
+		);
+	}
+}
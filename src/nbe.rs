@@ -0,0 +1,140 @@
+use crate::debruijn::{DBExpr, Term};
+use crate::error::Error;
+use crate::eval::Strategy;
+use crate::machine;
+
+/// Computes the normal form of the expression using normalization by
+/// evaluation. The expression is evaluated into the semantic domain of the
+/// abstract machine (see `machine::eval`), in which functions are closures
+/// (the body of the function and the environment it was defined in) and free
+/// variables applied to arguments are neutral values, identified by their de
+/// Bruijn level. The normal form is then read back by applying each closure to
+/// a fresh neutral variable. Arguments are evaluated lazily and at most once,
+/// so the result is the same as for normal order.
+///
+/// Only normal order is supported.
+pub fn eval(e: &DBExpr, strategy: Strategy, max_steps: Option<usize>) -> Result<Term, Error> {
+	if strategy != Strategy::NormalOrder {
+		return Err(Error::UnsupportedStrategy(
+			"normalization by evaluation only supports normal order".to_owned(),
+		));
+	}
+	machine::eval(e, strategy, max_steps, true)
+}
+
+#[cfg(test)]
+mod nbe_tests {
+	use crate::debruijn::Term;
+	use crate::error::Error;
+	use crate::eval::Strategy;
+	use crate::lex;
+	use crate::nbe;
+	use crate::parse;
+
+	fn parse(e: &str) -> Term {
+		let mut stream = lex::lex(e).unwrap();
+		parse::parse(&mut stream).unwrap().to_debruijn()
+	}
+
+	const DEFS: &str = "
+		where    + = \\m.\\n.\\s.\\z.m s (n s z)
+		where    * = \\m.\\n.\\s.m (n s)
+		where    ^ = \\m.\\n.n m
+		where pred = \\n.\\f.\\x.n (\\g.\\h.h (g f)) (\\u.x) (\\u.u)
+		where succ = \\n.\\s.\\z.s (n s z)
+		where    K = \\x.\\y.x
+		where    S = \\x.\\y.\\z.x z (y z)
+		where    Y = \\f.(\\x.f (x x)) (\\x.f (x x))
+		where    W = (\\x.x x) (\\x.x x)
+		where    0 = \\s.\\z.z
+		where    2 = \\s.\\z.s (s z)
+		where    3 = \\s.\\z.s (s (s z))";
+
+	#[test]
+	fn nbe_matches_eval() -> () {
+		let terms = [
+			"\\x.x",
+			"(\\x.x) (\\y.y)",
+			"\\x.(\\y.y) x",
+			"\\x.x ((\\y.y) x)",
+			"(\\x.\\y.x) ((\\z.z) (\\z.z))",
+			"\\x.(\\y.\\z.y z) x",
+			"K (\\x.x) W",
+			"S K K",
+			"succ 0",
+			"+ 2 3",
+			"* 2 3",
+			"^ 2 3",
+			"^ 3 2",
+			"pred 3",
+			"Y (\\f.\\n.n (\\x.f x) 0) 0",
+		];
+		for t in terms {
+			let e = parse(&format!("{t} {DEFS}"));
+			assert_eq!(
+				Ok(e.eval()),
+				nbe::eval(&e, Strategy::NormalOrder, None),
+				"{t}"
+			);
+		}
+	}
+
+	#[test]
+	fn nbe_large_numeral() -> () {
+		let e = parse(&format!("^ 2 (+ 3 (* 2 3)) {DEFS}"));
+		let mut expected = "z".to_owned();
+		for _ in 0..512 {
+			expected = format!("s ({expected})");
+		}
+		assert_eq!(
+			Ok(parse(&format!("\\s.\\z.{expected}"))),
+			nbe::eval(&e, Strategy::NormalOrder, None)
+		);
+	}
+
+	#[test]
+	fn nbe_step_limit() -> () {
+		let e = parse(&format!("W {DEFS}"));
+		assert_eq!(
+			Err(Error::StepLimitExceeded(100, e.clone())),
+			nbe::eval(&e, Strategy::NormalOrder, Some(100))
+		);
+		let e = parse("(\\x.x) (\\y.y)");
+		assert!(nbe::eval(&e, Strategy::NormalOrder, Some(0)).is_err());
+		assert!(nbe::eval(&e, Strategy::NormalOrder, Some(1)).is_ok());
+	}
+
+	#[test]
+	fn nbe_long_omega() -> () {
+		let e = parse(&format!("W {DEFS}"));
+		assert_eq!(
+			Err(Error::StepLimitExceeded(100000, e.clone())),
+			nbe::eval(&e, Strategy::NormalOrder, Some(100000))
+		);
+	}
+
+	#[test]
+	fn nbe_deep_normal_form() -> () {
+		// 2^17, which is too deep to be read back recursively
+		let e = parse(&format!(
+			"{}1{} where D = \\n.\\s.\\z.n s (n s z) where 1 = \\s.\\z.s z",
+			"D (".repeat(17),
+			")".repeat(17)
+		));
+		let expected = format!(
+			"\\a.\\b.{}a b{}",
+			"a (".repeat(131_071),
+			")".repeat(131_071)
+		);
+		let nf = nbe::eval(&e, Strategy::NormalOrder, None).unwrap();
+		assert_eq!(expected, nf.to_named().to_string());
+	}
+
+	#[test]
+	fn nbe_unsupported_strategy() -> () {
+		assert!(matches!(
+			nbe::eval(&parse("\\x.x"), Strategy::CallByName, None),
+			Err(Error::UnsupportedStrategy(_))
+		));
+	}
+}
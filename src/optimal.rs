@@ -0,0 +1,638 @@
+//! An experimental implementation of Lamping's algorithm for optimal
+//! reduction.
+//!
+//! The expression is translated into a sharing graph (an interaction net made
+//! of lambda and application nodes plus the "control" nodes that keep track of
+//! sharing: fans, croissants and brackets). Each node has an index, which is
+//! the number of arguments it is nested in. Arguments are never copied as a
+//! whole; instead, fans duplicate nodes one at a time and only when needed, so
+//! that no redex is ever duplicated.
+//!
+//! The normal form is read back by replacing each lambda found at the head by
+//! an atom for its variable, and each application whose function is a
+//! variable by a "neutral" node. These nodes are duplicated by fans like any
+//! other node, so that reading back the graph unfolds all remaining sharing.
+
+use crate::debruijn::{DBExpr, Term};
+use crate::error::Error;
+use crate::eval::{EvalStats, Strategy, Timer};
+
+/// The result of optimal reduction, along with its cost.
+#[derive(Clone, Debug, PartialEq)]
+pub struct Reduction {
+	/// The normal form.
+	pub expr: Term,
+	/// The number of interactions (i.e., graph rewrites) performed, including
+	/// those needed to read back the normal form.
+	pub interactions: usize,
+	/// The number of interactions that were beta reductions.
+	pub beta_steps: usize,
+}
+
+/// Computes the normal form using optimal reduction. The step limit applies
+/// to the number of interactions. Since the graph cannot be read back before
+/// reduction is done, the error contains the original expression if the limit
+/// is reached.
+///
+/// Only normal order is supported.
+pub fn eval(e: &DBExpr, strategy: Strategy, max_steps: Option<usize>) -> Result<Term, Error> {
+	check_strategy(strategy)?;
+	Ok(reduce(e, max_steps)?.expr)
+}
+
+/// Like `eval`, but also measures the cost of evaluation. The interactions are
+/// counted along with the beta reductions, but not the substitutions. Since the
+/// graph is only read back at the end, the sizes are those of the original
+/// expression and of the normal form.
+pub fn eval_with_stats(
+	e: &DBExpr,
+	strategy: Strategy,
+	max_steps: Option<usize>,
+) -> Result<(Term, EvalStats), Error> {
+	check_strategy(strategy)?;
+	let timer = Timer::start();
+	let mut stats = EvalStats::default();
+	stats.record(&Term::new(e.clone()));
+	let Reduction {
+		expr,
+		interactions,
+		beta_steps,
+	} = reduce(e, max_steps)?;
+	stats.record(&expr);
+	stats.beta_reductions = beta_steps;
+	stats.interactions = interactions;
+	stats.final_size = expr.size();
+	stats.time_ms = timer.elapsed_ms();
+	Ok((expr, stats))
+}
+
+fn check_strategy(strategy: Strategy) -> Result<(), Error> {
+	if strategy != Strategy::NormalOrder {
+		return Err(Error::UnsupportedStrategy(
+			"optimal reduction only supports normal order".to_owned(),
+		));
+	}
+	Ok(())
+}
+
+/// Computes the normal form using optimal reduction and reports the number of
+/// interactions needed. Gives up after `max_interactions` interactions, if set.
+pub fn reduce(e: &DBExpr, max_interactions: Option<usize>) -> Result<Reduction, Error> {
+	let mut net = Net {
+		nodes: Vec::new(),
+		free: Vec::new(),
+		interactions: 0,
+		beta_steps: 0,
+		max_interactions,
+	};
+	let root = net.alloc(Kind::Root, 0);
+	let mut binders = Vec::new();
+	let top = net.build(e, 0, &mut binders);
+	net.link(Port::new(root, 0), top);
+	match net.read(root, 0) {
+		Ok(expr) => Ok(Reduction {
+			expr,
+			interactions: net.interactions,
+			beta_steps: net.beta_steps,
+		}),
+		Err(OutOfFuel) => Err(Error::StepLimitExceeded(
+			net.interactions,
+			Term::new(e.clone()),
+		)),
+	}
+}
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+enum Kind {
+	/// The place where a term is being read back. Its only port is 0.
+	Root,
+	/// Ports: 0 is the lambda itself, 1 is the body, and 2 is the variable.
+	Lam,
+	/// Ports: 0 is the function, 1 is the argument, and 2 is the result.
+	App,
+	/// Ports: 0 is the shared side, 1 and 2 are the two copies.
+	Fan,
+	/// Decrements the index of the nodes it crosses.
+	Croissant,
+	/// Increments the index of the nodes it crosses.
+	Bracket,
+	/// Deletes the nodes it meets.
+	Eraser,
+	/// A variable found during readback, identified by its de Bruijn level.
+	Atom(usize),
+	/// An application whose function is an atom or another neutral node, found
+	/// during readback. Ports: 0 is the result, 1 is the function, and 2 is
+	/// the argument.
+	Neutral,
+}
+
+impl Kind {
+	fn aux_ports(self) -> usize {
+		match self {
+			Kind::Lam | Kind::App | Kind::Fan | Kind::Neutral => 2,
+			Kind::Croissant | Kind::Bracket => 1,
+			Kind::Root | Kind::Eraser | Kind::Atom(_) => 0,
+		}
+	}
+
+	fn is_control(self) -> bool {
+		matches!(self, Kind::Fan | Kind::Croissant | Kind::Bracket)
+	}
+
+	fn is_readback(self) -> bool {
+		matches!(self, Kind::Atom(_) | Kind::Neutral)
+	}
+}
+
+/// A pending step of `Net::build`.
+enum Build<'e> {
+	/// Translate this expression at the given level.
+	Expr(&'e DBExpr, usize),
+	/// Finish the lambda whose body was just translated.
+	Lam(usize, usize),
+	/// Translate the argument of the application whose function was just
+	/// translated.
+	Arg(usize, &'e DBExpr, usize),
+	/// Finish the application whose argument was just translated. Contains the
+	/// number of occurrences of each variable before the argument.
+	App(usize, Vec<usize>, usize),
+}
+
+/// A part of the normal form around the term that `Net::read` is reading back.
+enum Read {
+	/// Wrap the result in a function abstraction.
+	Fun,
+	/// Read back the argument connected to this root at the given depth, then
+	/// apply the result to it.
+	Arg(usize, usize),
+	/// Apply this function to the result.
+	App(Term),
+}
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+struct Port {
+	node: usize,
+	slot: usize,
+}
+
+impl Port {
+	fn new(node: usize, slot: usize) -> Self {
+		Port { node, slot }
+	}
+
+	const UNLINKED: Port = Port {
+		node: usize::MAX,
+		slot: 0,
+	};
+}
+
+struct Node {
+	kind: Kind,
+	index: usize,
+	ports: [Port; 3],
+}
+
+struct Net {
+	nodes: Vec<Node>,
+	free: Vec<usize>,
+	interactions: usize,
+	beta_steps: usize,
+	max_interactions: Option<usize>,
+}
+
+struct OutOfFuel;
+
+impl Net {
+	fn alloc(&mut self, kind: Kind, index: usize) -> usize {
+		let node = Node {
+			kind,
+			index,
+			ports: [Port::UNLINKED; 3],
+		};
+		match self.free.pop() {
+			Some(n) => {
+				self.nodes[n] = node;
+				n
+			}
+			None => {
+				self.nodes.push(node);
+				self.nodes.len() - 1
+			}
+		}
+	}
+
+	fn link(&mut self, a: Port, b: Port) {
+		self.nodes[a.node].ports[a.slot] = b;
+		self.nodes[b.node].ports[b.slot] = a;
+	}
+
+	fn partner(&self, p: Port) -> Port {
+		self.nodes[p.node].ports[p.slot]
+	}
+
+	/// Translates the expression into a graph and returns the port for its
+	/// root. `binders` holds, for each variable in scope, the ports that must
+	/// be connected to its occurrences. Uses an explicit stack since the
+	/// expression can be deep.
+	fn build(&mut self, e: &DBExpr, level: usize, binders: &mut Vec<Vec<Port>>) -> Port {
+		let mut todo = vec![Build::Expr(e, level)];
+		let mut results: Vec<Port> = Vec::new();
+		while let Some(item) = todo.pop() {
+			match item {
+				Build::Expr(DBExpr::Var(i), level) => {
+					let croissant = self.alloc(Kind::Croissant, level);
+					let n = binders.len();
+					binders[n - 1 - i].push(Port::new(croissant, 0));
+					results.push(Port::new(croissant, 1));
+				}
+				Build::Expr(DBExpr::Fun(body), level) => {
+					let lam = self.alloc(Kind::Lam, level);
+					binders.push(Vec::new());
+					todo.push(Build::Lam(lam, level));
+					todo.push(Build::Expr(body, level));
+				}
+				Build::Expr(DBExpr::App(f, a), level) => {
+					let app = self.alloc(Kind::App, level);
+					todo.push(Build::Arg(app, a, level));
+					todo.push(Build::Expr(f, level));
+				}
+				Build::Lam(lam, level) => {
+					let body = results.pop().unwrap();
+					self.link(Port::new(lam, 1), body);
+					let occurrences = binders.pop().unwrap();
+					self.share(Port::new(lam, 2), &occurrences, level);
+					results.push(Port::new(lam, 0));
+				}
+				Build::Arg(app, a, level) => {
+					let f = results.pop().unwrap();
+					self.link(Port::new(app, 0), f);
+					let before = binders.iter().map(|occ| occ.len()).collect();
+					todo.push(Build::App(app, before, level));
+					todo.push(Build::Expr(a, level + 1));
+				}
+				Build::App(app, before, level) => {
+					let a = results.pop().unwrap();
+					self.link(Port::new(app, 1), a);
+					// Free variables leave the argument through a bracket
+					for (occurrences, start) in binders.iter_mut().zip(before) {
+						for occ in occurrences[start..].iter_mut() {
+							let bracket = self.alloc(Kind::Bracket, level);
+							let (node, slot) = (occ.node, occ.slot);
+							self.nodes[bracket].ports[1] = *occ;
+							self.nodes[node].ports[slot] = Port::new(bracket, 1);
+							*occ = Port::new(bracket, 0);
+						}
+					}
+					results.push(Port::new(app, 2));
+				}
+			}
+		}
+		results.pop().unwrap()
+	}
+
+	/// Connects the variable port of a lambda to all occurrences of the
+	/// variable, using a chain of fans if there are several and an eraser if
+	/// there are none.
+	fn share(&mut self, var: Port, occurrences: &[Port], level: usize) {
+		let Some((last, rest)) = occurrences.split_last() else {
+			let eraser = self.alloc(Kind::Eraser, 0);
+			self.link(var, Port::new(eraser, 0));
+			return;
+		};
+		let mut var = var;
+		for occ in rest {
+			let fan = self.alloc(Kind::Fan, level);
+			self.link(var, Port::new(fan, 0));
+			self.link(Port::new(fan, 1), *occ);
+			var = Port::new(fan, 2);
+		}
+		self.link(var, *last);
+	}
+
+	/// Reduces the graph until the node connected to the root has its
+	/// principal port facing the root, and returns that node.
+	fn head(&mut self, root: usize) -> Result<usize, OutOfFuel> {
+		let mut stack = vec![Port::new(root, 0)];
+		loop {
+			let top = *stack.last().unwrap();
+			let p = self.partner(top);
+			if p.slot != 0 {
+				stack.push(Port::new(p.node, 0));
+			} else if stack.len() == 1 {
+				return Ok(p.node);
+			} else {
+				stack.pop();
+				self.interact(top.node, p.node)?;
+			}
+		}
+	}
+
+	/// Reads back the normal form of the term connected to the root, with an
+	/// explicit stack since the normal form can be deep.
+	fn read(&mut self, root: usize, depth: usize) -> Result<Term, OutOfFuel> {
+		let mut contexts: Vec<Read> = Vec::new();
+		let (mut root, mut depth) = (root, depth);
+		loop {
+			let head = self.head(root)?;
+			self.free.push(root);
+			let [_, p1, p2] = self.nodes[head].ports;
+			let mut result = match self.nodes[head].kind {
+				Kind::Lam => {
+					self.free.push(head);
+					let atom = self.alloc(Kind::Atom(depth), 0);
+					self.link(Port::new(atom, 0), p2);
+					let body = self.alloc(Kind::Root, 0);
+					self.link(Port::new(body, 0), p1);
+					contexts.push(Read::Fun);
+					(root, depth) = (body, depth + 1);
+					continue;
+				}
+				Kind::Atom(level) => {
+					self.free.push(head);
+					Term::new(DBExpr::Var(depth - 1 - level))
+				}
+				Kind::Neutral => {
+					self.free.push(head);
+					let f = self.alloc(Kind::Root, 0);
+					self.link(Port::new(f, 0), p1);
+					let a = self.alloc(Kind::Root, 0);
+					self.link(Port::new(a, 0), p2);
+					contexts.push(Read::Arg(a, depth));
+					root = f;
+					continue;
+				}
+				k => panic!("Unexpected {k:?} node during readback"),
+			};
+			loop {
+				match contexts.pop() {
+					None => return Ok(result),
+					Some(Read::Fun) => result = Term::new(DBExpr::Fun(result)),
+					Some(Read::Arg(a, d)) => {
+						contexts.push(Read::App(result));
+						(root, depth) = (a, d);
+						break;
+					}
+					Some(Read::App(f)) => result = Term::new(DBExpr::App(f, result)),
+				}
+			}
+		}
+	}
+
+	/// Rewrites a pair of nodes connected by their principal ports.
+	fn interact(&mut self, a: usize, b: usize) -> Result<(), OutOfFuel> {
+		if Some(self.interactions) == self.max_interactions {
+			return Err(OutOfFuel);
+		}
+		self.interactions += 1;
+		let (ka, kb) = (self.nodes[a].kind, self.nodes[b].kind);
+		let (i, j) = (self.nodes[a].index, self.nodes[b].index);
+		match (ka, kb) {
+			(Kind::Lam, Kind::App) => self.beta(a, b),
+			(Kind::App, Kind::Lam) => self.beta(b, a),
+			(Kind::Eraser, _) => self.commute(a, b, 0, j),
+			(_, Kind::Eraser) => self.commute(b, a, 0, i),
+			(_, Kind::App) if ka.is_readback() => self.neutral(a, b),
+			(Kind::App, _) if kb.is_readback() => self.neutral(b, a),
+			_ if ka == kb && ka.is_control() && i == j => self.annihilate(a, b),
+			_ if ka.is_control() && kb.is_readback() => self.commute(a, b, i, j),
+			_ if kb.is_control() && ka.is_readback() => self.commute(b, a, j, i),
+			_ if ka.is_control() && i < j => self.commute(a, b, i, crossed_index(ka, j)),
+			_ if kb.is_control() && j < i => self.commute(b, a, j, crossed_index(kb, i)),
+			_ => panic!("Unexpected interaction between {ka:?} ({i}) and {kb:?} ({j})"),
+		}
+		Ok(())
+	}
+
+	fn beta(&mut self, lam: usize, app: usize) {
+		self.beta_steps += 1;
+		let [_, body, var] = self.nodes[lam].ports;
+		let [_, arg, result] = self.nodes[app].ports;
+		self.link(result, body);
+		self.link(arg, var);
+		self.free.push(lam);
+		self.free.push(app);
+	}
+
+	/// Turns an application of a variable (or of an application of a variable)
+	/// into a neutral node.
+	fn neutral(&mut self, head: usize, app: usize) {
+		let [_, arg, result] = self.nodes[app].ports;
+		let neutral = self.alloc(Kind::Neutral, 0);
+		self.link(Port::new(neutral, 0), result);
+		self.link(Port::new(neutral, 1), Port::new(head, 0));
+		self.link(Port::new(neutral, 2), arg);
+		self.free.push(app);
+	}
+
+	fn annihilate(&mut self, a: usize, b: usize) {
+		for k in 1..=self.nodes[a].kind.aux_ports() {
+			let pa = self.partner(Port::new(a, k));
+			let pb = self.partner(Port::new(b, k));
+			self.link(pa, pb);
+		}
+		self.free.push(a);
+		self.free.push(b);
+	}
+
+	/// Lets `a` cross `b`: `a` is copied onto each auxiliary port of `b`, and
+	/// `b` is copied onto each auxiliary port of `a`. The copies are given the
+	/// indices `a_index` and `b_index` respectively.
+	fn commute(&mut self, a: usize, b: usize, a_index: usize, b_index: usize) {
+		let (ka, kb) = (self.nodes[a].kind, self.nodes[b].kind);
+		let a_ext: Vec<Port> = (1..=ka.aux_ports())
+			.map(|k| self.partner(Port::new(a, k)))
+			.collect();
+		let b_ext: Vec<Port> = (1..=kb.aux_ports())
+			.map(|k| self.partner(Port::new(b, k)))
+			.collect();
+		self.free.push(a);
+		self.free.push(b);
+		let a_copies: Vec<usize> = b_ext.iter().map(|_| self.alloc(ka, a_index)).collect();
+		let b_copies: Vec<usize> = a_ext.iter().map(|_| self.alloc(kb, b_index)).collect();
+		for (&copy, &ext) in b_copies.iter().zip(&a_ext) {
+			self.link(Port::new(copy, 0), ext);
+		}
+		for (&copy, &ext) in a_copies.iter().zip(&b_ext) {
+			self.link(Port::new(copy, 0), ext);
+		}
+		for (k, &b_copy) in b_copies.iter().enumerate() {
+			for (l, &a_copy) in a_copies.iter().enumerate() {
+				self.link(Port::new(b_copy, l + 1), Port::new(a_copy, k + 1));
+			}
+		}
+	}
+}
+
+/// The index of a node with index `index` after crossing a control node of the
+/// given kind.
+fn crossed_index(control: Kind, index: usize) -> usize {
+	match control {
+		Kind::Croissant => index - 1,
+		Kind::Bracket => index + 1,
+		_ => index,
+	}
+}
+
+#[cfg(test)]
+mod optimal_tests {
+	use crate::debruijn::{DBExpr, Term};
+	use crate::error::Error;
+	use crate::eval::Strategy;
+	use crate::lex;
+	use crate::optimal;
+	use crate::parse;
+
+	fn parse(e: &str) -> Term {
+		let mut stream = lex::lex(e).unwrap();
+		parse::parse(&mut stream).unwrap().to_debruijn()
+	}
+
+	const DEFS: &str = "
+		where    + = \\m.\\n.\\s.\\z.m s (n s z)
+		where    * = \\m.\\n.\\s.m (n s)
+		where    ^ = \\m.\\n.n m
+		where pred = \\n.\\f.\\x.n (\\g.\\h.h (g f)) (\\u.x) (\\u.u)
+		where succ = \\n.\\s.\\z.s (n s z)
+		where    K = \\x.\\y.x
+		where    S = \\x.\\y.\\z.x z (y z)
+		where    Y = \\f.(\\x.f (x x)) (\\x.f (x x))
+		where    W = (\\x.x x) (\\x.x x)
+		where    0 = \\s.\\z.z
+		where    2 = \\s.\\z.s (s z)
+		where    3 = \\s.\\z.s (s (s z))";
+
+	#[test]
+	fn optimal_matches_eval() -> () {
+		let terms = [
+			"\\x.x",
+			"(\\x.x) (\\y.y)",
+			"\\x.(\\y.y) x",
+			"\\x.x ((\\y.y) x)",
+			"(\\x.\\y.x) ((\\z.z) (\\z.z))",
+			"\\x.(\\y.\\z.y z) x",
+			"(\\k.\\a.\\b.\\f.f (k a) (k b)) (\\x.\\g.g x)",
+			"K (\\x.x) W",
+			"S K K",
+			"2 2",
+			"3 2",
+			"succ 0",
+			"+ 2 3",
+			"* 2 3",
+			"^ 2 3",
+			"^ 3 2",
+			"pred 3",
+			"pred (pred (* 3 3))",
+			"Y (\\f.\\n.n (\\x.f x) 0) 0",
+			"Y (\\f.\\n.n (\\x.f (pred n)) 0) 3",
+		];
+		for t in terms {
+			let e = parse(&format!("{t} {DEFS}"));
+			assert_eq!(
+				Ok(e.eval()),
+				optimal::eval(&e, Strategy::NormalOrder, None),
+				"{t}"
+			);
+		}
+	}
+
+	/// A small xorshift generator, so that the random tests are reproducible.
+	struct Rng(u64);
+
+	impl Rng {
+		fn below(&mut self, n: usize) -> usize {
+			self.0 ^= self.0 << 13;
+			self.0 ^= self.0 >> 7;
+			self.0 ^= self.0 << 17;
+			(self.0 % n as u64) as usize
+		}
+	}
+
+	/// Generates a random term of about the given size, whose free variables
+	/// are all below `scope`.
+	fn random_term(rng: &mut Rng, size: usize, scope: usize) -> Term {
+		match rng.below(3) {
+			_ if scope == 0 || size == 2 => {
+				Term::new(DBExpr::Fun(random_term(rng, size.max(2) - 1, scope + 1)))
+			}
+			_ if size <= 1 => Term::new(DBExpr::Var(rng.below(scope))),
+			0 => Term::new(DBExpr::Var(rng.below(scope))),
+			1 => Term::new(DBExpr::Fun(random_term(rng, size - 1, scope + 1))),
+			_ => {
+				let left = 1 + rng.below(size - 2);
+				Term::new(DBExpr::App(
+					random_term(rng, left, scope),
+					random_term(rng, size - 1 - left, scope),
+				))
+			}
+		}
+	}
+
+	#[test]
+	fn optimal_matches_eval_random() -> () {
+		let mut rng = Rng(0x2545_f491_4f6c_dd1d);
+		let mut checked = 0;
+		for i in 0..2000 {
+			let e = random_term(&mut rng, 5 + i % 20, 0);
+			// Skip terms without a normal form (or with one that is too far)
+			let Ok(expected) = e.eval_with_strategy(Strategy::NormalOrder, Some(200)) else {
+				continue;
+			};
+			assert_eq!(
+				Ok(expected),
+				optimal::eval(&e, Strategy::NormalOrder, Some(1_000_000)),
+				"{}",
+				e.to_named()
+			);
+			checked += 1;
+		}
+		assert!(checked > 1000);
+	}
+
+	#[test]
+	fn optimal_deep_normal_form() -> () {
+		// \x.\a.x a a ... a, which is too deep to be built or read back
+		// recursively. (Nesting the arguments instead would need a bracket for
+		// each argument around each occurrence.)
+		let mut body = Term::new(DBExpr::Var(1));
+		for _ in 0..100_000 {
+			body = Term::new(DBExpr::App(body, Term::new(DBExpr::Var(0))));
+		}
+		let e = Term::new(DBExpr::Fun(Term::new(DBExpr::Fun(body))));
+		assert_eq!(
+			Ok(e.clone()),
+			optimal::eval(&e, Strategy::NormalOrder, None)
+		);
+	}
+
+	#[test]
+	fn optimal_shares_redexes() -> () {
+		// Normal order reduces `(\y.y) (\z.z)` twice
+		let e = parse("(\\x.x x) ((\\y.y) (\\z.z))");
+		assert_eq!(5, e.trace(Strategy::NormalOrder, None).unwrap().len());
+		let reduction = optimal::reduce(&e, None).unwrap();
+		assert_eq!(parse("\\x.x"), reduction.expr);
+		assert_eq!(3, reduction.beta_steps);
+		assert!(reduction.interactions > reduction.beta_steps);
+	}
+
+	#[test]
+	fn optimal_step_limit() -> () {
+		let e = parse(&format!("W {DEFS}"));
+		assert_eq!(
+			Err(Error::StepLimitExceeded(100, e.clone())),
+			optimal::reduce(&e, Some(100))
+		);
+		let e = parse("(\\x.x) (\\y.y)");
+		let interactions = optimal::reduce(&e, None).unwrap().interactions;
+		assert!(optimal::reduce(&e, Some(interactions - 1)).is_err());
+		assert!(optimal::reduce(&e, Some(interactions)).is_ok());
+	}
+
+	#[test]
+	fn optimal_unsupported_strategy() -> () {
+		assert!(matches!(
+			optimal::eval(&parse("\\x.x"), Strategy::CallByValue, None),
+			Err(Error::UnsupportedStrategy(_))
+		));
+	}
+}
@@ -0,0 +1,341 @@
+use std::ops::Range;
+use std::sync::atomic::{AtomicBool, AtomicUsize, Ordering};
+
+use rayon::prelude::*;
+
+use crate::debruijn::{DBExpr, Term};
+use crate::divergence::DivergenceDetector;
+use crate::error::Error;
+use crate::eval::{try_beta_reduce, Strategy};
+
+/// Finds the normal form of the expression using several threads.
+///
+/// The expression is first reduced to head normal form `\x1...\xn.h a1 ... am`.
+/// Since `h` is a variable, the arguments can no longer interact and are
+/// normalized independently on the rayon thread pool, one level of the
+/// result at a time. This finds the same normal form as `DBExpr::eval`, and
+/// terminates whenever normal order does.
+///
+/// Since each argument is only reduced to head normal form, a term whose
+/// normal form keeps growing forever (such as `Y succ`) is found by comparing
+/// each argument with one of its ancestors, saved at exponentially spaced
+/// levels (as in Brent's cycle detection algorithm).
+///
+/// Only normal order is supported. The step limit applies to the total
+/// number of beta reductions across all threads, so the partial result
+/// returned when it is reached may differ from one run to the next.
+pub fn eval(e: &DBExpr, strategy: Strategy, max_steps: Option<usize>) -> Result<Term, Error> {
+	if strategy != Strategy::NormalOrder {
+		return Err(Error::UnsupportedStrategy(
+			"parallel evaluation only supports normal order".to_owned(),
+		));
+	}
+	let budget = Budget {
+		steps: AtomicUsize::new(0),
+		max_steps,
+		stopped: AtomicBool::new(false),
+	};
+	// Children always come after their parent, and each level of the result
+	// is contiguous
+	let mut nodes = vec![Node::Pending(Term::new(e.clone()))];
+	// For each node, the ancestor that it is compared with
+	let mut saved: Vec<Option<Term>> = vec![None];
+	let mut depth: usize = 0;
+	let mut level = 0..1;
+	let mut stopped = false;
+	while !level.is_empty() && !stopped {
+		let outcomes: Vec<Outcome> = nodes[level.clone()]
+			.par_iter()
+			.map(|node| match node {
+				Node::Pending(e) => head_normalize(e, &budget),
+				Node::Hnf { .. } => unreachable!("node was already normalized"),
+			})
+			.collect();
+		let next = nodes.len();
+		for (i, outcome) in level.zip(outcomes) {
+			match outcome {
+				Outcome::Done(hnf) => {
+					let (binders, head, args) = decompose(&hnf);
+					let ancestor = match &nodes[i] {
+						Node::Pending(e) if depth == 0 || depth.is_power_of_two() => {
+							Some(e.clone())
+						}
+						_ => saved[i].clone(),
+					};
+					if args.iter().any(|a| Some(a) == ancestor.as_ref()) {
+						return Err(Error::NonTermination(
+							"a subterm reduces to a term that contains it, so it keeps growing forever"
+								.to_owned(),
+						));
+					}
+					let first = nodes.len();
+					saved.extend(args.iter().map(|_| ancestor.clone()));
+					nodes.extend(args.into_iter().map(Node::Pending));
+					nodes[i] = Node::Hnf {
+						binders,
+						head,
+						args: first..nodes.len(),
+					};
+				}
+				Outcome::Stopped(partial) => {
+					nodes[i] = Node::Pending(partial);
+					stopped = true;
+				}
+				Outcome::Failed(e) => return Err(e),
+			}
+		}
+		level = next..nodes.len();
+		depth += 1;
+	}
+	let result = assemble(nodes);
+	if stopped {
+		Err(Error::StepLimitExceeded(
+			budget
+				.steps
+				.load(Ordering::Relaxed)
+				.min(max_steps.unwrap_or(usize::MAX)),
+			result,
+		))
+	} else {
+		Ok(result)
+	}
+}
+
+/// The steps shared by all threads.
+struct Budget {
+	steps: AtomicUsize,
+	max_steps: Option<usize>,
+	/// Set when some thread runs out of steps or finds an error, so that the
+	/// others stop too.
+	stopped: AtomicBool,
+}
+
+impl Budget {
+	fn take_step(&self) -> bool {
+		if self.stopped.load(Ordering::Relaxed) {
+			return false;
+		}
+		let steps = self.steps.fetch_add(1, Ordering::Relaxed);
+		if self.max_steps.is_some_and(|max| steps >= max) {
+			self.stop();
+			return false;
+		}
+		true
+	}
+
+	fn stop(&self) {
+		self.stopped.store(true, Ordering::Relaxed);
+	}
+}
+
+enum Node {
+	/// A subterm that has not been normalized yet, or whose normalization was
+	/// interrupted.
+	Pending(Term),
+	/// A head normal form `\x1...\xn.h a1 ... am`, where the `ai` are the
+	/// nodes in `args`.
+	Hnf {
+		binders: usize,
+		head: usize,
+		args: Range<usize>,
+	},
+}
+
+enum Outcome {
+	Done(Term),
+	Stopped(Term),
+	Failed(Error),
+}
+
+fn head_normalize(e: &Term, budget: &Budget) -> Outcome {
+	let mut e = e.clone();
+	let mut path = Vec::new();
+	let mut detector = DivergenceDetector::new(&e, Strategy::HeadReduction);
+	while let Some(next) = try_beta_reduce(&e, Strategy::HeadReduction, &mut path) {
+		if !budget.take_step() {
+			return Outcome::Stopped(e);
+		}
+		if let Err(err) = detector.check(&e, &path) {
+			budget.stop();
+			return Outcome::Failed(err);
+		}
+		e = next;
+		path.clear();
+	}
+	Outcome::Done(e)
+}
+
+/// Splits a head normal form `\x1...\xn.h a1 ... am` into `n`, `h` and the
+/// `ai`.
+fn decompose(hnf: &Term) -> (usize, usize, Vec<Term>) {
+	let mut binders = 0;
+	let mut e = hnf;
+	while let DBExpr::Fun(body) = e.as_ref() {
+		binders += 1;
+		e = body;
+	}
+	let mut args = Vec::new();
+	while let DBExpr::App(f, a) = e.as_ref() {
+		args.push(a.clone());
+		e = f;
+	}
+	args.reverse();
+	match e.as_ref() {
+		DBExpr::Var(head) => (binders, *head, args),
+		_ => unreachable!("head reduction stopped at a redex"),
+	}
+}
+
+fn assemble(nodes: Vec<Node>) -> Term {
+	let mut results: Vec<Option<Term>> = vec![None; nodes.len()];
+	for (i, node) in nodes.into_iter().enumerate().rev() {
+		let result = match node {
+			Node::Pending(e) => e,
+			Node::Hnf {
+				binders,
+				head,
+				args,
+			} => {
+				let mut e = Term::new(DBExpr::Var(head));
+				for j in args {
+					let a = results[j].take().expect("children are assembled first");
+					e = Term::new(DBExpr::App(e, a));
+				}
+				for _ in 0..binders {
+					e = Term::new(DBExpr::Fun(e));
+				}
+				e
+			}
+		};
+		results[i] = Some(result);
+	}
+	results[0].take().expect("root is assembled last")
+}
+
+#[cfg(test)]
+mod parallel_tests {
+	use crate::debruijn::Term;
+	use crate::error::Error;
+	use crate::eval::Strategy;
+	use crate::lex;
+	use crate::parallel;
+	use crate::parse;
+	use crate::{check_assertions, eval_lambda, eval_lambda_with_options, EvalOptions};
+
+	fn parse(e: &str) -> Term {
+		let mut stream = lex::lex(e).unwrap();
+		parse::parse(&mut stream).unwrap().to_debruijn()
+	}
+
+	const DEFS: &str = "
+		where    + = \\m.\\n.\\s.\\z.m s (n s z)
+		where    * = \\m.\\n.\\s.m (n s)
+		where    ^ = \\m.\\n.n m
+		where pred = \\n.\\f.\\x.n (\\g.\\h.h (g f)) (\\u.x) (\\u.u)
+		where succ = \\n.\\s.\\z.s (n s z)
+		where    K = \\x.\\y.x
+		where    S = \\x.\\y.\\z.x z (y z)
+		where    Y = \\f.(\\x.f (x x)) (\\x.f (x x))
+		where    W = (\\x.x x) (\\x.x x)
+		where    0 = \\s.\\z.z
+		where    2 = \\s.\\z.s (s z)
+		where    3 = \\s.\\z.s (s (s z))";
+
+	#[test]
+	fn parallel_matches_eval() -> () {
+		let terms = [
+			"\\x.x",
+			"(\\x.x) (\\y.y)",
+			"\\x.x ((\\y.y) x)",
+			"\\x.(\\y.\\z.y z) x",
+			"K (\\x.x) W",
+			"S K K",
+			"+ 2 3",
+			"^ 3 2",
+			"pred 3",
+			"\\f.f (^ 2 3) (* 3 3) (\\x.x (pred 2))",
+			"Y (\\f.\\n.n (\\x.f x) 0) 0",
+		];
+		for t in terms {
+			let e = parse(&format!("{t} {DEFS}"));
+			assert_eq!(
+				Ok(e.eval()),
+				parallel::eval(&e, Strategy::NormalOrder, None),
+				"{t}"
+			);
+		}
+	}
+
+	#[test]
+	fn parallel_large_numeral() -> () {
+		let e = parse(&format!("^ 2 (+ 3 (* 2 3)) {DEFS}"));
+		let expected = format!("\\s.\\z.{}z{}", "s (".repeat(512), ")".repeat(512));
+		assert_eq!(
+			Ok(parse(&expected)),
+			parallel::eval(&e, Strategy::NormalOrder, None)
+		);
+	}
+
+	#[test]
+	fn parallel_step_limit() -> () {
+		let e = parse("\\f.f ((\\x.x x x) (\\x.x x x)) ((\\x.x x x) (\\x.x x x))");
+		assert!(matches!(
+			parallel::eval(&e, Strategy::NormalOrder, Some(100)),
+			Err(Error::StepLimitExceeded(100, _))
+		));
+		let e = parse("(\\x.x) (\\y.y)");
+		assert!(parallel::eval(&e, Strategy::NormalOrder, Some(0)).is_err());
+		assert!(parallel::eval(&e, Strategy::NormalOrder, Some(1)).is_ok());
+	}
+
+	#[test]
+	fn parallel_non_termination() -> () {
+		for t in ["\\f.f 2 W", "Y succ", "\\f.f (Y (\\x.\\y.y x))"] {
+			let e = parse(&format!("{t} {DEFS}"));
+			assert!(
+				matches!(
+					parallel::eval(&e, Strategy::NormalOrder, None),
+					Err(Error::NonTermination(_))
+				),
+				"{t}"
+			);
+		}
+	}
+
+	#[test]
+	fn parallel_unsupported_strategy() -> () {
+		assert!(matches!(
+			parallel::eval(&parse("\\x.x"), Strategy::CallByName, None),
+			Err(Error::UnsupportedStrategy(_))
+		));
+	}
+
+	#[test]
+	fn parallel_eval_options() -> () {
+		let mut options = EvalOptions::new();
+		options.set_parallel(true);
+		assert_eq!(
+			eval_lambda_with_options(&format!("* 2 3 {DEFS}"), &options),
+			eval_lambda(&format!("* 2 3 {DEFS}"))
+		);
+		assert!(matches!(
+			eval_lambda_with_options(&format!("Y succ {DEFS}"), &options),
+			Err(Error::NonTermination(_))
+		));
+		let results = check_assertions(&format!("{{:assert Y succ == 0}} 0 {DEFS}"), &options);
+		assert!(results.unwrap()[0]
+			.actual()
+			.is_some_and(|actual| actual.starts_with("Evaluation does not terminate")));
+		options.set_strategy(Strategy::CallByName);
+		assert!(matches!(
+			eval_lambda_with_options("\\x.x", &options),
+			Err(Error::UnsupportedStrategy(_))
+		));
+		// Assertions use the parallel evaluator too, which only supports
+		// normal order
+		let results = check_assertions("{:assert \\x.x == \\y.y} \\x.x", &options);
+		assert!(results.unwrap()[0]
+			.actual()
+			.is_some_and(|actual| actual.starts_with("Unsupported strategy")));
+	}
+}
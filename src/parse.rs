@@ -1,14 +1,120 @@
-use std::collections::HashMap;
+use std::collections::{HashMap, HashSet};
 
 use crate::error::Error;
-use crate::lex::{Token, TokenStream};
+use crate::lex::{lex_at, Span, Token, TokenStream};
+use crate::literals::{self, NumeralEncoding};
 
-#[derive(Clone, Debug, PartialEq)]
+/// An expression, along with where it is in the program. Expressions that
+/// are not written in the program, such as the expansions of literals or the
+/// results of evaluation, have `Span::default()`. Spans are ignored when
+/// comparing expressions.
+#[derive(Debug)]
 #[cfg_attr(feature = "arbitrary", derive(arbitrary::Arbitrary))]
 pub enum Expr {
-	Fun(String, Box<Expr>),
-	App(Box<Expr>, Box<Expr>),
-	Var(String),
+	Fun(String, Box<Expr>, Span),
+	App(Box<Expr>, Box<Expr>, Span),
+	Var(String, Span),
+}
+
+impl Expr {
+	/// Where the expression is in the program.
+	pub fn span(&self) -> Span {
+		match self {
+			Expr::Fun(_, _, span) | Expr::App(_, _, span) | Expr::Var(_, span) => *span,
+		}
+	}
+
+	/// Moves the expression to `span`.
+	fn at(mut self: Box<Self>, span: Span) -> Box<Expr> {
+		match self.as_mut() {
+			Expr::Fun(_, _, s) | Expr::App(_, _, s) | Expr::Var(_, s) => *s = span,
+		}
+		self
+	}
+}
+
+impl Clone for Expr {
+	fn clone(&self) -> Self {
+		// Copy the subexpressions with an explicit stack, like `Drop`, since
+		// cloning them recursively could overflow the stack
+		let mut stack = vec![(false, self)];
+		let mut result_stack: Vec<Expr> = Vec::new();
+		while let Some((visited, e)) = stack.pop() {
+			match (visited, e) {
+				(_, Expr::Var(x, span)) => result_stack.push(Expr::Var(x.clone(), *span)),
+				(false, Expr::Fun(_, body, _)) => {
+					stack.push((true, e));
+					stack.push((false, body));
+				}
+				(false, Expr::App(f, a, _)) => {
+					stack.push((true, e));
+					stack.push((false, a));
+					stack.push((false, f));
+				}
+				(true, Expr::Fun(x, _, span)) => {
+					let body = result_stack.pop().expect("missing body");
+					result_stack.push(Expr::Fun(x.clone(), Box::new(body), *span));
+				}
+				(true, Expr::App(_, _, span)) => {
+					let a = result_stack.pop().expect("missing argument");
+					let f = result_stack.pop().expect("missing function");
+					result_stack.push(Expr::App(Box::new(f), Box::new(a), *span));
+				}
+			}
+		}
+		result_stack.pop().expect("missing result")
+	}
+}
+
+impl PartialEq for Expr {
+	fn eq(&self, other: &Self) -> bool {
+		// Compared with an explicit stack for the same reason as `Clone`
+		let mut stack = vec![(self, other)];
+		while let Some(pair) = stack.pop() {
+			match pair {
+				(Expr::Var(x, _), Expr::Var(y, _)) if x == y => {}
+				(Expr::Fun(x, b1, _), Expr::Fun(y, b2, _)) if x == y => stack.push((b1, b2)),
+				(Expr::App(f1, a1, _), Expr::App(f2, a2, _)) => {
+					stack.push((a1, a2));
+					stack.push((f1, f2));
+				}
+				_ => return false,
+			}
+		}
+		true
+	}
+}
+
+impl Drop for Expr {
+	fn drop(&mut self) {
+		// Free the subexpressions with an explicit stack, since dropping them
+		// recursively could overflow the stack for deep expressions
+		let mut stack = Vec::new();
+		take_children(self, &mut stack);
+		while let Some(mut e) = stack.pop() {
+			take_children(&mut e, &mut stack);
+		}
+	}
+}
+
+/// Moves the children of `e` that have children of their own to `stack`.
+fn take_children(e: &mut Expr, stack: &mut Vec<Expr>) {
+	let mut take = |child: &mut Box<Expr>| {
+		if !matches!(**child, Expr::Var(_, _)) {
+			stack.push(*std::mem::replace(
+				child,
+				Box::new(Expr::Var(String::new(), Span::default())),
+			));
+		}
+	};
+	match e {
+		Expr::Fun(_, body, _) => take(body),
+		Expr::App(f, a, _) => {
+			take(f);
+			take(a);
+		}
+		Expr::Var(_, _) => {}
+	}
 }
 
 pub fn find_type_annotation(tokens: &mut TokenStream) -> Option<String> {
@@ -23,27 +129,144 @@ pub fn find_type_annotation(tokens: &mut TokenStream) -> Option<String> {
 	}
 }
 
+/// A test written in a comment, either `{:assert <e1> == <e2>}`, which checks
+/// that both expressions have the same normal form, or `{:assert-type <t>}`,
+/// which checks that the result of the program can be interpreted as type
+/// `t`.
+#[derive(Debug, PartialEq)]
+pub struct Assertion {
+	/// The text of the comment, without the braces.
+	pub directive: String,
+	pub check: Check,
+}
+
+#[derive(Debug, PartialEq)]
+pub enum Check {
+	/// The two expressions, which may use the program's declarations.
+	Equal(Box<Expr>, Box<Expr>),
+	/// The name of the type.
+	Type(String),
+}
+
+/// Finds the `{:assert ...}` and `{:assert-type ...}` comments in the program.
+pub fn find_assertions(tokens: &TokenStream) -> Result<Vec<Assertion>, Error> {
+	let mut assertions = Vec::new();
+	for (t, span) in &tokens.tokens {
+		let Token::Comment(s) = t else {
+			continue;
+		};
+		let check = if let Some(t) = strip_directive(s, ":assert-type") {
+			Check::Type(t.trim().to_owned())
+		} else if let Some(e) = strip_directive(s, ":assert") {
+			let Some((lhs, rhs)) = e.split_once("==") else {
+				return Err(Error::SyntaxError(
+					format!("expected \"==\" in assertion \"{}\"", s.trim()),
+					*span,
+				));
+			};
+			// Where the expressions are in the program, after the brace
+			let lhs_start = span.after("{").after(&s[..s.len() - e.len()]);
+			let rhs_start = lhs_start.after(lhs).after("==");
+			Check::Equal(
+				parse_standalone(lhs, lhs_start)?,
+				parse_standalone(rhs, rhs_start)?,
+			)
+		} else {
+			continue;
+		};
+		assertions.push(Assertion {
+			directive: s.trim().to_owned(),
+			check,
+		});
+	}
+	Ok(assertions)
+}
+
+/// Finds the `{:numerals}` comment, which enables numeric literals, and returns
+/// the encoding it gives, if any. `{:numerals}` alone selects Church numerals.
+pub fn find_numerals(tokens: &TokenStream) -> Result<Option<NumeralEncoding>, Error> {
+	for (t, span) in &tokens.tokens {
+		let Token::Comment(s) = t else {
+			continue;
+		};
+		if let Some(encoding) = strip_directive(s, ":numerals") {
+			return match encoding.trim() {
+				"" => Ok(Some(NumeralEncoding::default())),
+				encoding => encoding
+					.try_into()
+					.map(Some)
+					.map_err(|e: Error| e.at(*span)),
+			};
+		}
+	}
+	Ok(None)
+}
+
+/// Returns the rest of the comment if it starts with the given directive.
+fn strip_directive<'s>(s: &'s str, directive: &str) -> Option<&'s str> {
+	s.strip_prefix(directive)
+		.filter(|rest| rest.is_empty() || rest.starts_with(char::is_whitespace))
+}
+
+/// Parses an expression without declarations, which starts at `origin` in
+/// the program.
+fn parse_standalone(src: &str, origin: Span) -> Result<Box<Expr>, Error> {
+	let mut tokens = lex_at(src, origin)?;
+	tokens.remove_comments();
+	let e = parse_e(&mut tokens)?;
+	match tokens.next() {
+		Token::End => Ok(e),
+		t => Err(Error::SyntaxError(
+			format!("unexpected trailing token \"{t}\""),
+			tokens.prev_span(),
+		)),
+	}
+}
+
 pub fn parse(tokens: &mut TokenStream) -> Result<Box<Expr>, Error> {
+	let (e, decls) = parse_with_decls(tokens)?;
+	Ok(inline_decls(&e, &decls))
+}
+
+/// A definition `where <name> = <expr>`.
+pub type Decl = (String, Box<Expr>);
+
+/// Like `parse`, but returns the declarations separately instead of inlining
+/// them.
+pub fn parse_with_decls(tokens: &mut TokenStream) -> Result<(Box<Expr>, Vec<Decl>), Error> {
 	let e = parse_e(tokens)?;
 	let decls = parse_decls(tokens)?;
-	let ee = inline_decls(&e, &decls);
 	match tokens.next() {
 		Token::End => (),
 		t => {
-			return Err(Error::SyntaxError(format!(
-				"unexpected trailing token \"{t}\""
-			)))
+			return Err(Error::SyntaxError(
+				format!("unexpected trailing token \"{t}\""),
+				tokens.prev_span(),
+			))
 		}
 	};
-	Ok(ee)
+	Ok((e, decls))
 }
 
 fn parse_e(tokens: &mut TokenStream) -> Result<Box<Expr>, Error> {
 	let mut e = parse_eprime(tokens)?;
 	loop {
 		match tokens.peek() {
-			Token::Lambda | Token::Ident(_) | Token::Lpar => {
-				e = Box::new(Expr::App(e, parse_eprime(tokens)?))
+			// The start of the next binding of a `let`
+			Token::Ident(_) if *tokens.peek_nth(1) == Token::Def => break,
+			Token::Lambda
+			| Token::Ident(_)
+			| Token::Lpar
+			| Token::Let
+			| Token::Lbracket
+			| Token::Langle
+			| Token::True
+			| Token::False
+			| Token::Str(_)
+			| Token::Char(_) => {
+				let a = parse_eprime(tokens)?;
+				let span = e.span().through(a.span());
+				e = Box::new(Expr::App(e, a, span));
 			}
 			_ => break,
 		}
@@ -56,7 +279,53 @@ fn parse_eprime(tokens: &mut TokenStream) -> Result<Box<Expr>, Error> {
 		Token::Lambda => parse_fun(tokens),
 		Token::Ident(_) => parse_var(tokens),
 		Token::Lpar => parse_parenthesized(tokens),
-		t => return Err(Error::SyntaxError(format!("unexpected token \"{t}\""))),
+		Token::Let => parse_let(tokens),
+		Token::Lbracket
+		| Token::Langle
+		| Token::True
+		| Token::False
+		| Token::Str(_)
+		| Token::Char(_) => parse_literal(tokens),
+		t => Err(Error::SyntaxError(
+			format!("unexpected token \"{t}\"{}", keyword_note(t)),
+			tokens.span(),
+		)),
+	}
+}
+
+/// Parses a literal, which is expanded to the expression it stands for. Kept
+/// apart from `parse_eprime` so that its locals don't add to the stack frames
+/// of nested parentheses.
+fn parse_literal(tokens: &mut TokenStream) -> Result<Box<Expr>, Error> {
+	match tokens.peek() {
+		Token::Lbracket => {
+			let start = tokens.span();
+			let elems = parse_elements(tokens, Token::Lbracket, Token::Rbracket)?;
+			Ok(literals::list(elems).at(start.through(tokens.prev_span())))
+		}
+		Token::Langle => {
+			let start = tokens.span();
+			let elems = parse_elements(tokens, Token::Langle, Token::Rangle)?;
+			Ok(literals::tuple(elems).at(start.through(tokens.prev_span())))
+		}
+		Token::True => {
+			tokens.next();
+			Ok(literals::boolean(true).at(tokens.prev_span()))
+		}
+		Token::False => {
+			tokens.next();
+			Ok(literals::boolean(false).at(tokens.prev_span()))
+		}
+		Token::Str(_) | Token::Char(_) => {
+			let e = match tokens.next() {
+				Token::Str(s) => literals::string(&s),
+				Token::Char(c) => literals::character(c),
+				_ => unreachable!(),
+			};
+			let span = tokens.prev_span();
+			e.map(|e| e.at(span)).map_err(|e| e.at(span))
+		}
+		_ => unreachable!(),
 	}
 }
 
@@ -64,41 +333,65 @@ fn parse_fun(tokens: &mut TokenStream) -> Result<Box<Expr>, Error> {
 	match tokens.next() {
 		Token::Lambda => (),
 		t => {
-			return Err(Error::SyntaxError(format!(
-				"expected \"{}\" but got \"{t}\"",
-				Token::Lambda
-			)))
+			return Err(Error::SyntaxError(
+				format!("expected \"{}\" but got \"{t}\"", Token::Lambda),
+				tokens.prev_span(),
+			))
 		}
 	};
-	let x = match tokens.next() {
-		Token::Ident(name) => name,
-		t => {
-			return Err(Error::SyntaxError(format!(
-				"expected an identifier but got \"{t}\""
-			)))
-		}
+	// Where each lambda starts: `\x` for the first one, and the name for the
+	// others
+	let mut starts = vec![tokens.prev_span()];
+	let mut xs = match tokens.next() {
+		Token::Ident(name) => vec![name],
+		t => return Err(expected_ident(t, tokens.prev_span())),
 	};
-	match tokens.next() {
-		Token::Dot => (),
-		t => {
-			return Err(Error::SyntaxError(format!(
-				"expected \"{}\" but got \"{t}\"",
-				Token::Dot
-			)))
+	// `\x y z.e` is short for `\x.\y.\z.e`
+	loop {
+		match tokens.next() {
+			Token::Ident(name) => {
+				xs.push(name);
+				starts.push(tokens.prev_span());
+			}
+			Token::Dot => break,
+			t => {
+				return Err(Error::SyntaxError(
+					format!("expected \"{}\" but got \"{t}\"", Token::Dot),
+					tokens.prev_span(),
+				))
+			}
 		}
-	};
-	let e = parse_e(tokens)?;
-	Ok(Box::new(Expr::Fun(x, e)))
+	}
+	let mut e = parse_e(tokens)?;
+	let end = e.span();
+	for (x, start) in xs.into_iter().zip(starts).rev() {
+		e = Box::new(Expr::Fun(x, e, start.through(end)));
+	}
+	Ok(e)
+}
+
+/// The error for a token found where an identifier was expected.
+fn expected_ident(t: Token, span: Span) -> Error {
+	Error::SyntaxError(
+		format!("expected an identifier but got \"{t}\"{}", keyword_note(&t)),
+		span,
+	)
+}
+
+/// Points out keywords in error messages, since some of them (like `in`) used
+/// to be valid names.
+fn keyword_note(t: &Token) -> &'static str {
+	if t.is_keyword() {
+		", which is a keyword"
+	} else {
+		""
+	}
 }
 
 fn parse_var(tokens: &mut TokenStream) -> Result<Box<Expr>, Error> {
 	match tokens.next() {
-		Token::Ident(name) => Ok(Box::new(Expr::Var(name))),
-		t => {
-			return Err(Error::SyntaxError(format!(
-				"expected an identifier but got \"{t}\""
-			)))
-		}
+		Token::Ident(name) => Ok(Box::new(Expr::Var(name, tokens.prev_span()))),
+		t => Err(expected_ident(t, tokens.prev_span())),
 	}
 }
 
@@ -106,69 +399,291 @@ fn parse_parenthesized(tokens: &mut TokenStream) -> Result<Box<Expr>, Error> {
 	match tokens.next() {
 		Token::Lpar => (),
 		t => {
-			return Err(Error::SyntaxError(format!(
-				"expected \"{}\" but got \"{t}\"",
-				Token::Lpar
-			)))
+			return Err(Error::SyntaxError(
+				format!("expected \"{}\" but got \"{t}\"", Token::Lpar),
+				tokens.prev_span(),
+			))
 		}
 	}
+	let open = tokens.prev_span();
 	let e = parse_e(tokens);
 	match tokens.next() {
 		Token::Rpar => (),
-		Token::End => return Err(Error::SyntaxError("unclosed parenthesis".to_owned())),
+		Token::End => return Err(Error::SyntaxError("unclosed parenthesis".to_owned(), open)),
 		t => {
-			return Err(Error::SyntaxError(format!(
-				"expected \"{}\" but got \"{t}\"",
-				Token::Rpar
-			)))
+			return Err(Error::SyntaxError(
+				format!("expected \"{}\" but got \"{t}\"", Token::Rpar),
+				tokens.prev_span(),
+			))
 		}
 	}
-	e
+	// The parentheses are part of the expression
+	Ok(e?.at(open.through(tokens.prev_span())))
 }
 
-fn parse_decls(tokens: &mut TokenStream) -> Result<Vec<(String, Box<Expr>)>, Error> {
-	let mut decls = Vec::new();
+/// Parses a comma-separated sequence of expressions between `open` and
+/// `close`, as in the list literal `[e1, e2, e3]`.
+fn parse_elements(tokens: &mut TokenStream, open: Token, close: Token) -> Result<Vec<Expr>, Error> {
+	let t = tokens.next();
+	if t != open {
+		return Err(Error::SyntaxError(
+			format!("expected \"{open}\" but got \"{t}\""),
+			tokens.prev_span(),
+		));
+	}
+	let open_span = tokens.prev_span();
+	let mut elems = Vec::new();
+	if *tokens.peek() == close {
+		tokens.next();
+		return Ok(elems);
+	}
 	loop {
-		match tokens.peek() {
-			Token::Where => decls.push(parse_decl(tokens)?),
-			_ => break,
+		elems.push(*parse_e(tokens)?);
+		match tokens.next() {
+			Token::Comma => (),
+			t if t == close => return Ok(elems),
+			Token::End => {
+				return Err(Error::SyntaxError(
+					format!("unclosed \"{open}\""),
+					open_span,
+				))
+			}
+			t => {
+				return Err(Error::SyntaxError(
+					format!(
+						"expected \"{}\" or \"{close}\" but got \"{t}\"",
+						Token::Comma
+					),
+					tokens.prev_span(),
+				))
+			}
 		}
 	}
+}
+
+/// Parses `let x1 = e1 ... xn = en in e`, and substitutes the definitions
+/// into `e` as with `inline_decls`, except that each definition can only
+/// refer to the ones before it. With `let rec`, the definitions can refer to
+/// each other and to themselves instead (see `resolve_rec`).
+fn parse_let(tokens: &mut TokenStream) -> Result<Box<Expr>, Error> {
+	match tokens.next() {
+		Token::Let => (),
+		t => {
+			return Err(Error::SyntaxError(
+				format!("expected \"{}\" but got \"{t}\"", Token::Let),
+				tokens.prev_span(),
+			))
+		}
+	};
+	let start = tokens.prev_span();
+	let rec = *tokens.peek() == Token::Rec;
+	if rec {
+		tokens.next();
+	}
+	let mut bindings = vec![parse_binding(tokens)?];
+	while let Token::Ident(_) = tokens.peek() {
+		bindings.push(parse_binding(tokens)?);
+	}
+	let (mut bindings, spans): (Vec<Decl>, Vec<Span>) = bindings.into_iter().unzip();
+	match tokens.next() {
+		Token::In => (),
+		t => {
+			return Err(Error::SyntaxError(
+				format!("expected \"{}\" but got \"{t}\"", Token::In),
+				tokens.prev_span(),
+			))
+		}
+	};
+	let e = parse_e(tokens)?;
+	let bindings = if rec {
+		resolve_rec(bindings, &spans)?
+	} else {
+		bindings.reverse();
+		bindings
+	};
+	// The definitions keep their own spans where they are substituted
+	Ok(inline_decls(&e, &bindings).at(start.through(tokens.prev_span())))
+}
+
+/// Parses the `where` declarations. The `where rec` ones are replaced by
+/// non-recursive definitions with `resolve_rec`.
+fn parse_decls(tokens: &mut TokenStream) -> Result<Vec<Decl>, Error> {
+	let mut decls = Vec::new();
+	let mut rec = Vec::new();
+	while *tokens.peek() == Token::Where {
+		let (decl, name, is_rec) = parse_decl(tokens)?;
+		if is_rec {
+			rec.push((decls.len(), name));
+		}
+		decls.push(decl);
+	}
+	let group = rec.iter().map(|&(i, _)| decls[i].clone()).collect();
+	let spans: Vec<Span> = rec.iter().map(|&(_, span)| span).collect();
+	let resolved = resolve_rec(group, &spans)?;
+	for ((i, _), decl) in rec.into_iter().zip(resolved) {
+		decls[i] = decl;
+	}
 	Ok(decls)
 }
 
-fn parse_decl(tokens: &mut TokenStream) -> Result<(String, Box<Expr>), Error> {
+/// Parses `where <name> = <expr>` or `where rec <name> = <expr>`, and returns
+/// where the name is and whether `rec` was given.
+fn parse_decl(tokens: &mut TokenStream) -> Result<(Decl, Span, bool), Error> {
 	match tokens.next() {
 		Token::Where => (),
 		t => {
-			return Err(Error::SyntaxError(format!(
-				"expected \"{}\" but got \"{t}\"",
-				Token::Where
-			)))
+			return Err(Error::SyntaxError(
+				format!("expected \"{}\" but got \"{t}\"", Token::Where),
+				tokens.prev_span(),
+			))
 		}
 	};
+	let rec = *tokens.peek() == Token::Rec;
+	if rec {
+		tokens.next();
+	}
+	let (decl, name) = parse_binding(tokens)?;
+	Ok((decl, name, rec))
+}
+
+/// Parses `<name> = <expr>`, and returns where the name is.
+fn parse_binding(tokens: &mut TokenStream) -> Result<(Decl, Span), Error> {
 	let x = match tokens.next() {
 		Token::Ident(name) => name,
-		t => {
-			return Err(Error::SyntaxError(format!(
-				"expected an identifier but got \"{t}\""
-			)))
-		}
+		t => return Err(expected_ident(t, tokens.prev_span())),
 	};
+	let name = tokens.prev_span();
 	match tokens.next() {
 		Token::Def => (),
 		t => {
-			return Err(Error::SyntaxError(format!(
-				"expected \"{}\" but got \"{t}\"",
-				Token::Def
-			)))
+			return Err(Error::SyntaxError(
+				format!("expected \"{}\" but got \"{t}\"", Token::Def),
+				tokens.prev_span(),
+			))
 		}
 	};
 	let e = parse_e(tokens)?;
-	Ok((x, e))
+	Ok(((x, e), name))
+}
+
+/// Turns a group of definitions that may refer to each other, and to
+/// themselves, into equivalent definitions that do not, keeping their order.
+///
+/// A definition `f = e` that refers to itself becomes `f = Y (\f.e)`.
+/// Definitions `f1 = e1 ... fn = en` that are mutually recursive are instead
+/// defined together as a tuple, with `fi = (Y (\p.\s.s e1' ... en')) pi`,
+/// where `pi` projects the `i`-th element and `ei'` is `ei` with each `fj`
+/// replaced by `p pj`. Any other definition `f = e` of the group is kept,
+/// with the definitions `e` uses substituted in.
+///
+/// `spans` are where the names of the definitions are, to point out the ones
+/// that are defined twice.
+fn resolve_rec(group: Vec<Decl>, spans: &[Span]) -> Result<Vec<Decl>, Error> {
+	let n = group.len();
+	let mut index = HashMap::new();
+	for (i, (x, _)) in group.iter().enumerate() {
+		if index.insert(x.as_str(), i).is_some() {
+			return Err(Error::SyntaxError(
+				format!("\"{x}\" is defined more than once with \"{}\"", Token::Rec),
+				spans[i],
+			));
+		}
+	}
+	// reaches[i][j] is whether definition i depends on definition j, directly
+	// or through other definitions
+	let mut reaches = vec![vec![false; n]; n];
+	for (i, (_, e)) in group.iter().enumerate() {
+		for x in free_vars(e) {
+			if let Some(&j) = index.get(x) {
+				reaches[i][j] = true;
+			}
+		}
+	}
+	for k in 0..n {
+		let via_k = reaches[k].clone();
+		for row in &mut reaches {
+			if row[k] {
+				for (r, v) in row.iter_mut().zip(&via_k) {
+					*r |= v;
+				}
+			}
+		}
+	}
+	let mut used: HashSet<&str> = group.iter().flat_map(|(_, e)| names(e)).collect();
+	used.extend(index.keys());
+	let p = fresh("p", &used);
+	let s = fresh("s", &used);
+	let var = |x: &str| Box::new(Expr::Var(x.to_owned(), Span::default()));
+	let fun = |x: &str, body| Box::new(Expr::Fun(x.to_owned(), body, Span::default()));
+	let app = |f, a| Box::new(Expr::App(f, a, Span::default()));
+	let y = || {
+		let half = fun("x", app(var("f"), app(var("x"), var("x"))));
+		fun("f", app(half.clone(), half))
+	};
+
+	let mut resolved: Vec<Option<Box<Expr>>> = vec![None; n];
+	// Resolve one group of mutually recursive definitions at a time, starting
+	// with the ones that only depend on resolved definitions
+	while let Some(i) = (0..n).find(|&i| {
+		resolved[i].is_none()
+			&& (0..n).all(|j| resolved[j].is_some() || !reaches[i][j] || reaches[j][i])
+	}) {
+		let scc: Vec<usize> = (0..n)
+			.filter(|&j| j == i || (reaches[i][j] && reaches[j][i]))
+			.collect();
+		let mut decls: Vec<Decl> = (0..n)
+			.filter_map(|j| Some((group[j].0.clone(), resolved[j].clone()?)))
+			.collect();
+		match scc[..] {
+			[j] if !reaches[j][j] => resolved[j] = Some(inline_decls(&group[j].1, &decls)),
+			[j] => {
+				let (x, e) = &group[j];
+				resolved[j] = Some(app(y(), fun(x, inline_decls(e, &decls))));
+			}
+			_ => {
+				// The selectors are closed, so their names do not matter
+				let selectors: Vec<String> = (1..=scc.len()).map(|k| format!("x{k}")).collect();
+				let project = |t: Box<Expr>, k: usize| {
+					let mut selector = var(&selectors[k]);
+					for x in selectors.iter().rev() {
+						selector = fun(x, selector);
+					}
+					app(t, selector)
+				};
+				for (k, &j) in scc.iter().enumerate() {
+					decls.push((group[j].0.clone(), project(var(&p), k)));
+				}
+				let mut tuple = var(&s);
+				for &j in &scc {
+					tuple = app(tuple, inline_decls(&group[j].1, &decls));
+				}
+				let fixpoint = app(y(), fun(&p, fun(&s, tuple)));
+				for (k, &j) in scc.iter().enumerate() {
+					resolved[j] = Some(project(fixpoint.clone(), k));
+				}
+			}
+		}
+	}
+	let resolved = group
+		.into_iter()
+		.zip(resolved)
+		.map(|((x, _), e)| (x, e.expect("every definition is resolved")))
+		.collect();
+	Ok(resolved)
+}
+
+/// Returns `base`, followed by as many primes as needed for it not to be in
+/// `used`.
+pub(crate) fn fresh(base: &str, used: &HashSet<&str>) -> String {
+	let mut name = base.to_owned();
+	while used.contains(name.as_str()) {
+		name.push('\'');
+	}
+	name
 }
 
-fn inline_decls(e: &Expr, decls: &[(String, Box<Expr>)]) -> Box<Expr> {
+/// Replaces the variables declared with `where` by their definitions.
+pub fn inline_decls(e: &Expr, decls: &[Decl]) -> Box<Expr> {
 	let mut e_by_var = HashMap::new();
 	for (x, e) in decls.iter().rev() {
 		e_by_var.insert(x, inline(e, &e_by_var));
@@ -177,7 +692,24 @@ fn inline_decls(e: &Expr, decls: &[(String, Box<Expr>)]) -> Box<Expr> {
 }
 
 fn inline(e: &Expr, decls: &HashMap<&String, Box<Expr>>) -> Box<Expr> {
-	let mut arg_stack = Vec::new();
+	// Lambdas binding a variable that is free in some definition are renamed,
+	// so that substituting the definition cannot capture it
+	let mut captured = HashSet::new();
+	let mut used: HashSet<String> = names(e).into_iter().map(str::to_owned).collect();
+	for e in decls.values() {
+		captured.extend(free_vars(e));
+		used.extend(names(e).into_iter().map(str::to_owned));
+	}
+	let mut fresh = |x: &String| {
+		let mut name = x.clone();
+		while used.contains(&name) {
+			name.push('\'');
+		}
+		used.insert(name.clone());
+		name
+	};
+	// The name of each enclosing lambda, and what it was renamed to
+	let mut arg_stack: Vec<(&String, String)> = Vec::new();
 	let mut e_stack = vec![(false, e)];
 	let mut result_stack = Vec::new();
 	while let Some((visited, e)) = e_stack.pop() {
@@ -185,60 +717,137 @@ fn inline(e: &Expr, decls: &HashMap<&String, Box<Expr>>) -> Box<Expr> {
 			e_stack.push((true, e));
 		}
 		match (visited, e) {
-			(false, Expr::Var(_)) => {}
-			(false, Expr::Fun(x, body)) => {
-				arg_stack.push(x);
+			(false, Expr::Var(_, _)) => {}
+			(false, Expr::Fun(x, body, _)) => {
+				let renamed = if captured.contains(x.as_str()) {
+					fresh(x)
+				} else {
+					x.clone()
+				};
+				arg_stack.push((x, renamed));
 				e_stack.push((false, body));
 			}
-			(false, Expr::App(e1, e2)) => {
+			(false, Expr::App(e1, e2, _)) => {
 				e_stack.push((false, e2));
 				e_stack.push((false, e1));
 			}
-			(true, Expr::Var(name)) => {
-				let e = if arg_stack.contains(&name) {
-					Expr::Var(name.clone())
-				} else {
-					match decls.get(&name) {
+			(true, Expr::Var(name, span)) => {
+				// Substituted definitions keep their own spans
+				let e = match arg_stack.iter().rev().find(|(x, _)| *x == name) {
+					Some((_, renamed)) => Expr::Var(renamed.clone(), *span),
+					None => match decls.get(&name) {
 						Some(e) => *e.clone(),
-						None => Expr::Var(name.clone()),
-					}
+						None => Expr::Var(name.clone(), *span),
+					},
 				};
 				result_stack.push(Box::new(e));
 			}
-			(true, Expr::Fun(x, _)) => {
-				match arg_stack.pop() {
-					Some(_) => (),
+			(true, Expr::Fun(_, _, span)) => {
+				let x = match arg_stack.pop() {
+					Some((_, renamed)) => renamed,
 					None => panic!("missing argument"),
 				};
 				let body = match result_stack.pop() {
 					Some(e) => e,
 					None => panic!("missing result for function abstraction"),
 				};
-				result_stack.push(Box::new(Expr::Fun(x.clone(), body)));
+				result_stack.push(Box::new(Expr::Fun(x, body, *span)));
 			}
-			(true, Expr::App(_, _)) => {
+			(true, Expr::App(_, _, span)) => {
 				let (e1, e2) = match (result_stack.pop(), result_stack.pop()) {
 					(Some(e2), Some(e1)) => (e1, e2),
 					_ => panic!("missing result for function application"),
 				};
-				result_stack.push(Box::new(Expr::App(e1, e2)));
+				result_stack.push(Box::new(Expr::App(e1, e2, *span)));
 			}
 		}
 	}
 	result_stack.pop().unwrap()
 }
 
+/// Returns the variables that occur free in `e`.
+pub(crate) fn free_vars(e: &Expr) -> HashSet<&str> {
+	free_occurrences(e).into_iter().map(|(x, _)| x).collect()
+}
+
+/// Returns each free occurrence of a variable in `e`, from left to right,
+/// along with where it is.
+pub(crate) fn free_occurrences(e: &Expr) -> Vec<(&str, Span)> {
+	enum Work<'e> {
+		Visit(&'e Expr),
+		Unbind,
+	}
+	let mut free = Vec::new();
+	let mut bound = Vec::new();
+	let mut work = vec![Work::Visit(e)];
+	while let Some(w) = work.pop() {
+		match w {
+			Work::Visit(Expr::Var(x, span)) => {
+				if !bound.contains(&x.as_str()) {
+					free.push((x.as_str(), *span));
+				}
+			}
+			Work::Visit(Expr::Fun(x, body, _)) => {
+				bound.push(x.as_str());
+				work.push(Work::Unbind);
+				work.push(Work::Visit(body));
+			}
+			Work::Visit(Expr::App(f, a, _)) => {
+				work.push(Work::Visit(a));
+				work.push(Work::Visit(f));
+			}
+			Work::Unbind => {
+				bound.pop();
+			}
+		}
+	}
+	free
+}
+
+/// Returns every variable name that occurs in `e`, bound or free.
+fn names(e: &Expr) -> HashSet<&str> {
+	let mut names = HashSet::new();
+	let mut stack = vec![e];
+	while let Some(e) = stack.pop() {
+		match e {
+			Expr::Var(x, _) => {
+				names.insert(x.as_str());
+			}
+			Expr::Fun(x, body, _) => {
+				names.insert(x.as_str());
+				stack.push(body);
+			}
+			Expr::App(f, a, _) => {
+				stack.push(a);
+				stack.push(f);
+			}
+		}
+	}
+	names
+}
+
 #[cfg(test)]
 mod parse_tests {
 	use std::collections::VecDeque;
 
+	use crate::lex::lex;
 	use crate::parse::*;
 
+	fn span(start: usize, end: usize, line: usize, column: usize) -> Span {
+		Span {
+			start,
+			end,
+			line,
+			column,
+		}
+	}
+
 	#[test]
 	fn parse_identity() -> () {
 		let f = Box::new(Expr::Fun(
 			"x".to_owned(),
-			Box::new(Expr::Var("x".to_owned())),
+			Box::new(Expr::Var("x".to_owned(), Span::default())),
+			Span::default(),
 		));
 		let tokens = VecDeque::from(vec![
 			Token::Lambda,
@@ -246,7 +855,7 @@ mod parse_tests {
 			Token::Dot,
 			Token::Ident("x".to_owned()),
 		]);
-		let mut stm = TokenStream { tokens };
+		let mut stm = TokenStream::from(tokens);
 		assert_eq!(Ok(f), parse(&mut stm));
 	}
 
@@ -257,8 +866,10 @@ mod parse_tests {
 			"s".to_owned(),
 			Box::new(Expr::Fun(
 				"z".to_owned(),
-				Box::new(Expr::Var("z".to_owned())),
+				Box::new(Expr::Var("z".to_owned(), Span::default())),
+				Span::default(),
 			)),
+			Span::default(),
 		));
 		let tokens = VecDeque::from(vec![
 			Token::Lambda,
@@ -269,7 +880,7 @@ mod parse_tests {
 			Token::Dot,
 			Token::Ident("z".to_owned()),
 		]);
-		let mut stm = TokenStream { tokens };
+		let mut stm = TokenStream::from(tokens);
 		assert_eq!(Ok(f), parse(&mut stm));
 	}
 
@@ -281,10 +892,13 @@ mod parse_tests {
 			Box::new(Expr::Fun(
 				"z".to_owned(),
 				Box::new(Expr::App(
-					Box::new(Expr::Var("s".to_owned())),
-					Box::new(Expr::Var("z".to_owned())),
+					Box::new(Expr::Var("s".to_owned(), Span::default())),
+					Box::new(Expr::Var("z".to_owned(), Span::default())),
+					Span::default(),
 				)),
+				Span::default(),
 			)),
+			Span::default(),
 		));
 		let tokens = VecDeque::from(vec![
 			Token::Lambda,
@@ -298,7 +912,7 @@ mod parse_tests {
 			Token::Ident("z".to_owned()),
 			Token::Rpar,
 		]);
-		let mut stm = TokenStream { tokens };
+		let mut stm = TokenStream::from(tokens);
 		assert_eq!(Ok(f), parse(&mut stm));
 	}
 
@@ -310,13 +924,17 @@ mod parse_tests {
 			Box::new(Expr::Fun(
 				"z".to_owned(),
 				Box::new(Expr::App(
-					Box::new(Expr::Var("s".to_owned())),
+					Box::new(Expr::Var("s".to_owned(), Span::default())),
 					Box::new(Expr::App(
-						Box::new(Expr::Var("s".to_owned())),
-						Box::new(Expr::Var("z".to_owned())),
+						Box::new(Expr::Var("s".to_owned(), Span::default())),
+						Box::new(Expr::Var("z".to_owned(), Span::default())),
+						Span::default(),
 					)),
+					Span::default(),
 				)),
+				Span::default(),
 			)),
+			Span::default(),
 		));
 		let tokens = VecDeque::from(vec![
 			Token::Lambda,
@@ -333,7 +951,7 @@ mod parse_tests {
 			Token::Rpar,
 			Token::Rpar,
 		]);
-		let mut stm = TokenStream { tokens };
+		let mut stm = TokenStream::from(tokens);
 		assert_eq!(Ok(f), parse(&mut stm));
 	}
 
@@ -349,13 +967,18 @@ mod parse_tests {
 					"c".to_owned(),
 					Box::new(Expr::App(
 						Box::new(Expr::App(
-							Box::new(Expr::Var("a".to_owned())),
-							Box::new(Expr::Var("b".to_owned())),
+							Box::new(Expr::Var("a".to_owned(), Span::default())),
+							Box::new(Expr::Var("b".to_owned(), Span::default())),
+							Span::default(),
 						)),
-						Box::new(Expr::Var("c".to_owned())),
+						Box::new(Expr::Var("c".to_owned(), Span::default())),
+						Span::default(),
 					)),
+					Span::default(),
 				)),
+				Span::default(),
 			)),
+			Span::default(),
 		));
 		let tokens = VecDeque::from(vec![
 			Token::Lambda,
@@ -371,23 +994,17 @@ mod parse_tests {
 			Token::Ident("b".to_owned()),
 			Token::Ident("c".to_owned()),
 		]);
-		let mut stm = TokenStream { tokens };
+		let mut stm = TokenStream::from(tokens);
 		assert_eq!(Ok(f), parse(&mut stm));
 	}
 
 	#[test]
 	fn parse_too_many_rparens() -> () {
-		// (x))
-		let tokens = VecDeque::from(vec![
-			Token::Lpar,
-			Token::Ident("x".to_owned()),
-			Token::Rpar,
-			Token::Rpar,
-		]);
-		let mut stm = TokenStream { tokens };
+		let mut stm = lex("(x))").unwrap();
 		assert_eq!(
 			Err(Error::SyntaxError(
-				"unexpected trailing token \")\"".to_owned()
+				"unexpected trailing token \")\"".to_owned(),
+				span(3, 4, 1, 4)
 			)),
 			parse(&mut stm)
 		);
@@ -395,20 +1012,64 @@ mod parse_tests {
 
 	#[test]
 	fn parse_unclosed_lparen() -> () {
-		// ((x)
-		let tokens = VecDeque::from(vec![
-			Token::Lpar,
-			Token::Lpar,
-			Token::Ident("x".to_owned()),
-			Token::Rpar,
-		]);
-		let mut stm = TokenStream { tokens };
+		let mut stm = lex("((x)").unwrap();
 		assert_eq!(
-			Err(Error::SyntaxError("unclosed parenthesis".to_owned())),
+			Err(Error::SyntaxError(
+				"unclosed parenthesis".to_owned(),
+				span(0, 1, 1, 1)
+			)),
 			parse(&mut stm)
 		);
 	}
 
+	#[test]
+	fn parse_multiple_binders() -> () {
+		// \s z.s z
+		let f = Box::new(Expr::Fun(
+			"s".to_owned(),
+			Box::new(Expr::Fun(
+				"z".to_owned(),
+				Box::new(Expr::App(
+					Box::new(Expr::Var("s".to_owned(), Span::default())),
+					Box::new(Expr::Var("z".to_owned(), Span::default())),
+					Span::default(),
+				)),
+				Span::default(),
+			)),
+			Span::default(),
+		));
+		let mut stm = lex("\\s z.s z").unwrap();
+		assert_eq!(Ok(f.clone()), parse(&mut stm));
+		let mut stm = lex("\\s.\\z.s z").unwrap();
+		assert_eq!(Ok(f), parse(&mut stm));
+	}
+
+	#[test]
+	fn parse_lambda_without_binder() -> () {
+		let mut stm = lex("\\.x").unwrap();
+		assert_eq!(
+			Err(Error::SyntaxError(
+				"expected an identifier but got \".\"".to_owned(),
+				span(1, 2, 1, 2)
+			)),
+			parse(&mut stm)
+		);
+		let mut stm = lex("\\x y x").unwrap();
+		assert!(parse(&mut stm).is_err());
+	}
+
+	#[test]
+	fn parse_former_operator_name() -> () {
+		// `<<` used to be an identifier, but `<` now starts a tuple
+		assert_eq!(
+			Err(Error::SyntaxError(
+				"expected an identifier but got \"<\"".to_owned(),
+				span(8, 9, 1, 9)
+			)),
+			parse_str("x where << = \\a.a")
+		);
+	}
+
 	#[test]
 	fn parse_with_decls() -> () {
 		// x y where x = y y (\y.y) where y = \z.z z
@@ -418,30 +1079,40 @@ mod parse_tests {
 					Box::new(Expr::Fun(
 						"z".to_owned(),
 						Box::new(Expr::App(
-							Box::new(Expr::Var("z".to_owned())),
-							Box::new(Expr::Var("z".to_owned())),
+							Box::new(Expr::Var("z".to_owned(), Span::default())),
+							Box::new(Expr::Var("z".to_owned(), Span::default())),
+							Span::default(),
 						)),
+						Span::default(),
 					)),
 					Box::new(Expr::Fun(
 						"z".to_owned(),
 						Box::new(Expr::App(
-							Box::new(Expr::Var("z".to_owned())),
-							Box::new(Expr::Var("z".to_owned())),
+							Box::new(Expr::Var("z".to_owned(), Span::default())),
+							Box::new(Expr::Var("z".to_owned(), Span::default())),
+							Span::default(),
 						)),
+						Span::default(),
 					)),
+					Span::default(),
 				)),
 				Box::new(Expr::Fun(
 					"y".to_owned(),
-					Box::new(Expr::Var("y".to_owned())),
+					Box::new(Expr::Var("y".to_owned(), Span::default())),
+					Span::default(),
 				)),
+				Span::default(),
 			)),
 			Box::new(Expr::Fun(
 				"z".to_owned(),
 				Box::new(Expr::App(
-					Box::new(Expr::Var("z".to_owned())),
-					Box::new(Expr::Var("z".to_owned())),
+					Box::new(Expr::Var("z".to_owned(), Span::default())),
+					Box::new(Expr::Var("z".to_owned(), Span::default())),
+					Span::default(),
 				)),
+				Span::default(),
 			)),
+			Span::default(),
 		));
 		let tokens = VecDeque::from(vec![
 			Token::Ident("x".to_owned()),
@@ -466,7 +1137,346 @@ mod parse_tests {
 			Token::Ident("z".to_owned()),
 			Token::Ident("z".to_owned()),
 		]);
-		let mut stm = TokenStream { tokens };
+		let mut stm = TokenStream::from(tokens);
 		assert_eq!(Ok(e), parse(&mut stm));
 	}
+
+	fn parse_str(src: &str) -> Result<Box<Expr>, Error> {
+		parse(&mut lex(src).unwrap())
+	}
+
+	#[test]
+	fn parse_let() -> () {
+		assert_eq!(
+			parse_str("(\\x.x) (\\x.x)"),
+			parse_str("let id = \\x.x in id id")
+		);
+		// Only the body of the `let` is affected
+		assert_eq!(
+			parse_str("f (\\y.y) x"),
+			parse_str("f (let x = \\y.y in x) x")
+		);
+		assert_eq!(parse_str("\\x.x"), parse_str("let x = \\y.y in \\x.x"));
+		assert_eq!(
+			parse_str("(\\x.x) (\\a.a)"),
+			parse_str("let x = z in (\\x.x) x where z = \\a.a")
+		);
+	}
+
+	#[test]
+	fn parse_let_several_bindings() -> () {
+		// Each binding can use the previous ones
+		assert_eq!(
+			parse_str("(\\x.x) (\\x.x) (\\s.s)"),
+			parse_str("let a = \\x.x b = a a c = \\s.s in b c")
+		);
+		assert_eq!(
+			parse_str("let a = \\x.x in let b = a a in b"),
+			parse_str("let a = \\x.x b = a a in b")
+		);
+	}
+
+	#[test]
+	fn parse_let_avoids_capture() -> () {
+		assert_eq!(
+			parse_str("\\y.\\y'.y y'"),
+			parse_str("\\y.let x = y in \\y.x y")
+		);
+	}
+
+	#[test]
+	fn parse_rec() -> () {
+		let y = "(\\f.(\\x.f (x x)) (\\x.f (x x)))";
+		assert_eq!(
+			parse_str(&format!("{y} (\\g.\\n.g n)")),
+			parse_str("g where rec g = \\n.g n")
+		);
+		assert_eq!(
+			parse_str(&format!("\\z.{y} (\\g.\\n.g n) z")),
+			parse_str("let rec g = \\n.g n in \\z.g z")
+		);
+		// Definitions that are not recursive are left alone
+		assert_eq!(
+			parse_str("\\a.\\b.a"),
+			parse_str("k where rec k = \\a.\\b.a")
+		);
+		// Recursive definitions can come in any order
+		assert_eq!(
+			parse_str(&format!("{y} (\\g.\\n.g (\\z.z))")),
+			parse_str("g where rec g = \\n.g i where rec i = \\z.z")
+		);
+	}
+
+	#[test]
+	fn parse_rec_mutual() -> () {
+		let y = "(\\f.(\\x.f (x x)) (\\x.f (x x)))";
+		let fixpoint =
+			format!("({y} (\\p.\\s.s (\\n.p (\\x1.\\x2.x2) n) (\\n.p (\\x1.\\x2.x1) n)))");
+		assert_eq!(
+			parse_str(&format!(
+				"{fixpoint} (\\x1.\\x2.x1) ({fixpoint} (\\x1.\\x2.x2))"
+			)),
+			parse_str("a b where rec a = \\n.b n where rec b = \\n.a n")
+		);
+		assert_eq!(
+			Err(Error::SyntaxError(
+				"\"a\" is defined more than once with \"rec\"".to_owned(),
+				span(28, 29, 1, 29)
+			)),
+			parse_str("a where rec a = a where rec a = a")
+		);
+	}
+
+	#[test]
+	fn parse_literals() -> () {
+		assert_eq!(parse_str("\\t.\\f.t"), parse_str("true"));
+		assert_eq!(parse_str("\\t.\\f.f"), parse_str("false"));
+		assert_eq!(parse_str("\\x.\\t.\\f.t"), parse_str("[]"));
+		assert_eq!(
+			parse_str("\\p.p a (\\p.p (b c) (\\x.\\t.\\f.t))"),
+			parse_str("[a, b c]")
+		);
+		assert_eq!(parse_str("\\s.s"), parse_str("<>"));
+		assert_eq!(
+			parse_str("\\s.s a (\\s.s) (\\x.x)"),
+			parse_str("<a, <>, \\x.x>")
+		);
+		assert_eq!(
+			parse_str("f (\\s.s a) (\\t.\\f.t)"),
+			parse_str("f <a> true")
+		);
+	}
+
+	#[test]
+	fn parse_string_and_char() -> () {
+		let nil = "\\x.\\t.\\f.t";
+		assert_eq!(parse_str(nil), parse_str("\"\""));
+		assert_eq!(
+			parse_str(&format!(
+				"\\p.p (\\s.\\z.s (s z)) (\\p.p (\\s.\\z.z) ({nil}))"
+			)),
+			parse_str("\"\\u{2}\\0\"")
+		);
+		assert_eq!(parse_str("f (\\s.\\z.s z)"), parse_str("f '\\u{1}'"));
+	}
+
+	#[test]
+	fn parse_string_and_char_too_large() -> () {
+		assert_eq!(
+			Err(Error::SyntaxError(
+				"character literal is too large: code point 1114111 is more than 1000000"
+					.to_owned(),
+				span(2, 14, 1, 3)
+			)),
+			parse_str("f '\\u{10FFFF}'")
+		);
+		let s = "\\u{FFFF}".repeat(16);
+		assert_eq!(
+			Err(Error::SyntaxError(
+				"string literal is too large: its code points add up to more than 1000000"
+					.to_owned(),
+				span(2, 4 + s.len(), 1, 3)
+			)),
+			parse_str(&format!("f \"{s}\" x"))
+		);
+	}
+
+	#[test]
+	fn parse_nested_app_spans() -> () {
+		let e = parse_str("f (g x) y").unwrap();
+		assert_eq!(span(0, 9, 1, 1), e.span());
+		let Expr::App(f, y, _) = e.as_ref() else {
+			panic!("expected an application")
+		};
+		assert_eq!(span(8, 9, 1, 9), y.span());
+		assert_eq!(span(0, 7, 1, 1), f.span());
+		let Expr::App(f, gx, _) = f.as_ref() else {
+			panic!("expected an application")
+		};
+		assert_eq!(span(0, 1, 1, 1), f.span());
+		// The parentheses are part of the argument
+		assert_eq!(span(2, 7, 1, 3), gx.span());
+		let Expr::App(g, x, _) = gx.as_ref() else {
+			panic!("expected an application")
+		};
+		assert_eq!(span(3, 4, 1, 4), g.span());
+		assert_eq!(span(5, 6, 1, 6), x.span());
+	}
+
+	#[test]
+	fn parse_fun_spans() -> () {
+		let e = parse_str("\\x y.\nx").unwrap();
+		assert_eq!(span(0, 7, 1, 1), e.span());
+		let Expr::Fun(_, e, _) = e.as_ref() else {
+			panic!("expected a function")
+		};
+		assert_eq!(span(3, 7, 1, 4), e.span());
+		let Expr::Fun(_, e, _) = e.as_ref() else {
+			panic!("expected a function")
+		};
+		assert_eq!(span(6, 7, 2, 1), e.span());
+	}
+
+	#[test]
+	fn parse_let_spans() -> () {
+		let e = parse_str("let f = \\a.a in f f").unwrap();
+		assert_eq!(span(0, 19, 1, 1), e.span());
+		// Each use of `f` is where `f` is defined
+		let Expr::App(f1, f2, _) = e.as_ref() else {
+			panic!("expected an application")
+		};
+		for f in [f1, f2] {
+			assert_eq!(span(8, 12, 1, 9), f.span());
+			let Expr::Fun(_, a, _) = f.as_ref() else {
+				panic!("expected a function")
+			};
+			assert_eq!(span(11, 12, 1, 12), a.span());
+		}
+	}
+
+	#[test]
+	fn parse_literals_avoid_capture() -> () {
+		assert_eq!(parse_str("\\s'.s' s"), parse_str("<s>"));
+		assert_eq!(parse_str("\\p'.p' p (\\x.\\t.\\f.t)"), parse_str("[p]"));
+	}
+
+	#[test]
+	fn parse_literals_malformed() -> () {
+		assert_eq!(
+			Err(Error::SyntaxError(
+				"unclosed \"[\"".to_owned(),
+				span(0, 1, 1, 1)
+			)),
+			parse_str("[a, b")
+		);
+		assert_eq!(
+			Err(Error::SyntaxError(
+				"expected \",\" or \">\" but got \"]\"".to_owned(),
+				span(2, 3, 1, 3)
+			)),
+			parse_str("<a]")
+		);
+		assert_eq!(
+			Err(Error::SyntaxError(
+				"unexpected token \",\"".to_owned(),
+				span(3, 4, 1, 4)
+			)),
+			parse_str("[a,,b]")
+		);
+	}
+
+	#[test]
+	fn parse_let_missing_in() -> () {
+		assert_eq!(
+			Err(Error::SyntaxError(
+				"expected \"in\" but got \"<END>\"".to_owned(),
+				span(14, 14, 1, 15)
+			)),
+			parse_str("let x = \\y.y x")
+		);
+		assert_eq!(
+			Err(Error::SyntaxError(
+				"expected an identifier but got \"in\", which is a keyword".to_owned(),
+				span(4, 6, 1, 5)
+			)),
+			parse_str("let in x")
+		);
+	}
+
+	#[test]
+	fn parse_keyword_as_name() -> () {
+		assert_eq!(
+			Err(Error::SyntaxError(
+				"expected an identifier but got \"true\", which is a keyword".to_owned(),
+				span(1, 5, 1, 2)
+			)),
+			parse_str("\\true.x")
+		);
+		assert_eq!(
+			Err(Error::SyntaxError(
+				"unexpected token \"rec\", which is a keyword".to_owned(),
+				span(12, 15, 1, 13)
+			)),
+			parse_str("x where y = rec")
+		);
+	}
+
+	#[test]
+	fn find_assertions_equal_and_type() -> () {
+		let stm =
+			crate::lex::lex("{:: bool } {:assert not T == F } {:assert-type bool } x").unwrap();
+		let assertions = find_assertions(&stm).unwrap();
+		assert_eq!(2, assertions.len());
+		assert_eq!(":assert not T == F", assertions[0].directive);
+		assert_eq!(
+			Check::Equal(
+				Box::new(Expr::App(
+					Box::new(Expr::Var("not".to_owned(), Span::default())),
+					Box::new(Expr::Var("T".to_owned(), Span::default())),
+					Span::default(),
+				)),
+				Box::new(Expr::Var("F".to_owned(), Span::default())),
+			),
+			assertions[0].check
+		);
+		assert_eq!(Check::Type("bool".to_owned()), assertions[1].check);
+	}
+
+	#[test]
+	fn find_assertions_ignores_other_comments() -> () {
+		let stm = crate::lex::lex("{ assert x == y } {:asserted x == y } x").unwrap();
+		assert_eq!(Ok(vec![]), find_assertions(&stm));
+	}
+
+	#[test]
+	fn find_assertions_malformed() -> () {
+		let stm = crate::lex::lex("{:assert x } x").unwrap();
+		assert!(matches!(
+			find_assertions(&stm),
+			Err(Error::SyntaxError(_, _))
+		));
+		// The spans of errors in the expressions are positions in the program
+		let stm = crate::lex::lex("x\n{:assert x == (y }").unwrap();
+		assert_eq!(
+			Err(Error::SyntaxError(
+				"unclosed parenthesis".to_owned(),
+				span(16, 17, 2, 15)
+			)),
+			find_assertions(&stm)
+		);
+	}
+
+	#[test]
+	fn find_numerals_pragma() -> () {
+		let find = |src| find_numerals(&lex(src).unwrap());
+		assert_eq!(Ok(None), find("{:: church } 2"));
+		assert_eq!(Ok(Some(NumeralEncoding::Church)), find("{:numerals} 2"));
+		assert_eq!(
+			Ok(Some(NumeralEncoding::Scott)),
+			find("2 {:numerals scott }")
+		);
+		assert_eq!(
+			Err(Error::SyntaxError(
+				"unknown numeral encoding \"roman\"".to_owned(),
+				span(0, 17, 1, 1)
+			)),
+			find("{:numerals roman} 2")
+		);
+	}
+
+	#[test]
+	fn clone_and_compare_deep_expr() -> () {
+		let mut e = Box::new(Expr::Var("z".to_owned(), Span::default()));
+		for _ in 0..1_000_000 {
+			e = Box::new(Expr::App(
+				Box::new(Expr::Var("s".to_owned(), Span::default())),
+				e,
+				Span::default(),
+			));
+		}
+		let copy = e.clone();
+		assert_eq!(e, copy);
+		let other = Box::new(Expr::Fun("s".to_owned(), copy, Span::default()));
+		assert_ne!(e, other);
+	}
 }
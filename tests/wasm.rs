@@ -1,4 +1,12 @@
-use lambda::eval_lambda;
+use lambda::equiv::Equivalence;
+use lambda::error::{Diagnostic, Error};
+use lambda::eval::{Engine, Strategy};
+use lambda::lex::Span;
+use lambda::literals::NumeralEncoding;
+use lambda::{
+	alpha_eq_lambda, beta_eq_lambda, check_assertions, eval_lambda, eval_lambda_with_options,
+	eval_lambda_with_stats, trace_lambda, EvalOptions, Evaluation,
+};
 use wasm_bindgen_test::wasm_bindgen_test;
 
 wasm_bindgen_test::wasm_bindgen_test_configure!(run_in_browser);
@@ -195,3 +203,629 @@ fn test_list_bool() {
 		Ok("[true, false, false]".to_owned())
 	);
 }
+
+#[wasm_bindgen_test]
+fn test_step_limit() {
+	let mut options = EvalOptions::new();
+	options.set_max_steps(Some(1000));
+
+	let growing = "(\\x.x x x) (\\x.x x x)";
+	let err = eval_lambda_with_options(growing, &options).unwrap_err();
+	assert!(err.to_string().starts_with("Step limit exceeded"));
+
+	let e = "succ (\\s.\\z.z)\nwhere succ = \\n.\\s.\\z.s(n s z)";
+	assert_eq!(
+		eval_lambda_with_options(e, &options),
+		Ok("\\a.\\b.a b".to_owned())
+	);
+}
+
+#[wasm_bindgen_test]
+fn test_strategies() {
+	let e = "(\\x.\\y.x) ((\\z.z) (\\z.z))";
+	let mut options = EvalOptions::new();
+
+	options.set_strategy(Strategy::NormalOrder);
+	assert_eq!(
+		eval_lambda_with_options(e, &options),
+		Ok("\\a.\\b.b".to_owned())
+	);
+	options.set_strategy(Strategy::ApplicativeOrder);
+	assert_eq!(
+		eval_lambda_with_options(e, &options),
+		Ok("\\a.\\b.b".to_owned())
+	);
+	options.set_strategy(Strategy::CallByValue);
+	assert_eq!(
+		eval_lambda_with_options(e, &options),
+		Ok("\\a.\\b.b".to_owned())
+	);
+	options.set_strategy(Strategy::CallByName);
+	assert_eq!(
+		eval_lambda_with_options(e, &options),
+		Ok("\\a.(\\b.b) (\\b.b)".to_owned())
+	);
+}
+
+#[wasm_bindgen_test]
+fn test_call_by_need() {
+	// Evaluating `(\y.y) (\z.z)` once and sharing it takes 3 steps, whereas
+	// call-by-name takes 4
+	let e = "(\\x.x x) ((\\y.y) (\\z.z))";
+	let mut options = EvalOptions::new();
+	options.set_max_steps(Some(3));
+	options.set_strategy(Strategy::CallByNeed);
+	assert_eq!(
+		eval_lambda_with_options(e, &options),
+		Ok("\\a.a".to_owned())
+	);
+	// Rewriting the whole expression cannot share the argument
+	assert!(trace_lambda(e, &options).is_err());
+	assert!(eval_lambda_with_stats(e, &options).is_err());
+	assert!(Evaluation::new(e, &options).is_err());
+	options.set_strategy(Strategy::CallByName);
+	assert!(eval_lambda_with_options(e, &options).is_err());
+}
+
+#[wasm_bindgen_test]
+fn test_trace() {
+	let e = "
+		not T
+		where not = \\b.b F T
+		where   T = \\t.\\f.t
+		where   F = \\t.\\f.f"
+		.trim();
+	let steps = trace_lambda(e, &EvalOptions::new()).unwrap();
+	let exprs: Vec<String> = steps.iter().map(|s| s.expr()).collect();
+	assert_eq!(
+		exprs,
+		vec![
+			"(\\a.a (\\b.\\c.c) (\\b.\\c.b)) (\\a.\\b.a)",
+			"(\\a.\\b.a) (\\a.\\b.b) (\\a.\\b.a)",
+			"(\\a.\\b.\\c.c) (\\a.\\b.a)",
+			"\\a.\\b.b",
+		]
+	);
+	let redexes: Vec<Option<String>> = steps
+		.iter()
+		.map(|s| Some(s.expr()[s.redex_start()?..s.redex_end()?].to_owned()))
+		.collect();
+	assert_eq!(
+		redexes,
+		vec![
+			Some("(\\a.a (\\b.\\c.c) (\\b.\\c.b)) (\\a.\\b.a)".to_owned()),
+			Some("(\\a.\\b.a) (\\a.\\b.b)".to_owned()),
+			Some("(\\a.\\b.\\c.c) (\\a.\\b.a)".to_owned()),
+			None,
+		]
+	);
+}
+
+#[wasm_bindgen_test]
+fn test_trace_complete_development() {
+	let e = "
+		not (not T)
+		where not = \\b.b F T
+		where   T = \\t.\\f.t
+		where   F = \\t.\\f.f"
+		.trim();
+	let mut options = EvalOptions::new();
+	options.set_strategy(Strategy::CompleteDevelopment);
+	let steps = trace_lambda(e, &options).unwrap();
+	assert_eq!(
+		"(\\a.a (\\b.\\c.c) (\\b.\\c.b)) ((\\a.a (\\b.\\c.c) (\\b.\\c.b)) (\\a.\\b.a))",
+		steps[0].expr()
+	);
+	assert_eq!(vec![0], steps[0].redex_starts());
+	assert_eq!(vec![steps[0].expr().len()], steps[0].redex_ends());
+	assert_eq!(
+		"(\\a.\\b.a) (\\a.\\b.b) (\\a.\\b.a) (\\a.\\b.b) (\\a.\\b.a)",
+		steps[1].expr()
+	);
+	assert_eq!(
+		Ok(steps.last().unwrap().expr()),
+		trace_lambda(e, &EvalOptions::new()).map(|s| s.last().unwrap().expr())
+	);
+	assert!(steps.len() < trace_lambda(e, &EvalOptions::new()).unwrap().len());
+}
+
+#[wasm_bindgen_test]
+fn test_eta() {
+	let e = "\\f.\\x.f x";
+	let mut options = EvalOptions::new();
+	assert_eq!(
+		eval_lambda_with_options(e, &options),
+		Ok("\\a.\\b.a b".to_owned())
+	);
+	options.set_eta(true);
+	assert_eq!(
+		eval_lambda_with_options(e, &options),
+		Ok("\\a.a".to_owned())
+	);
+	assert_eq!(
+		eval_lambda_with_options(&format!("{{:: church }} {e}"), &options),
+		Ok("1".to_owned())
+	);
+	assert_eq!(eval_lambda("{:: church } \\s.s"), Ok("1".to_owned()));
+}
+
+#[wasm_bindgen_test]
+fn test_let() {
+	let e = "{:: church }
+		let 2 = \\s z.s (s z)
+		    4 = + 2 2
+		in * 4 (let 2 = succ 1 in 2)
+		where + = \\m n s z.m s (n s z)
+		where * = \\m n s.m (n s)
+		where succ = \\n s z.s (n s z)
+		where 1 = \\s z.s z";
+	assert_eq!(eval_lambda(e), Ok("8".to_owned()));
+}
+
+#[wasm_bindgen_test]
+fn test_rec() {
+	let defs = "
+		where   if = \\b t f.b t f
+		where zero = \\n.n (\\x t f.f) (\\t f.t)
+		where pred = \\n f x.n (\\g h.h (g f)) (\\u.x) (\\u.u)
+		where    * = \\m n s.m (n s)
+		where    1 = \\s z.s z
+		where    3 = \\s z.s (s (s z))";
+	let fact = format!(
+		"{{:: church }} fact 3 where rec fact = \\n.if (zero n) 1 (* n (fact (pred n))) {defs}"
+	);
+	assert_eq!(eval_lambda(&fact), Ok("6".to_owned()));
+	let even = format!(
+		"{{:: bool }}
+		let rec
+			even = \\n.if (zero n) (\\t f.t) (odd (pred n))
+			odd = \\n.if (zero n) (\\t f.f) (even (pred n))
+		in odd 3 {defs}"
+	);
+	assert_eq!(eval_lambda(&even), Ok("true".to_owned()));
+}
+
+#[wasm_bindgen_test]
+fn test_numerals() {
+	let e = "{:: church } + 4 2 where + = \\m n.m succ n where succ = \\n s z.s (n s z)";
+	assert_eq!(
+		eval_lambda(&format!("{{:numerals}} {e}")),
+		Ok("6".to_owned())
+	);
+	let mut options = EvalOptions::new();
+	options.set_numerals(Some(NumeralEncoding::Church));
+	assert_eq!(eval_lambda_with_options(e, &options), Ok("6".to_owned()));
+	options.set_numerals(Some(NumeralEncoding::Binary));
+	assert_eq!(
+		eval_lambda_with_options("{:: list[bool] } 6", &options),
+		Ok("[false, true, true]".to_owned())
+	);
+	assert_eq!(
+		eval_lambda_with_options("{:numerals scott} 1", &options),
+		Ok("\\a.\\b.a (\\c.\\d.d)".to_owned())
+	);
+}
+
+#[wasm_bindgen_test]
+fn test_literals() {
+	let e = "{:: tuple[list[bool], church, bool] }
+		{:numerals}
+		<[not true, false], 2, not false>
+		where not = \\b.b false true";
+	assert_eq!(eval_lambda(e), Ok("([false, false], 2, true)".to_owned()));
+}
+
+#[wasm_bindgen_test]
+fn test_strings() {
+	let e = "{:: string }
+		append \"hello, \" \"w\\u{f6}rld\"
+		where rec append = \\a b.if (null a) b (\\p.p (head a) (append (tail a) b))
+		where   if = \\c t f.c t f
+		where null = \\l.l (\\h t.false)
+		where head = \\l.l (\\h t.h)
+		where tail = \\l.l (\\h t.t)";
+	assert_eq!(eval_lambda(e), Ok("\"hello, wörld\"".to_owned()));
+	assert_eq!(eval_lambda("{:: char } 'x'"), Ok("'x'".to_owned()));
+	assert_eq!(
+		eval_lambda("{:: string } [true]").map_err(|e| e.to_string()),
+		Err(
+			"Type error: could not interpret result as string (element 0 is not a Church numeral)"
+				.to_owned()
+		)
+	);
+}
+
+#[wasm_bindgen_test]
+fn test_unicode() {
+	let e = "(λα x₁.α) (λy.y)";
+	assert_eq!(eval_lambda(e), Ok("\\a.\\b.b".to_owned()));
+	let mut options = EvalOptions::new();
+	options.set_unicode_lambda(true);
+	assert_eq!(
+		eval_lambda_with_options(e, &options),
+		Ok("λa.λb.b".to_owned())
+	);
+	let steps = trace_lambda(e, &options).unwrap();
+	assert_eq!("(λa.λb.a) (λa.a)", steps[0].expr());
+	assert_eq!(Some(0), steps[0].redex_start());
+	assert_eq!(Some(16), steps[0].redex_end());
+}
+
+#[wasm_bindgen_test]
+fn test_syntax_error_span() {
+	let err = eval_lambda("\\x.x\nwhere f = )").unwrap_err();
+	let span = Span {
+		start: 15,
+		end: 16,
+		line: 2,
+		column: 11,
+	};
+	assert_eq!(Some(span), err.span());
+	let diagnostic = Diagnostic::from(err);
+	assert_eq!(Some(span), diagnostic.span());
+	assert_eq!(
+		"Syntax error at line 2, column 11: unexpected token \")\"\n  |\n2 | where f = )\n  |           ^",
+		diagnostic.message()
+	);
+
+	let err = check_assertions("x\n{:assert x == (y }", &EvalOptions::new()).unwrap_err();
+	assert_eq!(
+		Some(Span {
+			start: 16,
+			end: 17,
+			line: 2,
+			column: 15,
+		}),
+		err.span()
+	);
+}
+
+#[wasm_bindgen_test]
+fn test_collapse_lambdas() {
+	let e = "\\x y.(\\z w.z) x";
+	assert_eq!(eval_lambda(e), Ok("\\a.\\b.\\c.a".to_owned()));
+	let mut options = EvalOptions::new();
+	options.set_collapse_lambdas(true);
+	assert_eq!(
+		eval_lambda_with_options(e, &options),
+		Ok("\\a b c.a".to_owned())
+	);
+	let steps = trace_lambda(e, &options).unwrap();
+	assert_eq!("\\a b.(\\c d.c) a", steps[0].expr());
+	assert_eq!(Some(5), steps[0].redex_start());
+	assert_eq!("\\a b c.a", steps[1].expr());
+}
+
+#[wasm_bindgen_test]
+fn test_machine_engine() {
+	let defs = "
+		where    * = \\m.\\n.\\s.m (n s)
+		where    + = \\x.\\y.x succ y
+		where succ = \\n.\\s.\\z.s(n s z)"
+		.trim();
+	let mut options = EvalOptions::new();
+	options.set_engine(Engine::Machine);
+
+	for n in 0..10 {
+		for m in 0..10 {
+			let n_expr = make_church_num(n);
+			let m_expr = make_church_num(m);
+			assert_eq!(
+				eval_lambda_with_options(&format!("+ ({n_expr}) ({m_expr})\n{defs}"), &options),
+				Ok(make_church_num(n + m))
+			);
+			assert_eq!(
+				eval_lambda_with_options(
+					&format!("{{:: church }} * ({n_expr}) ({m_expr})\n{defs}"),
+					&options
+				),
+				Ok((n * m).to_string())
+			);
+		}
+	}
+
+	options.set_strategy(Strategy::ApplicativeOrder);
+	assert!(eval_lambda_with_options("\\x.x", &options)
+		.unwrap_err()
+		.to_string()
+		.starts_with("Unsupported strategy"));
+}
+
+#[wasm_bindgen_test]
+fn test_graph_engine() {
+	let defs = "
+		where    * = \\m.\\n.\\s.m (n s)
+		where   10 = \\s.\\z.s (s (s (s (s (s (s (s (s (s z)))))))))"
+		.trim();
+	let mut options = EvalOptions::new();
+	options.set_engine(Engine::Graph);
+	assert_eq!(
+		eval_lambda_with_options(&format!("{{:: church }} * 10 (* 10 10)\n{defs}"), &options),
+		Ok("1000".to_owned())
+	);
+}
+
+#[wasm_bindgen_test]
+fn test_nbe_engine() {
+	let defs = "
+		where    * = \\m.\\n.\\s.m (n s)
+		where   10 = \\s.\\z.s (s (s (s (s (s (s (s (s (s z)))))))))"
+		.trim();
+	let mut options = EvalOptions::new();
+	options.set_engine(Engine::Nbe);
+	assert_eq!(
+		eval_lambda_with_options(&format!("{{:: church }} * 10 (* 10 10)\n{defs}"), &options),
+		Ok("1000".to_owned())
+	);
+	options.set_strategy(Strategy::CallByName);
+	assert!(eval_lambda_with_options(&format!("* 10 10\n{defs}"), &options).is_err());
+}
+
+#[wasm_bindgen_test]
+fn test_optimal_engine() {
+	let defs = "
+		where    * = \\m.\\n.\\s.m (n s)
+		where   10 = \\s.\\z.s (s (s (s (s (s (s (s (s (s z)))))))))"
+		.trim();
+	let mut options = EvalOptions::new();
+	options.set_engine(Engine::Optimal);
+	assert_eq!(
+		eval_lambda_with_options(&format!("{{:: church }} * 10 (* 10 10)\n{defs}"), &options),
+		Ok("1000".to_owned())
+	);
+}
+
+#[wasm_bindgen_test]
+fn test_non_termination() {
+	let mut options = EvalOptions::new();
+	options.set_max_steps(Some(1000));
+	let omega = "(\\x.x x) (\\x.x x)";
+	assert_eq!(
+		eval_lambda_with_options(omega, &options).map_err(|e| e.to_string()),
+		Err("Evaluation does not terminate: term repeats after 1 step".to_owned())
+	);
+}
+
+#[wasm_bindgen_test]
+fn test_stats() {
+	let e = "{:: church }\nsucc (\\s.\\z.z)\nwhere succ = \\n.\\s.\\z.s(n s z)";
+	let result = eval_lambda_with_stats(e, &EvalOptions::new()).unwrap();
+	assert_eq!("1", result.output());
+	// succ 0 -> \s.\z.s ((\s.\z.z) s z) -> \s.\z.s ((\z.z) z) -> \s.\z.s z
+	let stats = result.stats();
+	assert_eq!(3, stats.beta_reductions);
+	assert_eq!(2, stats.substitutions);
+	assert_eq!(5, stats.final_size);
+}
+
+#[wasm_bindgen_test]
+fn test_stats_optimal() {
+	let e = "{:: church }\nsucc (\\s.\\z.z)\nwhere succ = \\n.\\s.\\z.s(n s z)";
+	let mut options = EvalOptions::new();
+	options.set_engine(Engine::Optimal);
+	let result = eval_lambda_with_stats(e, &options).unwrap();
+	assert_eq!("1", result.output());
+	let stats = result.stats();
+	assert_eq!(3, stats.beta_reductions);
+	assert_eq!(0, stats.substitutions);
+	assert_eq!(5, stats.final_size);
+	assert!(stats.interactions > stats.beta_reductions);
+	// The substitution engine does not count interactions
+	let stats = eval_lambda_with_stats(e, &EvalOptions::new())
+		.unwrap()
+		.stats();
+	assert_eq!(0, stats.interactions);
+}
+
+#[wasm_bindgen_test]
+fn test_evaluation() {
+	let e = "{:: church }\nsucc (\\s.\\z.z)\nwhere succ = \\n.\\s.\\z.s(n s z)";
+	let mut evaluation = Evaluation::new(e, &EvalOptions::new()).unwrap();
+	assert_eq!(Ok(false), evaluation.step(2));
+	assert!(!evaluation.is_done());
+	assert_eq!(2, evaluation.steps());
+	assert_eq!("\\a.\\b.a ((\\c.c) b)", evaluation.current());
+	assert_eq!(Ok(true), evaluation.step(10));
+	assert!(evaluation.is_done());
+	assert_eq!(3, evaluation.steps());
+	assert_eq!("\\a.\\b.a b", evaluation.current());
+	assert_eq!(Ok("1".to_owned()), evaluation.output());
+	assert_eq!(
+		eval_lambda_with_stats(e, &EvalOptions::new())
+			.unwrap()
+			.stats()
+			.beta_reductions,
+		evaluation.stats().beta_reductions
+	);
+}
+
+#[wasm_bindgen_test]
+fn test_evaluation_cancel() {
+	let omega = "(\\x.x x x) (\\x.x x x)";
+	let mut evaluation = Evaluation::new(omega, &EvalOptions::new()).unwrap();
+	assert_eq!(Ok(false), evaluation.step(3));
+	evaluation.cancel();
+	assert!(evaluation.is_stopped());
+	assert_eq!(Ok(false), evaluation.step(3));
+	assert_eq!(3, evaluation.steps());
+	assert!(matches!(evaluation.output(), Err(Error::Cancelled(3, _))));
+
+	let mut options = EvalOptions::new();
+	options.set_max_steps(Some(5));
+	let mut evaluation = Evaluation::new(omega, &options).unwrap();
+	assert!(evaluation.step(10).is_err());
+	assert!(evaluation.is_stopped());
+	assert_eq!(5, evaluation.steps());
+	assert!(matches!(
+		evaluation.output(),
+		Err(Error::StepLimitExceeded(5, _))
+	));
+}
+
+#[wasm_bindgen_test]
+fn test_evaluation_error() {
+	let omega = "(\\x.x x) (\\x.x x)";
+	let mut evaluation = Evaluation::new(omega, &EvalOptions::new()).unwrap();
+	let error = evaluation.step(10).unwrap_err();
+	assert!(matches!(error, Error::NonTermination(_)));
+	assert!(evaluation.is_stopped());
+	assert_eq!(Err(error), evaluation.output());
+	// Cancelling afterwards keeps the error
+	evaluation.cancel();
+	assert!(matches!(evaluation.output(), Err(Error::NonTermination(_))));
+}
+
+#[wasm_bindgen_test]
+fn test_alpha_eq() {
+	assert_eq!(Ok(true), alpha_eq_lambda("\\x.\\y.x", "{ K } \\a.\\b.a"));
+	assert_eq!(Ok(false), alpha_eq_lambda("\\x.\\y.x", "\\a.\\b.b"));
+	assert_eq!(Ok(true), alpha_eq_lambda("\\x.f x", "\\y.f y"));
+	assert_eq!(Ok(false), alpha_eq_lambda("(\\x.x) y", "y"));
+	assert!(alpha_eq_lambda("\\x.", "x").is_err());
+}
+
+#[wasm_bindgen_test]
+fn test_beta_eq() {
+	let answer = "
+		\\s.s (&& F F) (&& F T) (&& T F) (&& T T)
+		where && = \\a.\\b.a b F
+		where  F = \\t.\\f.f
+		where  T = \\t.\\f.t";
+	let attempt = "
+		\\s.s (&& F F) (&& F T) (&& T F) (&& T T)
+		where && = \\a.\\b.b a b
+		where  F = \\x.\\y.y
+		where  T = \\x.\\y.x";
+	let wrong = "
+		\\s.s (&& F F) (&& F T) (&& T F) (&& T T)
+		where && = \\a.\\b.a
+		where  F = \\t.\\f.f
+		where  T = \\t.\\f.t";
+	assert_eq!(
+		Ok(Equivalence::Equal),
+		beta_eq_lambda(answer, attempt, 1000)
+	);
+	assert_eq!(
+		Ok(Equivalence::NotEqual),
+		beta_eq_lambda(answer, wrong, 1000)
+	);
+	assert_eq!(Ok(Equivalence::Unknown), beta_eq_lambda(answer, attempt, 1));
+	assert_eq!(Ok(Equivalence::Equal), beta_eq_lambda("(\\x.x) f", "f", 10));
+}
+
+#[wasm_bindgen_test]
+fn test_assertions() {
+	let e = "
+		{:assert + 2 2 == 4 }
+		{:assert + 2 2 == 2 }
+		{:assert-type church }
+		{:assert-type bool }
+		+ 2 2
+		where + = \\m.\\n.\\s.\\z.m s (n s z)
+		where 4 = \\f.\\x.f (f (f (f x)))
+		where 2 = \\s.\\z.s (s z)";
+	let results = check_assertions(e, &EvalOptions::new()).unwrap();
+	let summary: Vec<(String, bool, Option<String>)> = results
+		.iter()
+		.map(|r| (r.directive(), r.passed(), r.actual()))
+		.collect();
+	let four = "\\a.\\b.a (a (a (a b)))".to_owned();
+	assert_eq!(
+		vec![
+			(":assert + 2 2 == 4".to_owned(), true, None),
+			(":assert + 2 2 == 2".to_owned(), false, Some(four.clone())),
+			(":assert-type church".to_owned(), true, None),
+			(":assert-type bool".to_owned(), false, Some(four)),
+		],
+		summary
+	);
+}
+
+#[wasm_bindgen_test]
+fn test_assertions_errors() {
+	let mut options = EvalOptions::new();
+	options.set_max_steps(Some(100));
+	let e = "{:assert W == W } \\x.x where W = (\\x.x x) (\\x.x x)";
+	let results = check_assertions(e, &options).unwrap();
+	assert!(!results[0].passed());
+	assert_eq!(
+		Some("Evaluation does not terminate: term repeats after 1 step".to_owned()),
+		results[0].actual()
+	);
+	assert!(check_assertions("{:assert x } x", &options).is_err());
+}
+
+#[wasm_bindgen_test]
+fn test_assertions_malformed_type() {
+	// The other assertions are still checked
+	let e = "{:assert-type nat } {:assert I == I } {:assert-type expr } I where I = \\x.x";
+	let results = check_assertions(e, &EvalOptions::new()).unwrap();
+	assert_eq!(3, results.len());
+	assert!(!results[0].passed());
+	assert_eq!(
+		Some("Malformed type: invalid type identifier: \"nat\"".to_owned()),
+		results[0].actual()
+	);
+	assert!(results[1].passed());
+	assert!(results[2].passed());
+}
+
+#[wasm_bindgen_test]
+fn test_assertions_free_variable() {
+	let e = "{:assert x == x } {:assert \\y.y == \\z.z } \\y.y";
+	let results = check_assertions(e, &EvalOptions::new()).unwrap();
+	assert_eq!(2, results.len());
+	assert!(!results[0].passed());
+	// The error points at the first use of the variable
+	assert_eq!(
+		Some(format!(
+			"Free variable: `x` is not bound\n  |\n1 | {e}\n  |          ^"
+		)),
+		results[0].actual()
+	);
+	assert!(results[1].passed());
+	assert_eq!(
+		eval_lambda("\\y.x").map_err(|e| e.to_string()),
+		Err("Free variable: `x` is not bound\n  |\n1 | \\y.x\n  |    ^".to_owned())
+	);
+}
+
+#[wasm_bindgen_test]
+fn test_deep_numeral() {
+	// 2^18, computed by doubling 1 eighteen times
+	let src = |annotation| {
+		format!(
+			"{annotation} {}1{}\nwhere D = \\n.\\s.\\z.n s (n s z)\nwhere 1 = \\s.\\z.s z",
+			"D (".repeat(18),
+			")".repeat(18)
+		)
+	};
+	let mut options = EvalOptions::new();
+	options.set_strategy(Strategy::ApplicativeOrder);
+	assert_eq!(
+		eval_lambda_with_options(&src("{:: church }"), &options),
+		Ok("262144".to_owned())
+	);
+	let expected = format!(
+		"\\a.\\b.{}a b{}",
+		"a (".repeat(262_143),
+		")".repeat(262_143)
+	);
+	assert_eq!(eval_lambda_with_options(&src(""), &options), Ok(expected));
+}
+
+#[wasm_bindgen_test]
+fn test_large_numeral_literal() {
+	// Parsing, inlining and printing a numeral this deep must not recurse
+	assert_eq!(
+		eval_lambda("{:numerals} {:: church } 100000"),
+		Ok("100000".to_owned())
+	);
+	let expected = format!("\\a.\\b.{}a b{}", "a (".repeat(99_999), ")".repeat(99_999));
+	assert_eq!(eval_lambda("{:numerals} 100000"), Ok(expected));
+
+	assert_eq!(
+		eval_lambda("{:numerals} 1000000000").map_err(|e| e.to_string()),
+		Err("Numeral too large: 1000000000 is more than 1000000\n  |\n1 | {:numerals} 1000000000\n  |             ^^^^^^^^^^".to_owned())
+	);
+}
@@ -6,9 +6,32 @@ const BASE_URL = getBaseUrl();
 const DESCRIPTION_ELEM = document.getElementById("exercise-description");
 const INPUT_TEXTAREA = document.getElementById("input-block");
 const OUTPUT_TEXTAREA = document.getElementById("output-block");
+const TRACE_CONTAINER = document.getElementById("trace-container");
+const TRACE_LABEL = document.getElementById("trace-label");
+const TRACE_BLOCK = document.getElementById("trace-block");
+const COMPARE_COLUMN = document.getElementById("compare-column");
+const COMPARE_BLOCK = document.getElementById("compare-block");
+const STATS_BLOCK = document.getElementById("stats-block");
 const EVAL_BTN = document.getElementById("eval-btn");
+const STOP_BTN = document.getElementById("stop-btn");
+const TRACE_BTN = document.getElementById("trace-btn");
+const ASSERT_BTN = document.getElementById("assert-btn");
 const CLEAR_BTN = document.getElementById("clear-btn");
+const CHECK_ANSWER_BTN = document.getElementById("check-answer-btn");
 const SHOW_ANSWER_BTN = document.getElementById("show-answer-btn");
+const STRATEGY_SELECT = document.getElementById("strategy-select");
+const ENGINE_SELECT = document.getElementById("engine-select");
+const NUMERALS_SELECT = document.getElementById("numerals-select");
+const ETA_CHECKBOX = document.getElementById("eta-checkbox");
+const COLLAPSE_CHECKBOX = document.getElementById("collapse-checkbox");
+const LAMBDA_CHECKBOX = document.getElementById("lambda-checkbox");
+const COMPARE_CHECKBOX = document.getElementById("compare-checkbox");
+const MAX_STEPS = 100000;
+const MAX_TRACE_STEPS = 1000;
+const STEPS_PER_CHUNK = 100;
+const CHUNK_TIME_MS = 50;
+
+let runningEvaluation = null;
 
 const url = new URL(window.location.href);
 
@@ -16,11 +39,16 @@ const exercise = await getExercise(url.searchParams.get("ex"));
 if (exercise) {
 	DESCRIPTION_ELEM.innerHTML = exercise.description;
 	if (exercise.answer) {
+		CHECK_ANSWER_BTN.style.visibility = "visible";
+		CHECK_ANSWER_BTN.addEventListener("click", () => {
+			checkAnswer(exercise.answer);
+		});
 		SHOW_ANSWER_BTN.style.visibility = "visible";
 		SHOW_ANSWER_BTN.addEventListener("click", () => {
 			INPUT_TEXTAREA.value = exercise.answer;
 		});
 	} else {
+		CHECK_ANSWER_BTN.style.visibility = "hidden";
 		SHOW_ANSWER_BTN.style.visibility = "hidden";
 	}
 } else {
@@ -42,16 +70,225 @@ EVAL_BTN.addEventListener("click", () => {
 	evaluateExpression();
 });
 
+STOP_BTN.addEventListener("click", () => {
+	stopEvaluation();
+});
+
+TRACE_BTN.addEventListener("click", () => {
+	traceExpression();
+});
+
+ASSERT_BTN.addEventListener("click", () => {
+	runAssertions();
+});
+
 CLEAR_BTN.addEventListener("click", () => {
 	INPUT_TEXTAREA.value = "";
 });
 
+function getEvalOptions(maxSteps) {
+	const options = new lambda.EvalOptions();
+	options.max_steps = maxSteps;
+	options.strategy = lambda.Strategy[STRATEGY_SELECT.value];
+	options.eta = ETA_CHECKBOX.checked;
+	options.engine = lambda.Engine[ENGINE_SELECT.value];
+	options.collapse_lambdas = COLLAPSE_CHECKBOX.checked;
+	options.unicode_lambda = LAMBDA_CHECKBOX.checked;
+	options.numerals = NUMERALS_SELECT.value ? lambda.NumeralEncoding[NUMERALS_SELECT.value] : undefined;
+	return options;
+}
+
 function evaluateExpression() {
+	stopEvaluation();
+	OUTPUT_TEXTAREA.value = "...";
+	TRACE_CONTAINER.hidden = true;
+	STATS_BLOCK.hidden = true;
+	const e = INPUT_TEXTAREA.value;
+	const options = getEvalOptions(MAX_STEPS);
+	try {
+		OUTPUT_TEXTAREA.classList.remove("error");
+		// Only the substitution engine can be run a few steps at a time, and
+		// not with call-by-need, which needs sharing
+		if (options.engine === lambda.Engine.Substitution && options.strategy !== lambda.Strategy.CallByNeed) {
+			runningEvaluation = new lambda.Evaluation(e, options);
+			STOP_BTN.disabled = false;
+			runEvaluation(runningEvaluation);
+		} else if (options.engine === lambda.Engine.Optimal) {
+			const result = lambda.eval_lambda_with_stats(e, options);
+			OUTPUT_TEXTAREA.value = result.output;
+			showStats(result.stats);
+		} else {
+			OUTPUT_TEXTAREA.value = lambda.eval_lambda_with_options(e, options);
+		}
+	} catch (e) {
+		OUTPUT_TEXTAREA.classList.add("error");
+		OUTPUT_TEXTAREA.value = e;
+		selectErrorSpan(e);
+	}
+}
+
+// Selects the part of the program that an error is about, if any. Spans are
+// offsets in the program's UTF-8 encoding, whereas the textarea counts UTF-16
+// code units.
+function selectErrorSpan(error) {
+	const span = error?.span;
+	if (!span) {
+		return;
+	}
+	const bytes = new TextEncoder().encode(INPUT_TEXTAREA.value);
+	const decoder = new TextDecoder();
+	const toIndex = (offset) => decoder.decode(bytes.slice(0, offset)).length;
+	INPUT_TEXTAREA.focus();
+	INPUT_TEXTAREA.setSelectionRange(toIndex(span.start), toIndex(span.end));
+}
+
+function runEvaluation(evaluation) {
+	if (evaluation !== runningEvaluation) {
+		return;
+	}
+	try {
+		const start = performance.now();
+		while (!evaluation.step(STEPS_PER_CHUNK)) {
+			if (performance.now() - start > CHUNK_TIME_MS) {
+				OUTPUT_TEXTAREA.value = `Evaluating... ${evaluation.steps} step(s)`;
+				// Yield so that the page can update and the Stop button works
+				setTimeout(() => runEvaluation(evaluation));
+				return;
+			}
+		}
+		OUTPUT_TEXTAREA.value = evaluation.output();
+		showStats(evaluation.stats);
+	} catch (e) {
+		OUTPUT_TEXTAREA.classList.add("error");
+		OUTPUT_TEXTAREA.value = e;
+	}
+	finishEvaluation();
+}
+
+function stopEvaluation() {
+	if (runningEvaluation === null) {
+		return;
+	}
+	runningEvaluation.cancel();
+	OUTPUT_TEXTAREA.value =
+		`Stopped after ${runningEvaluation.steps} step(s). Partial result:\n${runningEvaluation.current}`;
+	showStats(runningEvaluation.stats);
+	finishEvaluation();
+}
+
+function finishEvaluation() {
+	runningEvaluation.free();
+	runningEvaluation = null;
+	STOP_BTN.disabled = true;
+}
+
+function showStats(stats) {
+	STATS_BLOCK.textContent = [
+		`${stats.beta_reductions} beta reduction(s)`,
+		`${stats.substitutions} substitution(s)`,
+		`peak size ${stats.peak_size}`,
+		`final size ${stats.final_size}`,
+		`max depth ${stats.max_depth}`,
+		...(stats.interactions > 0 ? [`${stats.interactions} interaction(s)`] : []),
+		`${stats.time_ms.toFixed(1)} ms`,
+	].join(" \u00b7 ");
+	STATS_BLOCK.hidden = false;
+}
+
+function traceExpression() {
+	stopEvaluation();
 	OUTPUT_TEXTAREA.value = "...";
+	STATS_BLOCK.hidden = true;
 	const e = INPUT_TEXTAREA.value;
 	try {
 		OUTPUT_TEXTAREA.classList.remove("error");
-		OUTPUT_TEXTAREA.value = lambda.eval_lambda(e);
+		const options = getEvalOptions(MAX_TRACE_STEPS);
+		const steps = lambda.trace_lambda(e, options);
+		showTrace(TRACE_BLOCK, steps);
+		TRACE_LABEL.textContent = STRATEGY_SELECT.selectedOptions[0].text;
+		let summary = `${steps.length - 1} step(s)`;
+		COMPARE_COLUMN.hidden = !COMPARE_CHECKBOX.checked;
+		if (COMPARE_CHECKBOX.checked) {
+			options.strategy = lambda.Strategy.NormalOrder;
+			const normalSteps = lambda.trace_lambda(e, options);
+			showTrace(COMPARE_BLOCK, normalSteps);
+			summary += `, ${normalSteps.length - 1} step(s) in normal order`;
+		}
+		OUTPUT_TEXTAREA.value = summary;
+		TRACE_CONTAINER.hidden = false;
+	} catch (e) {
+		OUTPUT_TEXTAREA.classList.add("error");
+		OUTPUT_TEXTAREA.value = e;
+		TRACE_CONTAINER.hidden = true;
+		selectErrorSpan(e);
+	}
+}
+
+function showTrace(block, steps) {
+	block.replaceChildren();
+	for (const step of steps) {
+		const li = document.createElement("li");
+		const expr = step.expr;
+		// Complete development contracts several redexes at once
+		const starts = step.redex_starts;
+		const ends = step.redex_ends;
+		let end = 0;
+		for (let i = 0; i < starts.length; i++) {
+			const redex = document.createElement("mark");
+			redex.textContent = expr.slice(starts[i], ends[i]);
+			li.append(expr.slice(end, starts[i]), redex);
+			end = ends[i];
+		}
+		li.append(expr.slice(end));
+		block.append(li);
+	}
+}
+
+function runAssertions() {
+	stopEvaluation();
+	TRACE_CONTAINER.hidden = true;
+	STATS_BLOCK.hidden = true;
+	try {
+		OUTPUT_TEXTAREA.classList.remove("error");
+		const results = lambda.check_assertions(INPUT_TEXTAREA.value, getEvalOptions(MAX_STEPS));
+		if (results.length === 0) {
+			OUTPUT_TEXTAREA.value = "No {:assert ...} or {:assert-type ...} comments found.";
+			return;
+		}
+		const failed = results.filter((r) => !r.passed).length;
+		const lines = results.map((r) =>
+			r.passed ? `PASS {${r.directive}}` : `FAIL {${r.directive}}\n     got ${r.actual}`
+		);
+		lines.push(`${results.length - failed} passed, ${failed} failed`);
+		if (failed > 0) {
+			OUTPUT_TEXTAREA.classList.add("error");
+		}
+		OUTPUT_TEXTAREA.value = lines.join("\n");
+	} catch (e) {
+		OUTPUT_TEXTAREA.classList.add("error");
+		OUTPUT_TEXTAREA.value = e;
+		selectErrorSpan(e);
+	}
+}
+
+function checkAnswer(answer) {
+	stopEvaluation();
+	TRACE_CONTAINER.hidden = true;
+	STATS_BLOCK.hidden = true;
+	try {
+		OUTPUT_TEXTAREA.classList.remove("error");
+		switch (lambda.beta_eq_lambda(INPUT_TEXTAREA.value, answer, MAX_STEPS)) {
+			case lambda.Equivalence.Equal:
+				OUTPUT_TEXTAREA.value = "Correct! Your program is equivalent to the answer.";
+				break;
+			case lambda.Equivalence.NotEqual:
+				OUTPUT_TEXTAREA.classList.add("error");
+				OUTPUT_TEXTAREA.value = "Incorrect: your program is not equivalent to the answer.";
+				break;
+			default:
+				OUTPUT_TEXTAREA.value =
+					`Could not check your program: no normal form found after ${MAX_STEPS} steps.`;
+		}
 	} catch (e) {
 		OUTPUT_TEXTAREA.classList.add("error");
 		OUTPUT_TEXTAREA.value = e;